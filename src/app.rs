@@ -0,0 +1,970 @@
+use std::error::Error;
+use std::io;
+use std::io::Write;
+
+use clap::{App, Arg, ArgGroup, Shell};
+
+use crate::types::TypeRegistry;
+
+// The command line of lps, parsed by `Config::new`
+pub fn app() -> App<'static, 'static> {
+    definition(None)
+}
+
+// Completion scripts list the file types in `types`
+fn definition<'b>(types: Option<&'b [&'b str]>) -> App<'b, 'b> {
+    App::new("lps")
+        .version("1.0.1")
+        .author("Loris Leitner (Loris156)")
+        .about("High-speed parallelized searching")
+        .arg(
+            Arg::with_name("filename")
+                .short("n")
+                .long("name")
+                .value_name("FILENAME")
+                .help("Filename pattern")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ignore-filename-case")
+                .short("b")
+                .long("ignore-filename-case")
+                .requires("filename")
+                .help("Ignores casing of filename"),
+        )
+        .arg(
+            Arg::with_name("smart-case")
+                .short("S")
+                .long("smart-case")
+                .help("Ignores casing of filename and content unless the pattern contains uppercase letters"),
+        )
+        .arg(
+            Arg::with_name("glob")
+                .short("g")
+                .long("glob")
+                .requires("filename")
+                .conflicts_with("name-regex")
+                .help("Interprets filename pattern as glob (e.g. *.rs or src/**/test_*.txt)"),
+        )
+        .arg(
+            Arg::with_name("name-regex")
+                .long("name-regex")
+                .requires("filename")
+                .help("Interprets filename pattern as regular expression"),
+        )
+        .arg(
+            Arg::with_name("no-normalize")
+                .long("no-normalize")
+                .help("Matches file names and excludes in the Unicode form they are stored in, not NFC"),
+        )
+        .arg(
+            Arg::with_name("content")
+                .short("c")
+                .long("content")
+                .value_name("TEXT")
+                .help("File content (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("pattern-file")
+                .short("f")
+                .long("pattern-file")
+                .value_name("FILE")
+                .help("Searches for any pattern in FILE, one per line (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .group(
+            ArgGroup::with_name("patterns")
+                .args(&["content", "pattern-file"])
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("name-or-content")
+                .long("name-or-content")
+                .requires_all(&["filename", "patterns"])
+                .conflicts_with("files-without-match")
+                .help("Reports files matching the name or the content, labeled separately"),
+        )
+        .arg(
+            Arg::with_name("any")
+                .long("any")
+                .requires("patterns")
+                .conflicts_with("all")
+                .help("Files have to contain any of the contents (default)"),
+        )
+        .arg(
+            Arg::with_name("all")
+                .long("all")
+                .requires("patterns")
+                .help("Files have to contain all of the contents"),
+        )
+        .arg(
+            Arg::with_name("regex")
+                .short("r")
+                .long("regex")
+                .requires("patterns")
+                .help("Interprets content as regular expression"),
+        )
+        .arg(
+            Arg::with_name("fixed-strings")
+                .short("F")
+                .long("fixed-strings")
+                .requires("patterns")
+                .conflicts_with("regex")
+                .help("Interprets content literally, even if regex is enabled in the config file"),
+        )
+        .arg(
+            Arg::with_name("ignore-content-case")
+                .short("x")
+                .long("ignore-content-case")
+                .requires("patterns")
+                .help("Ignores casing of content"),
+        )
+        .arg(
+            Arg::with_name("word-regexp")
+                .short("w")
+                .long("word-regexp")
+                .requires("patterns")
+                .help("Only matches whole words"),
+        )
+        .arg(
+            Arg::with_name("line-regexp")
+                .long("line-regexp")
+                .requires("patterns")
+                .help("Only matches whole lines"),
+        )
+        .arg(
+            Arg::with_name("multiline")
+                .long("multiline")
+                .requires("patterns")
+                .conflicts_with("invert-match")
+                .help("Allows matches to span multiple lines"),
+        )
+        .arg(
+            Arg::with_name("invert-match")
+                .long("invert-match")
+                .requires("patterns")
+                .help("Reports lines not containing the content"),
+        )
+        .arg(
+            Arg::with_name("files-with-matches")
+                .short("l")
+                .long("files-with-matches")
+                .requires("patterns")
+                .conflicts_with_all(&["count", "count-matches", "files-without-match"])
+                .help("Only lists files containing the content"),
+        )
+        .arg(
+            Arg::with_name("files-without-match")
+                .long("files-without-match")
+                .requires("patterns")
+                .conflicts_with_all(&["count", "count-matches"])
+                .help("Only lists files not containing the content"),
+        )
+        .arg(
+            Arg::with_name("replace")
+                .long("replace")
+                .value_name("TEXT")
+                .requires("patterns")
+                .conflicts_with_all(&[
+                    "invert-match",
+                    "multiline",
+                    "files-with-matches",
+                    "files-without-match",
+                    "count",
+                    "count-matches",
+                    "archives",
+                    "search-zip",
+                ])
+                .help("Replaces every match with TEXT in place, $1 refers to regex groups")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .requires("replace")
+                .help("Only prints the changes of --replace"),
+        )
+        .arg(
+            Arg::with_name("backup")
+                .long("backup")
+                .requires("replace")
+                .help("Keeps the original of replaced files as <file>.bak"),
+        )
+        .arg(
+            Arg::with_name("file-timeout")
+                .long("file-timeout")
+                .value_name("SECS")
+                .requires("patterns")
+                .help("Gives up on files taking longer than SECS to search and reports them")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-count")
+                .short("m")
+                .long("max-count")
+                .value_name("NUM")
+                .requires("patterns")
+                .help("Stops searching a file after NUM matching lines")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-total")
+                .long("max-total")
+                .value_name("NUM")
+                .requires("patterns")
+                .help("Stops the search after NUM matches in total")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-line-length")
+                .long("max-line-length")
+                .value_name("BYTES")
+                .requires("patterns")
+                .conflicts_with("multiline")
+                .help("Truncates or skips lines longer than BYTES (e.g. 500 or 1K)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("memory-limit")
+                .long("memory-limit")
+                .value_name("SIZE")
+                .conflicts_with_all(&["sort", "group-by-dir"])
+                .help("Bounds found paths, read files and pending results held at once (e.g. 64M)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("long-lines")
+                .long("long-lines")
+                .value_name("ACTION")
+                .requires("max-line-length")
+                .possible_values(&["truncate", "skip"])
+                .help("What to do with lines over --max-line-length (defaults to truncate)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("archives")
+                .long("archives")
+                .requires("patterns")
+                .help("Searches files inside .zip, .tar and .tar.gz archives"),
+        )
+        .arg(
+            Arg::with_name("search-zip")
+                .short("z")
+                .long("search-zip")
+                .requires("patterns")
+                .help("Searches inside .gz, .bz2, .xz and .zst compressed files"),
+        )
+        .arg(
+            Arg::with_name("encoding")
+                .short("E")
+                .long("encoding")
+                .value_name("ENCODING")
+                .requires("patterns")
+                .possible_values(&["auto", "utf-8", "utf-16le", "utf-16be", "latin1"])
+                .help("Encoding of searched files (defaults to auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("binary")
+                .long("binary")
+                .requires("patterns")
+                .conflicts_with("binary-files")
+                .help("Searches binary files like text files"),
+        )
+        .arg(
+            Arg::with_name("binary-files")
+                .long("binary-files")
+                .value_name("TYPE")
+                .requires("patterns")
+                .possible_values(&["text", "skip", "list"])
+                .help("How to handle binary files (defaults to skip)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("count")
+                .long("count")
+                .requires("patterns")
+                .help("Only prints the number of matching lines per file"),
+        )
+        .arg(
+            Arg::with_name("count-matches")
+                .long("count-matches")
+                .requires("patterns")
+                .conflicts_with("count")
+                .help("Only prints the number of matches per file"),
+        )
+        .arg(
+            Arg::with_name("dop")
+                .short("d")
+                .long("dop")
+                .value_name("THREAD COUNT")
+                .help("Degree of parallelism (defaults to logical core count)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("walk-threads")
+                .long("walk-threads")
+                .value_name("THREAD COUNT")
+                .help("Threads reading directories (defaults to --dop)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("search-threads")
+                .long("search-threads")
+                .value_name("THREAD COUNT")
+                .requires("patterns")
+                .help("Threads searching file contents (defaults to --dop)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ignore-file")
+                .long("ignore-file")
+                .value_name("PATH")
+                .help("Loads extra ignore rules from a gitignore formatted file (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("no-ignore")
+                .long("no-ignore")
+                .help("Don't respect .gitignore, .ignore, .lpsignore and global git excludes"),
+        )
+        .arg(type_arg(types))
+        .arg(
+            Arg::with_name("type-add")
+                .long("type-add")
+                .value_name("NAME:GLOBS")
+                .help("Defines a file type, e.g. web:*.html,*.css (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("hidden")
+                .long("hidden")
+                .help("Includes hidden files and directories"),
+        )
+        .arg(
+            Arg::with_name("attrib")
+                .long("attrib")
+                .value_name("ATTRIBUTES")
+                .help("Only includes files with these attributes: hidden, system, readonly or archive, -NAME excludes them")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .multiple(true)
+                .require_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("allow-special")
+                .long("allow-special")
+                .help("Includes sockets, pipes and devices and descends into /proc, /sys and /dev"),
+        )
+        .arg(
+            Arg::with_name("one-file-system")
+                .long("one-file-system")
+                .help("Doesn't descend into directories on other filesystems"),
+        )
+        .arg(
+            Arg::with_name("strategy")
+                .long("strategy")
+                .value_name("ORDER")
+                .possible_values(&["dfs", "bfs"])
+                .help("Walks the tree depth-first or breadth-first (defaults to dfs)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("kind")
+                .long("kind")
+                .value_name("KIND")
+                .possible_values(&["f", "d", "l", "s", "p", "b", "c"])
+                .help("Only includes entries of these kinds, comma separated (defaults to f and l)")
+                .takes_value(true)
+                .multiple(true)
+                .require_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("empty")
+                .long("empty")
+                .conflicts_with_all(&["content", "pattern-file"])
+                .help("Only includes empty files and directories"),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .help("Skips files matching the glob (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("exclude-dir")
+                .long("exclude-dir")
+                .value_name("GLOB")
+                .help("Skips directories matching the glob without descending (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("min-size")
+                .long("min-size")
+                .value_name("SIZE")
+                .help("Only includes files of at least this size (e.g. 10K, 5M, 1G)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-size")
+                .long("max-size")
+                .value_name("SIZE")
+                .help("Only includes files of at most this size (e.g. 10K, 5M, 1G)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("perm")
+                .long("perm")
+                .value_name("MODE")
+                .allow_hyphen_values(true)
+                .help("Only includes files with these permissions (e.g. 0644, -0600, /0022, +x, o+w)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("owner")
+                .long("owner")
+                .value_name("USER")
+                .help("Only includes files owned by USER (name or id)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("group")
+                .long("group")
+                .value_name("GROUP")
+                .help("Only includes files belonging to GROUP (name or id)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("newer-than")
+                .long("newer-than")
+                .value_name("TIME")
+                .help("Only includes files modified after TIME (e.g. 2d, 3h or 2019-05-20)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("older-than")
+                .long("older-than")
+                .value_name("TIME")
+                .help("Only includes files modified before TIME (e.g. 2d, 3h or 2019-05-20)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-depth")
+                .long("max-depth")
+                .value_name("DEPTH")
+                .help("Maximum directory depth below the root (1 only searches the root itself)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("follow")
+                .short("L")
+                .long("follow")
+                .help("Follows symlinked directories"),
+        )
+        .arg(
+            Arg::with_name("files-from")
+                .long("files-from")
+                .value_name("FILE")
+                .conflicts_with_all(&["root", "roots"])
+                .help("Searches the files listed in FILE instead of walking a directory, - reads stdin")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sort")
+                .long("sort")
+                .value_name("ORDER")
+                .possible_values(&["path", "mtime", "size", "none"])
+                .conflicts_with("interactive")
+                .help("Prints results sorted once the search has finished (defaults to none)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("group-by-dir")
+                .long("group-by-dir")
+                .conflicts_with_all(&["json", "null", "interactive", "watch"])
+                .help("Groups results below a header per directory"),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .conflicts_with_all(&["null", "interactive"])
+                .help("Prints files searched, matches, bytes and timings after the results"),
+        )
+        .arg(
+            Arg::with_name("cache")
+                .long("cache")
+                .help("Reuses directory listings and results of unchanged files from the last run"),
+        )
+        .arg(
+            Arg::with_name("serve")
+                .long("serve")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .conflicts_with_all(&["content", "pattern-file", "interactive", "watch", "exec", "exec-batch"])
+                .help("Answers searches on a Unix socket, host:port or stdin (-), keeping directory listings in memory"),
+        )
+        .arg(
+            Arg::with_name("index")
+                .long("index")
+                .requires("patterns")
+                .help("Skips files ruled out by the trigram index, see lps index build"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .conflicts_with("interactive")
+                .help("Prints the time spent per phase and per thread to stderr after the results"),
+        )
+        .arg(
+            Arg::with_name("pattern-stats")
+                .long("pattern-stats")
+                .requires("patterns")
+                .conflicts_with_all(&["interactive", "invert-match"])
+                .help("Prints the files and lines every content pattern matched to stderr after the results"),
+        )
+        .arg(
+            Arg::with_name("explain")
+                .long("explain")
+                .conflicts_with_all(&["serve", "interactive", "watch"])
+                .help("Prints the roots, matchers, ignore files and threads instead of searching"),
+        )
+        .arg(
+            Arg::with_name("why")
+                .long("why")
+                .value_name("PATH")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["serve", "interactive", "watch", "explain"])
+                .help("Prints whether PATH would be searched or the rule skipping it, no search"),
+        )
+        .arg(
+            Arg::with_name("duplicates")
+                .long("duplicates")
+                .conflicts_with_all(&["content", "pattern-file", "watch", "interactive", "sort", "group-by-dir"])
+                .help("Prints groups of files with identical content"),
+        )
+        .arg(
+            Arg::with_name("exec")
+                .long("exec")
+                .value_name("COMMAND")
+                .conflicts_with_all(&[
+                    "exec-batch",
+                    "replace",
+                    "count",
+                    "count-matches",
+                    "duplicates",
+                    "hash",
+                    "interactive",
+                    "watch",
+                    "quiet",
+                    "sort",
+                    "group-by-dir",
+                ])
+                .help("Runs COMMAND for every matched file in parallel, {} is the path")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("exec-batch")
+                .long("exec-batch")
+                .value_name("COMMAND")
+                .conflicts_with_all(&[
+                    "replace",
+                    "count",
+                    "count-matches",
+                    "duplicates",
+                    "hash",
+                    "interactive",
+                    "watch",
+                    "quiet",
+                    "sort",
+                    "group-by-dir",
+                ])
+                .help("Runs COMMAND once with all matched files after the search")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cat")
+                .long("cat")
+                .conflicts_with_all(&[
+                    "exec",
+                    "exec-batch",
+                    "replace",
+                    "count",
+                    "count-matches",
+                    "duplicates",
+                    "hash",
+                    "interactive",
+                    "group-by-dir",
+                    "json",
+                    "format",
+                    "format-template",
+                    "null",
+                ])
+                .help("Prints the content of every matched file instead of its name"),
+        )
+        .arg(
+            Arg::with_name("header")
+                .long("header")
+                .requires("cat")
+                .help("Prints ==> path <== before the content of every file with --cat"),
+        )
+        .arg(
+            Arg::with_name("hash")
+                .long("hash")
+                .value_name("ALGORITHM")
+                .possible_values(&["sha256", "md5", "blake3"])
+                .conflicts_with_all(&["content", "pattern-file", "duplicates", "interactive", "format-template"])
+                .help("Prints a digest of every found file like sha256sum")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("watch")
+                .long("watch")
+                .conflicts_with_all(&["files-from", "interactive", "replace", "max-total"])
+                .help("Keeps running and searches files again when they change"),
+        )
+        .arg(
+            Arg::with_name("interactive")
+                .short("i")
+                .long("interactive")
+                .conflicts_with_all(&[
+                    "json",
+                    "format",
+                    "null",
+                    "count",
+                    "count-matches",
+                    "replace",
+                    "progress",
+                ])
+                .help("Browses the results in a terminal UI"),
+        )
+        .arg(
+            Arg::with_name("progress")
+                .long("progress")
+                .help("Shows files searched, matches and bytes read on stderr"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Print results as JSON, one object per line"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .possible_values(&["text", "json", "csv", "tsv"])
+                .conflicts_with_all(&["json", "null"])
+                .help("Output format (defaults to text)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("format-template")
+                .long("format-template")
+                .value_name("TEMPLATE")
+                .conflicts_with_all(&["json", "null", "format", "interactive", "group-by-dir"])
+                .help("Prints results like {file}:{line}:{col}: {text}")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("only-matching")
+                .short("o")
+                .long("only-matching")
+                .requires("patterns")
+                .conflicts_with("invert-match")
+                .help("Prints every match on its own instead of whole lines"),
+        )
+        .arg(
+            Arg::with_name("max-columns")
+                .long("max-columns")
+                .value_name("NUM")
+                .requires("patterns")
+                .help("Prints NUM characters around the match of longer lines")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("column-mode")
+                .long("column-mode")
+                .value_name("MODE")
+                .possible_values(&["bytes", "chars", "graphemes"])
+                .requires("patterns")
+                .help("Counts columns in bytes, characters or graphemes (defaults to chars)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tab-width")
+                .long("tab-width")
+                .value_name("NUM")
+                .requires("patterns")
+                .help("Counts tabs up to the next multiple of NUM columns, like editors show them")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("byte-offset")
+                .long("byte-offset")
+                .requires("patterns")
+                .help("Prints the byte offset of every match within its file"),
+        )
+        .arg(
+            Arg::with_name("null")
+                .short("0")
+                .long("null")
+                .conflicts_with_all(&["json", "count", "count-matches"])
+                .help("Only prints file names, terminated by NUL instead of newline"),
+        )
+        .arg(
+            Arg::with_name("heading")
+                .long("heading")
+                .overrides_with("no-heading")
+                .help("Prints matched lines below their file (default on terminals)"),
+        )
+        .arg(
+            Arg::with_name("no-heading")
+                .long("no-heading")
+                .overrides_with("heading")
+                .help("Prints the file on every matched line like file:line:column:text"),
+        )
+        .arg(
+            Arg::with_name("absolute-path")
+                .long("absolute-path")
+                .conflicts_with("relative-to")
+                .help("Prints absolute paths, even if the root is relative"),
+        )
+        .arg(
+            Arg::with_name("relative-to")
+                .long("relative-to")
+                .value_name("DIR")
+                .help("Prints paths relative to DIR, with .. for files outside of it")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("hyperlink")
+                .long("hyperlink")
+                .help("Makes file names and positions clickable links in supporting terminals"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .value_name("WHEN")
+                .possible_values(&["auto", "always", "never"])
+                .help("When to use colored output (defaults to auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no-config")
+                .long("no-config")
+                .help("Ignores the config file"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .conflicts_with_all(&[
+                    "verbose",
+                    "replace",
+                    "interactive",
+                    "watch",
+                    "progress",
+                    "stats",
+                    "max-total",
+                ])
+                .help("Prints nothing and stops at the first match, only the exit status tells"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .help("Logs to stderr, -vv adds skip decisions and -vvv what every thread does"),
+        )
+        .arg(
+            Arg::with_name("roots")
+                .long("root")
+                .value_name("DIR")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Searches DIR as well, can be given several times")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("root")
+                .help("Root search directories, all of them are searched")
+                .value_name("ROOT")
+                .multiple(true),
+        )
+}
+
+// Names are only listed for completion, parsing would reject types added
+// with `--type-add`
+fn type_arg<'b>(types: Option<&'b [&'b str]>) -> Arg<'b, 'b> {
+    let arg = Arg::with_name("type")
+        .short("t")
+        .long("type")
+        .value_name("TYPE")
+        .help("Only searches files of a type like rust or py (repeatable)")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1);
+
+    match types {
+        Some(types) => arg.possible_values(types),
+        None => arg,
+    }
+}
+
+// Writes the completion script of `shell` (bash, zsh, fish or powershell)
+// File types are completed with the built-in ones and `definitions` like
+// `web:*.html,*.css`
+pub fn completions<W: Write>(
+    shell: &str,
+    definitions: &[String],
+    out: &mut W,
+) -> Result<(), Box<dyn Error>> {
+    let shell = match shell {
+        "bash" => Shell::Bash,
+        "zsh" => Shell::Zsh,
+        "fish" => Shell::Fish,
+        "powershell" => Shell::PowerShell,
+        _ => {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown shell: {}", shell),
+            )));
+        }
+    };
+
+    let mut registry = TypeRegistry::new();
+    for definition in definitions {
+        registry.add(definition)?;
+    }
+    let names = registry.names();
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+
+    definition(Some(&names)).gen_completions_to("lps", shell, out);
+    Ok(())
+}
+
+// Writes a man page in roff, rendered from the help of `app` so both always
+// describe the same options
+pub fn man<W: Write>(out: &mut W) -> io::Result<()> {
+    let mut help = Vec::new();
+    app()
+        .set_term_width(usize::MAX)
+        .write_help(&mut help)
+        .map_err(|err| io::Error::other(err.message))?;
+    let help = String::from_utf8_lossy(&help);
+
+    let mut lines = help.lines();
+    let version = lines.next().unwrap_or("lps");
+    let author = lines.next().unwrap_or("");
+    let about = lines.next().unwrap_or("");
+
+    writeln!(
+        out,
+        ".TH LPS 1 \"\" \"{}\" \"User Commands\"",
+        roff(version)
+    )?;
+    writeln!(out, ".SH NAME")?;
+    writeln!(out, "lps \\- {}", roff(about))?;
+
+    let mut section = "";
+    for line in lines {
+        if line.is_empty() {
+            continue;
+        }
+
+        if !line.starts_with(' ') {
+            // `FLAGS:` and `OPTIONS:` are all options to a reader
+            let heading = match line.trim_end_matches(':') {
+                "USAGE" => "SYNOPSIS",
+                "FLAGS" | "OPTIONS" => "OPTIONS",
+                "ARGS" => "ARGUMENTS",
+                other => other,
+            };
+            if heading != section {
+                writeln!(out, ".SH {}", heading)?;
+                section = heading;
+            }
+            continue;
+        }
+
+        let line = line.trim_start();
+        if section == "SYNOPSIS" {
+            writeln!(out, "{}", entry(line))?;
+            writeln!(out, ".br")?;
+            for usage in [
+                "lps index build|query [OPTIONS] [ROOT]",
+                "lps completions bash|zsh|fish|powershell",
+                "lps man",
+            ] {
+                writeln!(out, "{}", entry(usage))?;
+                writeln!(out, ".br")?;
+            }
+            continue;
+        }
+
+        // The name and the description are separated by at least two spaces
+        let (name, description) = line.split_once("  ").unwrap_or((line, ""));
+        writeln!(out, ".TP")?;
+        writeln!(out, "{}", entry(name))?;
+        writeln!(out, "{}", roff(description.trim_start()))?;
+    }
+
+    writeln!(out, ".SH EXIT STATUS")?;
+    for (code, meaning) in [
+        ("0", "Something matched, also despite errors with --quiet"),
+        ("1", "Nothing matched"),
+        ("2", "An error occurred"),
+        ("130", "Interrupted by Ctrl-C"),
+    ] {
+        writeln!(out, ".TP\n{}\n{}", code, roff(meaning))?;
+    }
+    writeln!(out, ".SH SEE ALSO")?;
+    writeln!(out, "The README of lps describes every option in detail.")?;
+    writeln!(out, ".SH AUTHOR")?;
+    writeln!(out, "{}", roff(author))
+}
+
+// Options in bold and values in italics, like `-c, --content <TEXT>...`
+fn entry(text: &str) -> String {
+    let words: Vec<String> = text
+        .split(' ')
+        .map(|word| {
+            let (word, comma) = match word.strip_suffix(',') {
+                Some(w) => (w, ","),
+                None => (word, ""),
+            };
+            if word.starts_with('-') {
+                format!("\\fB{}\\fR{}", roff(word), comma)
+            } else if word.starts_with('<') {
+                format!("\\fI{}\\fR{}", roff(word), comma)
+            } else {
+                format!("{}{}", roff(word), comma)
+            }
+        })
+        .collect();
+    words.join(" ")
+}
+
+fn roff(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    // A line starting with a dot or an apostrophe would be a request
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
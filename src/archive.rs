@@ -0,0 +1,163 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+
+use crate::content;
+use crate::{Config, Context, FileMatch};
+
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+// Detects archives by their file extension
+pub fn kind(path: &Path) -> Option<ArchiveKind> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+
+    if name.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else if name.ends_with(".tar") {
+        Some(ArchiveKind::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveKind::TarGz)
+    } else {
+        None
+    }
+}
+
+// Searches every file inside an archive, results are reported as
+// `archive.zip!path/inside.txt`
+// Returns `false` if `found` doesn't accept any more results
+pub fn search<F: FnMut(FileMatch) -> bool>(
+    config: &Config,
+    context: &Context,
+    path: &Path,
+    kind: ArchiveKind,
+    found: &mut F,
+) -> bool {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(err) => {
+            context.errors.skip(path, err);
+            return true;
+        }
+    };
+
+    if let Ok(metadata) = file.metadata() {
+        context.progress.add_bytes(metadata.len());
+    }
+
+    match kind {
+        ArchiveKind::Zip => search_zip(config, context, path, file, found),
+        ArchiveKind::Tar => search_tar(config, context, path, BufReader::new(file), found),
+        ArchiveKind::TarGz => search_tar(
+            config,
+            context,
+            path,
+            GzDecoder::new(BufReader::new(file)),
+            found,
+        ),
+    }
+}
+
+fn search_zip<F: FnMut(FileMatch) -> bool>(
+    config: &Config,
+    context: &Context,
+    path: &Path,
+    file: File,
+    found: &mut F,
+) -> bool {
+    let mut archive = match zip::ZipArchive::new(BufReader::new(file)) {
+        Ok(a) => a,
+        Err(err) => {
+            context
+                .errors
+                .skip(path, io::Error::new(io::ErrorKind::InvalidData, err));
+            return true;
+        }
+    };
+
+    for i in 0..archive.len() {
+        let entry = match archive.by_index(i) {
+            Ok(e) => e,
+            Err(err) => {
+                context
+                    .errors
+                    .skip(path, io::Error::new(io::ErrorKind::InvalidData, err));
+                continue;
+            }
+        };
+
+        if !entry.is_file() {
+            continue;
+        }
+
+        let name = match entry.name() {
+            Ok(n) => n.into_owned(),
+            Err(_) => continue,
+        };
+
+        let entry_path = entry_path(path, &name);
+        if let Some(result) = content::search_reader(config, context, entry_path, entry) {
+            if !found(result) {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+fn search_tar<R: Read, F: FnMut(FileMatch) -> bool>(
+    config: &Config,
+    context: &Context,
+    path: &Path,
+    reader: R,
+    found: &mut F,
+) -> bool {
+    let mut archive = tar::Archive::new(reader);
+    let entries = match archive.entries() {
+        Ok(e) => e,
+        Err(err) => {
+            context.errors.skip(path, err);
+            return true;
+        }
+    };
+
+    for entry in entries {
+        // A corrupt entry ends the archive, later headers can't be located
+        let entry = match entry {
+            Ok(e) => e,
+            Err(err) => {
+                context.errors.skip(path, err);
+                break;
+            }
+        };
+
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let name = match entry.path() {
+            Ok(p) => p.to_string_lossy().into_owned(),
+            Err(_) => continue,
+        };
+
+        let entry_path = entry_path(path, &name);
+        if let Some(result) = content::search_reader(config, context, entry_path, entry) {
+            if !found(result) {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+fn entry_path(archive: &Path, entry: &str) -> PathBuf {
+    PathBuf::from(format!("{}!{}", archive.display(), entry))
+}
@@ -0,0 +1,1107 @@
+use std::convert::TryFrom;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::cache;
+use crate::exec::Exec;
+use crate::index::{Index, IndexFilter};
+use crate::log::Log;
+use crate::matcher;
+use crate::matcher::{has_uppercase, Anchor, ContentMatcher, FilenameMatcher, Pattern};
+use crate::perm;
+use crate::perm::Permissions;
+use crate::platform;
+use crate::printer::{Columns, PathStyle};
+use crate::replace::Replace;
+use crate::template::Template;
+use crate::types::TypeRegistry;
+use crate::{
+    Attribute, BinaryFiles, Colors, ColumnMode, Config, CountMode, Encoding, FileKind,
+    HashAlgorithm, LogLevel, LongLines, OutputFormat, SortBy, Strategy,
+};
+
+// How the filename pattern is interpreted
+pub enum NameMode {
+    Literal,
+    Glob,
+    Regex,
+}
+
+// Builds a `Config` programmatically, patterns are compiled in `build`
+pub struct Search {
+    config: Config,
+    roots: Vec<PathBuf>,
+    log_level: LogLevel,
+    filename: Option<(String, NameMode)>,
+    ignore_filename_case: bool,
+    smart_filename_case: bool,
+    normalize_names: bool,
+    content: Vec<String>,
+    pattern_files: Vec<PathBuf>,
+    all_patterns: bool,
+    pattern_stats: bool,
+    regex: bool,
+    ignore_content_case: bool,
+    smart_content_case: bool,
+    word: bool,
+    line: bool,
+    exclude: Vec<String>,
+    exclude_dir: Vec<String>,
+    file_types: Vec<String>,
+    type_definitions: Vec<String>,
+    template: Option<String>,
+    walk_threads: Option<usize>,
+    search_threads: Option<usize>,
+    perm: Option<String>,
+    owner: Option<String>,
+    group: Option<String>,
+    // Command line and whether it runs in batch mode
+    exec: Option<(String, bool)>,
+    // Directory of the cache files and the key of the options
+    cache: Option<(PathBuf, String)>,
+    // Directory the trigram index is kept in
+    index: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn builder() -> Search {
+        Search {
+            config: Config {
+                log: Log::new(LogLevel::Off),
+                filename: None,
+                content: None,
+                dop: num_cpus::get(),
+                walk_threads: 0,
+                search_threads: 0,
+                roots: Vec::new(),
+                file_roots: Vec::new(),
+                no_ignore: false,
+                ignore_files: Vec::new(),
+                hidden: false,
+                max_depth: None,
+                strategy: Strategy::DepthFirst,
+                one_file_system: false,
+                allow_special: false,
+                attributes: Vec::new(),
+                file_timeout: None,
+                exclude: Vec::new(),
+                exclude_dir: Vec::new(),
+                file_types: Vec::new(),
+                kinds: Vec::new(),
+                empty: false,
+                format: OutputFormat::Text,
+                color: false,
+                colors: Colors::default(),
+                heading: true,
+                path_style: PathStyle::Found,
+                hyperlink: false,
+                byte_offset: false,
+                only_matching: false,
+                binary_files: BinaryFiles::Skip,
+                count: None,
+                min_size: None,
+                max_size: None,
+                max_count: None,
+                max_total: None,
+                newer_than: None,
+                older_than: None,
+                follow: false,
+                invert_match: false,
+                multiline: false,
+                replace: None,
+                files_without_match: false,
+                files_with_matches: false,
+                archives: false,
+                search_zip: false,
+                encoding: Encoding::Auto,
+                files_from: None,
+                stdin: false,
+                progress: false,
+                interactive: false,
+                watch: false,
+                sort: SortBy::None,
+                group_by_dir: false,
+                stats: false,
+                profile: false,
+                pattern_stats: None,
+                cache: None,
+                listings: None,
+                index: None,
+                template: None,
+                quiet: false,
+                max_line_length: None,
+                memory_limit: None,
+                long_lines: LongLines::Truncate,
+                max_columns: None,
+                columns: Columns {
+                    mode: ColumnMode::Chars,
+                    tab_width: None,
+                },
+                name_or_content: false,
+                duplicates: false,
+                hash: None,
+                exec: None,
+                cat: false,
+                cat_header: false,
+                perm: None,
+                owner: None,
+                group: None,
+            },
+            roots: Vec::new(),
+            log_level: LogLevel::Off,
+            filename: None,
+            ignore_filename_case: false,
+            smart_filename_case: false,
+            normalize_names: true,
+            content: Vec::new(),
+            pattern_files: Vec::new(),
+            all_patterns: false,
+            pattern_stats: false,
+            regex: false,
+            ignore_content_case: false,
+            smart_content_case: false,
+            word: false,
+            line: false,
+            exclude: Vec::new(),
+            exclude_dir: Vec::new(),
+            file_types: Vec::new(),
+            type_definitions: Vec::new(),
+            template: None,
+            walk_threads: None,
+            search_threads: None,
+            perm: None,
+            owner: None,
+            group: None,
+            exec: None,
+            cache: None,
+            index: None,
+        }
+    }
+}
+
+impl Search {
+    // Root search directory, defaults to the current working directory
+    // Given several times all roots are searched, the same directory is only
+    // searched once even if it's below several of them
+    // A file is searched by itself, no matter if it's hidden or ignored
+    pub fn root<P: Into<PathBuf>>(mut self, root: P) -> Search {
+        self.roots.push(root.into());
+        self
+    }
+
+    pub fn name(mut self, pattern: &str, mode: NameMode) -> Search {
+        self.filename = Some((String::from(pattern), mode));
+        self
+    }
+
+    pub fn ignore_filename_case(mut self, ignore: bool) -> Search {
+        self.ignore_filename_case = ignore;
+        self
+    }
+
+    // Ignores casing of the filename unless the pattern contains uppercase letters
+    pub fn smart_filename_case(mut self, smart: bool) -> Search {
+        self.smart_filename_case = smart;
+        self
+    }
+
+    // Name filters and excludes match names in NFC, whichever Unicode
+    // normalization form they are stored in, true by default
+    pub fn normalize_names(mut self, normalize: bool) -> Search {
+        self.normalize_names = normalize;
+        self
+    }
+
+    // Reports files matching the name and files matching the content
+    // independently instead of only searching files matching the name
+    pub fn name_or_content(mut self, either: bool) -> Search {
+        self.config.name_or_content = either;
+        self
+    }
+
+    // Adds a content pattern, can be called multiple times
+    pub fn content(mut self, text: &str) -> Search {
+        self.content.push(String::from(text));
+        self
+    }
+
+    // Loads content patterns from a file, one per line, that are searched
+    // as a single pattern matching if any line matches
+    pub fn pattern_file<P: Into<PathBuf>>(mut self, path: P) -> Search {
+        self.pattern_files.push(path.into());
+        self
+    }
+
+    // Requires files to contain all content patterns instead of any
+    pub fn all_patterns(mut self, all: bool) -> Search {
+        self.all_patterns = all;
+        self
+    }
+
+    // Counts the files and lines every content pattern matched, printed to
+    // stderr after the results of `run`, a pattern file counts as one
+    pub fn pattern_stats(mut self, stats: bool) -> Search {
+        self.pattern_stats = stats;
+        self
+    }
+
+    // Interprets the content as regular expression
+    pub fn regex(mut self, regex: bool) -> Search {
+        self.regex = regex;
+        self
+    }
+
+    // Case folding follows Unicode rules and works in literal and regex mode
+    pub fn ignore_content_case(mut self, ignore: bool) -> Search {
+        self.ignore_content_case = ignore;
+        self
+    }
+
+    // Ignores casing of each content pattern that contains no uppercase letters
+    pub fn smart_content_case(mut self, smart: bool) -> Search {
+        self.smart_content_case = smart;
+        self
+    }
+
+    // Only matches whole words, bounded by non-word characters
+    pub fn word(mut self, word: bool) -> Search {
+        self.word = word;
+        self
+    }
+
+    // Only matches whole lines, takes precedence over `word`
+    pub fn line(mut self, line: bool) -> Search {
+        self.line = line;
+        self
+    }
+
+    // Amount of threads used for walking and content search unless
+    // set separately
+    pub fn dop(mut self, dop: usize) -> Search {
+        self.config.dop = dop;
+        self
+    }
+
+    // Amount of threads reading directories, fewer threads keep
+    // spinning disks from seeking
+    pub fn walk_threads(mut self, threads: usize) -> Search {
+        self.walk_threads = Some(threads);
+        self
+    }
+
+    // Amount of worker threads used for content search
+    pub fn search_threads(mut self, threads: usize) -> Search {
+        self.search_threads = Some(threads);
+        self
+    }
+
+    pub fn no_ignore(mut self, no_ignore: bool) -> Search {
+        self.config.no_ignore = no_ignore;
+        self
+    }
+
+    // Loads extra gitignore rules matched relative to the root, can be
+    // called multiple times, later files take precedence
+    // They apply even with `no_ignore` and have the lowest precedence
+    pub fn ignore_file<P: Into<PathBuf>>(mut self, path: P) -> Search {
+        self.config.ignore_files.push(path.into());
+        self
+    }
+
+    // Includes hidden files and directories
+    pub fn hidden(mut self, hidden: bool) -> Search {
+        self.config.hidden = hidden;
+        self
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Search {
+        self.config.max_depth = Some(depth);
+        self
+    }
+
+    // Walks depth-first by default, breadth-first finds shallow files first
+    // at the cost of more pending directories
+    pub fn strategy(mut self, strategy: Strategy) -> Search {
+        self.config.strategy = strategy;
+        self
+    }
+
+    // Doesn't descend into mount points, only supported on unix
+    pub fn one_file_system(mut self, one_file_system: bool) -> Search {
+        self.config.one_file_system = one_file_system;
+        self
+    }
+
+    // Includes sockets, pipes and devices and descends into `/proc`, `/sys`
+    // and `/dev`, which are skipped by default since reading them can block
+    // forever, special files given to `kind` are always included
+    pub fn allow_special(mut self, allow: bool) -> Search {
+        self.config.allow_special = allow;
+        self
+    }
+
+    // Only includes files which have the attribute, or don't have it if
+    // `set` is false, can be called multiple times
+    // Requiring `Attribute::Hidden` includes hidden files
+    pub fn attribute(mut self, attribute: Attribute, set: bool) -> Search {
+        if attribute == Attribute::Hidden && set {
+            self.config.hidden = true;
+        }
+        self.config.attributes.push((attribute, set));
+        self
+    }
+
+    pub fn exclude(mut self, glob: &str) -> Search {
+        self.exclude.push(String::from(glob));
+        self
+    }
+
+    pub fn exclude_dir(mut self, glob: &str) -> Search {
+        self.exclude_dir.push(String::from(glob));
+        self
+    }
+
+    // Only searches files of a type like `rust` or `py`, can be called
+    // multiple times to include several types
+    pub fn file_type(mut self, name: &str) -> Search {
+        self.file_types.push(String::from(name));
+        self
+    }
+
+    // Only includes entries of this kind, can be called multiple times
+    // Directories are reported without searching their content
+    pub fn kind(mut self, kind: FileKind) -> Search {
+        if !self.config.kinds.contains(&kind) {
+            self.config.kinds.push(kind);
+        }
+        self
+    }
+
+    // Only includes zero-byte files and directories without entries
+    pub fn empty(mut self, empty: bool) -> Search {
+        self.config.empty = empty;
+        self
+    }
+
+    // Defines a file type like `web:*.html,*.css`, existing types are extended
+    pub fn type_add(mut self, definition: &str) -> Search {
+        self.type_definitions.push(String::from(definition));
+        self
+    }
+
+    // Same as the `Info` level of `log_level`
+    pub fn verbose(mut self, verbose: bool) -> Search {
+        self.log_level = if verbose {
+            LogLevel::Info
+        } else {
+            LogLevel::Off
+        };
+        self
+    }
+
+    // Logs to stderr, `Debug` adds why entries are skipped and `Trace`
+    // what every walk and search thread works on
+    pub fn log_level(mut self, level: LogLevel) -> Search {
+        self.log_level = level;
+        self
+    }
+
+    // Only used by `run`, `search` yields results directly
+    pub fn format(mut self, format: OutputFormat) -> Search {
+        self.config.format = format;
+        self
+    }
+
+    // Prints every result of `run` with a layout like `{file}:{line}: {text}`
+    // instead of `format`, see `Template` for the placeholders
+    pub fn format_template(mut self, template: &str) -> Search {
+        self.template = Some(String::from(template));
+        self
+    }
+
+    pub fn color(mut self, color: bool) -> Search {
+        self.config.color = color;
+        self
+    }
+
+    // Escape sequences used if `color` is enabled
+    pub fn colors(mut self, colors: Colors) -> Search {
+        self.config.colors = colors;
+        self
+    }
+
+    // Prints the matched lines of a file below its name in text output,
+    // otherwise every line is printed like `file:line:column:text`
+    pub fn heading(mut self, heading: bool) -> Search {
+        self.config.heading = heading;
+        self
+    }
+
+    // Prints absolute paths, even if the root is relative
+    pub fn absolute_path(mut self, absolute: bool) -> Search {
+        self.config.path_style = if absolute {
+            PathStyle::Absolute
+        } else {
+            PathStyle::Found
+        };
+        self
+    }
+
+    // Prints paths relative to `dir`, with `..` for files outside of it
+    pub fn relative_to<P: Into<PathBuf>>(mut self, dir: P) -> Search {
+        self.config.path_style = PathStyle::RelativeTo(dir.into());
+        self
+    }
+
+    // Wraps file names and positions in text output in OSC 8 hyperlinks to
+    // `file://` URLs with the line as fragment, e.g. `file:///src/main.rs#12`
+    pub fn hyperlink(mut self, hyperlink: bool) -> Search {
+        self.config.hyperlink = hyperlink;
+        self
+    }
+
+    // Prints the byte offset of every match in text output,
+    // `LineMatch::offset` is always available
+    pub fn byte_offset(mut self, byte_offset: bool) -> Search {
+        self.config.byte_offset = byte_offset;
+        self
+    }
+
+    // Reports every match of a line on its own and only prints the
+    // matched text instead of the whole line
+    pub fn only_matching(mut self, only_matching: bool) -> Search {
+        self.config.only_matching = only_matching;
+        self
+    }
+
+    // Prints a window of `max` characters around the match of longer
+    // lines in text output
+    pub fn max_columns(mut self, max: usize) -> Search {
+        self.config.max_columns = Some(max);
+        self
+    }
+
+    // Counts reported columns in bytes, characters or graphemes, characters
+    // by default
+    pub fn column_mode(mut self, mode: ColumnMode) -> Search {
+        self.config.columns.mode = mode;
+        self
+    }
+
+    // Reports visual columns with tabs expanded to the next multiple of
+    // `width`, like an editor shows them
+    pub fn tab_width(mut self, width: usize) -> Search {
+        self.config.columns.tab_width = Some(width);
+        self
+    }
+
+    pub fn binary_files(mut self, binary_files: BinaryFiles) -> Search {
+        self.config.binary_files = binary_files;
+        self
+    }
+
+    // Only count matches per file instead of collecting lines
+    pub fn count(mut self, mode: CountMode) -> Search {
+        self.config.count = Some(mode);
+        self
+    }
+
+    // Minimum file size in bytes
+    pub fn min_size(mut self, size: u64) -> Search {
+        self.config.min_size = Some(size);
+        self
+    }
+
+    // Maximum file size in bytes
+    pub fn max_size(mut self, size: u64) -> Search {
+        self.config.max_size = Some(size);
+        self
+    }
+
+    // Gives up searching a file after this time and reports it as error,
+    // e.g. a file on a hanging network mount
+    // The worker is replaced, its thread can't be stopped and is left behind
+    pub fn file_timeout(mut self, timeout: Duration) -> Search {
+        self.config.file_timeout = Some(timeout);
+        self
+    }
+
+    // Stops searching a file after `max` matching lines
+    pub fn max_count(mut self, max: usize) -> Search {
+        self.config.max_count = Some(max);
+        self
+    }
+
+    // Stops the whole search after `max` matches, listed files count once
+    pub fn max_total(mut self, max: usize) -> Search {
+        self.config.max_total = Some(max);
+        self
+    }
+
+    // Lines longer than `max` bytes are truncated or skipped, keeps
+    // minified files from flooding the output and memory
+    pub fn max_line_length(mut self, max: usize, long_lines: LongLines) -> Search {
+        self.config.max_line_length = Some(max);
+        self.config.long_lines = long_lines;
+        self
+    }
+
+    // Bounds the bytes of found paths, files read at once and results not
+    // yet taken by the consumer, threads wait once it's reached
+    // Lines are cut at half the share of a worker unless `max_line_length`
+    // is set, results can't be sorted or grouped
+    pub fn memory_limit(mut self, bytes: u64) -> Search {
+        self.config.memory_limit = Some(bytes);
+        self
+    }
+
+    // Only includes files modified after `time`
+    pub fn newer_than(mut self, time: SystemTime) -> Search {
+        self.config.newer_than = Some(time);
+        self
+    }
+
+    // Only includes files modified before `time`
+    pub fn older_than(mut self, time: SystemTime) -> Search {
+        self.config.older_than = Some(time);
+        self
+    }
+
+    // Only includes files with matching permission bits, like `0644`,
+    // `-0600` (all bits), `/0022` (any bit) or `+x`, `o+w`, `u+s`
+    // Permission, owner and group filters are only supported on unix
+    pub fn perm(mut self, spec: &str) -> Search {
+        self.perm = Some(String::from(spec));
+        self
+    }
+
+    // Only includes files owned by the user name or id
+    pub fn owner(mut self, user: &str) -> Search {
+        self.owner = Some(String::from(user));
+        self
+    }
+
+    // Only includes files belonging to the group name or id
+    pub fn group(mut self, group: &str) -> Search {
+        self.group = Some(String::from(group));
+        self
+    }
+
+    // Descends into symlinked directories, loops are detected
+    pub fn follow(mut self, follow: bool) -> Search {
+        self.config.follow = follow;
+        self
+    }
+
+    // Reports lines not containing the content
+    pub fn invert_match(mut self, invert: bool) -> Search {
+        self.config.invert_match = invert;
+        self
+    }
+
+    // Matches can span multiple lines, e.g. `fn\s+main\(\)\s*\{\n`
+    // Files are searched as a whole instead of line by line
+    pub fn multiline(mut self, multiline: bool) -> Search {
+        self.config.multiline = multiline;
+        self
+    }
+
+    // Rewrites matches with `text` in place, see `run`
+    // Only changes are printed with `dry_run`, `backup` keeps `<file>.bak`
+    pub fn replace(mut self, text: &str, dry_run: bool, backup: bool) -> Search {
+        self.config.replace = Some(Replace {
+            text: String::from(text),
+            expand: false,
+            dry_run,
+            backup,
+        });
+        self
+    }
+
+    // Reports files not containing the content instead of matches
+    pub fn files_without_match(mut self, files_without_match: bool) -> Search {
+        self.config.files_without_match = files_without_match;
+        self
+    }
+
+    // Reports files containing the content instead of matches,
+    // a file isn't read any further after its first match
+    pub fn files_with_matches(mut self, files_with_matches: bool) -> Search {
+        self.config.files_with_matches = files_with_matches;
+        self
+    }
+
+    // Searches the entries of .zip, .tar and .tar.gz files
+    pub fn archives(mut self, archives: bool) -> Search {
+        self.config.archives = archives;
+        self
+    }
+
+    // Transparently decompresses .gz, .bz2, .xz and .zst files
+    pub fn search_zip(mut self, search_zip: bool) -> Search {
+        self.config.search_zip = search_zip;
+        self
+    }
+
+    // Encoding of searched files, detected by byte order mark by default
+    pub fn encoding(mut self, encoding: Encoding) -> Search {
+        self.config.encoding = encoding;
+        self
+    }
+
+    // Searches the files listed in a file instead of walking the root,
+    // `-` reads the list from stdin
+    pub fn files_from<P: Into<PathBuf>>(mut self, list: P) -> Search {
+        self.config.files_from = Some(list.into());
+        self
+    }
+
+    // Searches the content of stdin instead of the roots, its result is
+    // reported as `<stdin>`
+    pub fn stdin(mut self, stdin: bool) -> Search {
+        self.config.stdin = stdin;
+        self
+    }
+
+    // Shows files searched, matches and bytes on stderr while `run` is running
+    pub fn progress(mut self, progress: bool) -> Search {
+        self.config.progress = progress;
+        self
+    }
+
+    // Shows the results of `run` in a terminal UI instead of printing them
+    pub fn interactive(mut self, interactive: bool) -> Search {
+        self.config.interactive = interactive;
+        self
+    }
+
+    // Buffers the results of `run` and prints them in this order
+    pub fn sort(mut self, by: SortBy) -> Search {
+        self.config.sort = by;
+        self
+    }
+
+    // Prints the results of `run` below a header per directory,
+    // results are buffered like with `sort`
+    pub fn group_by_dir(mut self, group: bool) -> Search {
+        self.config.group_by_dir = group;
+        self
+    }
+
+    // Prints counters and timings after the results of `run`
+    pub fn stats(mut self, stats: bool) -> Search {
+        self.config.stats = stats;
+        self
+    }
+
+    // Prints the time spent per phase and how busy every thread was
+    // to stderr after the results of `run`
+    pub fn profile(mut self, profile: bool) -> Search {
+        self.config.profile = profile;
+        self
+    }
+
+    // Prints groups of found files with identical content instead of the
+    // files themselves, only works in file search
+    pub fn duplicates(mut self, duplicates: bool) -> Search {
+        self.config.duplicates = duplicates;
+        self
+    }
+
+    // Prints a digest of the content next to every found file, only works
+    // in file search
+    pub fn hash(mut self, algorithm: HashAlgorithm) -> Search {
+        self.config.hash = Some(algorithm);
+        self
+    }
+
+    // Runs a command for every matched file instead of printing it, on
+    // multiple threads like the search itself
+    // `{}` is replaced with the path, `{/}` with the file name, `{//}` with
+    // the parent directory and `{.}` and `{/.}` without extension, the path
+    // is appended if there's no placeholder
+    pub fn exec(mut self, command: &str) -> Search {
+        self.exec = Some((String::from(command), false));
+        self
+    }
+
+    // Runs a command once with all matched files after the search
+    pub fn exec_batch(mut self, command: &str) -> Search {
+        self.exec = Some((String::from(command), true));
+        self
+    }
+
+    // Keeps directory listings and results in a file per root in `dir`,
+    // so the next search of the same tree skips unchanged directories and
+    // files, results are only reused if `key` is the same
+    // `key` has to change with every option affecting the results
+    pub fn cache<P: Into<PathBuf>>(mut self, dir: P, key: &str) -> Search {
+        self.cache = Some((dir.into(), String::from(key)));
+        self
+    }
+
+    // Doesn't search files which are unchanged since `lps index build` and
+    // can't match according to the trigram index kept in `dir`
+    pub fn index<P: Into<PathBuf>>(mut self, dir: P) -> Search {
+        self.index = Some(dir.into());
+        self
+    }
+
+    // Prints the content of every matched file instead of its name,
+    // optionally after a `==> path <==` header
+    pub fn cat(mut self, cat: bool, header: bool) -> Search {
+        self.config.cat = cat;
+        self.config.cat_header = header;
+        self
+    }
+
+    // Keeps `run` going after the search and re-searches files as they
+    // are created or modified, only new matches are printed
+    pub fn watch(mut self, watch: bool) -> Search {
+        self.config.watch = watch;
+        self
+    }
+
+    // Prints nothing in `run` and stops at the first match
+    pub fn quiet(mut self, quiet: bool) -> Search {
+        self.config.quiet = quiet;
+        self
+    }
+
+    pub fn build(self) -> Result<Arc<Config>, Box<dyn Error>> {
+        let mut config = self.config;
+        config.log = Log::new(self.log_level);
+
+        let roots = if self.roots.is_empty() {
+            vec![env::current_dir()?]
+        } else {
+            self.roots
+        };
+        // Roots reached through different paths are the same root, the first
+        // way it was given is kept
+        let mut canonical = Vec::with_capacity(roots.len());
+        for root in roots {
+            let is_file = root.is_file();
+            if !is_file && !root.is_dir() {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{}: root is neither a directory nor a file",
+                        platform::display(&root)
+                    ),
+                )));
+            }
+
+            let id = fs::canonicalize(&root)?;
+            if !canonical.contains(&id) {
+                canonical.push(id);
+                // Lifts the path length limit of Windows for everything below the root
+                config.roots.push(platform::extended(&root).into_owned());
+                config.file_roots.push(is_file);
+            }
+        }
+
+        if let Some((dir, key)) = &self.cache {
+            let name = cache::file_name(&config.roots, "json");
+            config.cache = Some((dir.join(name), key.clone()));
+        }
+
+        let normalize = self.normalize_names;
+        let normalized = |pattern: &str| {
+            if normalize {
+                matcher::nfc(pattern).into_owned()
+            } else {
+                String::from(pattern)
+            }
+        };
+
+        if let Some((pattern, mode)) = &self.filename {
+            let pattern = normalized(pattern);
+            let ignore_case = self.ignore_filename_case
+                || (self.smart_filename_case
+                    && !has_uppercase(&pattern, matches!(mode, NameMode::Regex)));
+            let matcher = match mode {
+                NameMode::Literal => FilenameMatcher::literal(&pattern, ignore_case),
+                NameMode::Glob => FilenameMatcher::glob(&pattern, ignore_case)?,
+                NameMode::Regex => FilenameMatcher::regex(&pattern, ignore_case)?,
+            };
+            config.filename = Some(matcher.normalized(normalize));
+        }
+
+        if !self.content.is_empty() || !self.pattern_files.is_empty() {
+            let anchor = if self.line {
+                Anchor::Line
+            } else if self.word {
+                Anchor::Word
+            } else {
+                Anchor::None
+            };
+
+            let mut patterns = Vec::new();
+            // Every pattern with its alternatives and casing for the index
+            let mut sources = Vec::new();
+            let mut casing = Vec::new();
+            for pattern in &self.content {
+                let ignore_case = self.ignore_content_case
+                    || (self.smart_content_case && !has_uppercase(pattern, self.regex));
+                patterns.push(Pattern::new(pattern, self.regex, ignore_case, anchor)?);
+                sources.push(vec![pattern.clone()]);
+                casing.push(ignore_case);
+            }
+
+            for path in &self.pattern_files {
+                let lines = read_patterns(path)?;
+                let regex = self.regex;
+                let ignore_case = self.ignore_content_case
+                    || (self.smart_content_case && !lines.iter().any(|p| has_uppercase(p, regex)));
+                patterns.push(Pattern::any(&lines, self.regex, ignore_case, anchor)?);
+                sources.push(lines);
+                casing.push(ignore_case);
+            }
+            config.content = Some(ContentMatcher::new(patterns, self.all_patterns));
+
+            if self.pattern_stats {
+                if config.invert_match {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "pattern statistics can't be taken of inverted matches",
+                    )));
+                }
+                let files = self.pattern_files.iter().map(|p| platform::display(p));
+                let names = self.content.iter().cloned();
+                config.pattern_stats = Some(names.chain(files.map(String::from)).collect());
+            }
+
+            if let Some(dir) = &self.index {
+                if config.invert_match || config.archives || config.search_zip {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "the index can't be used with inverted matches or archives",
+                    )));
+                }
+                if !matches!(config.encoding, Encoding::Auto | Encoding::Utf8) {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "the index can't be used with other encodings than UTF-8",
+                    )));
+                }
+
+                if config.roots.len() > 1 {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "the index can only be used with a single root",
+                    )));
+                }
+                if config.file_roots[0] {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "the index can only be used with a directory as root",
+                    )));
+                }
+
+                let (index, prefix) = Index::find(dir, &config.roots[0])?;
+                config.index = Some(IndexFilter::new(
+                    index,
+                    prefix,
+                    &sources,
+                    self.regex,
+                    &casing,
+                    self.all_patterns,
+                ));
+            }
+        } else if self.index.is_some() {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the index can only be used in content search",
+            )));
+        } else if self.pattern_stats {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "pattern statistics are only taken in content search",
+            )));
+        }
+
+        for pattern in &self.exclude {
+            let matcher = FilenameMatcher::glob(&normalized(pattern), false)?;
+            config.exclude.push(matcher.normalized(normalize));
+        }
+
+        for pattern in &self.exclude_dir {
+            // Allow `target/` as well as `target`
+            let pattern = normalized(pattern.trim_end_matches('/'));
+            let matcher = FilenameMatcher::glob(&pattern, false)?;
+            config.exclude_dir.push(matcher.normalized(normalize));
+        }
+
+        // Groups can only be referenced in regex mode
+        if let Some(replace) = &mut config.replace {
+            replace.expand = self.regex;
+        }
+
+        if !self.file_types.is_empty() {
+            let mut registry = TypeRegistry::new();
+            for definition in &self.type_definitions {
+                registry.add(definition)?;
+            }
+            config.file_types = registry.matchers(&self.file_types)?;
+        }
+
+        if config.duplicates && config.content.is_some() {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "duplicates can only be found in file search",
+            )));
+        }
+
+        if config.hash.is_some() && config.content.is_some() {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "files can only be hashed in file search",
+            )));
+        }
+
+        if config.one_file_system && cfg!(not(unix)) {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "one file system is only supported on unix",
+            )));
+        }
+
+        if self.perm.is_some() || self.owner.is_some() || self.group.is_some() {
+            if cfg!(not(unix)) {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "permission filters are only supported on unix",
+                )));
+            }
+
+            if let Some(spec) = &self.perm {
+                config.perm = Some(Permissions::parse(spec)?);
+            }
+            if let Some(user) = &self.owner {
+                config.owner = Some(perm::user_id(user)?);
+            }
+            if let Some(group) = &self.group {
+                config.group = Some(perm::group_id(group)?);
+            }
+        }
+
+        // Ignore files of directories are optional, these aren't
+        for path in &config.ignore_files {
+            if let Err(err) = fs::File::open(path) {
+                return Err(Box::new(io::Error::new(
+                    err.kind(),
+                    format!("ignore file {}: {}", path.display(), err),
+                )));
+            }
+        }
+
+        if let Some((command, batch)) = &self.exec {
+            config.exec = Some(Arc::new(Exec::parse(command, *batch)?));
+        }
+
+        if let Some(template) = &self.template {
+            config.template = Some(Template::parse(template)?);
+        }
+
+        // Stops like `max_total` once anything matched, a file isn't
+        // read any further after its first match
+        if config.quiet {
+            config.max_total = Some(1);
+            if !config.files_without_match {
+                config.files_with_matches = true;
+            }
+        }
+
+        // Without workers content search would silently find nothing
+        config.walk_threads = self.walk_threads.unwrap_or(config.dop);
+        config.search_threads = self.search_threads.unwrap_or(config.dop);
+        if config.walk_threads == 0 || config.search_threads == 0 {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid degree of parallelism",
+            )));
+        }
+
+        if let Some(limit) = config.memory_limit {
+            if config.sort != SortBy::None || config.group_by_dir {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the memory limit can't be used with sorted or grouped output",
+                )));
+            }
+
+            // Every worker may keep a line of half its share, multiline
+            // search needs whole files anyway
+            let share = limit / config.search_threads as u64 / 2;
+            if config.max_line_length.is_none() && !config.multiline {
+                config.max_line_length = Some(usize::try_from(share).unwrap_or(usize::MAX).max(1));
+            }
+        }
+
+        if config.max_depth == Some(0) {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid maximum depth",
+            )));
+        }
+
+        if config.columns.tab_width == Some(0) {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid tab width",
+            )));
+        }
+
+        if config.stdin && config.content.is_none() {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only the content of stdin can be searched",
+            )));
+        }
+        // Its content is gone once searched
+        let rereads = config.cat || config.exec.is_some() || config.watch;
+        if config.stdin && (rereads || config.replace.is_some()) {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "stdin can't be printed, replaced in, passed to a command or watched",
+            )));
+        }
+
+        Ok(Arc::new(config))
+    }
+}
+
+// Empty lines are skipped, they would match every line
+fn read_patterns(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let text = match fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(err) => {
+            return Err(Box::new(io::Error::new(
+                err.kind(),
+                format!("pattern file {}: {}", path.display(), err),
+            )));
+        }
+    };
+
+    let patterns: Vec<String> = text
+        .lines()
+        .map(|l| l.trim_end_matches('\r'))
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect();
+
+    if patterns.is_empty() {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("pattern file {} contains no patterns", path.display()),
+        )));
+    }
+
+    Ok(patterns)
+}
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Map, Value};
+
+use crate::kind::FileKind;
+use crate::{FileMatch, LineMatch};
+
+// Bumped whenever the format changes, older caches are discarded
+const VERSION: u64 = 1;
+
+// Timestamps this close to the time they are recorded can't be trusted,
+// the entry could change again within the resolution of the filesystem
+const RACY: Duration = Duration::from_secs(2);
+
+// Directory listings and search results of the last run, see `Search::cache`
+// A listing is reused while its directory wasn't modified and a result while
+// the size and modification time of its file are unchanged and the search
+// options, identified by `key`, are the same
+// Only what was used during a run is written back, so deleted files drop out
+pub struct Cache {
+    // `None` for the listings `--serve` keeps in memory
+    path: Option<PathBuf>,
+    key: String,
+    dirs: HashMap<String, Listing>,
+    files: HashMap<String, Entry>,
+    used_dirs: Mutex<HashMap<String, Listing>>,
+    used_files: Mutex<HashMap<String, Entry>>,
+}
+
+#[derive(Clone)]
+struct Listing {
+    modified: u64,
+    entries: Vec<(String, FileKind)>,
+}
+
+#[derive(Clone)]
+struct Entry {
+    modified: u64,
+    len: u64,
+    result: FileMatch,
+}
+
+impl Cache {
+    // A missing or unreadable cache file is the same as an empty one
+    pub fn load(path: PathBuf, key: &str) -> Cache {
+        let mut cache = Cache {
+            path: Some(path),
+            key: String::from(key),
+            ..Cache::memory()
+        };
+
+        let value: Value = match fs::read(cache.path.as_ref().unwrap())
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+        {
+            Some(v) => v,
+            None => return cache,
+        };
+        if value["version"].as_u64() != Some(VERSION) {
+            return cache;
+        }
+
+        if let Some(dirs) = value["dirs"].as_object() {
+            cache.dirs = dirs
+                .iter()
+                .filter_map(|(path, listing)| Some((path.clone(), parse_listing(listing)?)))
+                .collect();
+        }
+
+        // Results of other options are useless, listings are still valid
+        if value["key"].as_str() == Some(key) {
+            if let Some(files) = value["files"].as_object() {
+                cache.files = files
+                    .iter()
+                    .filter_map(|(path, entry)| Some((path.clone(), parse_entry(path, entry)?)))
+                    .collect();
+            }
+        }
+
+        cache
+    }
+
+    // Only keeps listings, results depend on the options of every search
+    pub fn memory() -> Cache {
+        Cache {
+            path: None,
+            key: String::new(),
+            dirs: HashMap::new(),
+            files: HashMap::new(),
+            used_dirs: Mutex::new(HashMap::new()),
+            used_files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // The listings of the last run and those read during it, for the next
+    // search of `--serve`
+    pub fn merged(&self) -> Cache {
+        let mut dirs = self.dirs.clone();
+        dirs.extend(self.used_dirs.lock().unwrap().clone());
+        Cache {
+            dirs,
+            ..Cache::memory()
+        }
+    }
+
+    // Returns the entries of `dir` if it wasn't modified since it was listed
+    pub fn listing(&self, dir: &Path, modified: SystemTime) -> Option<Vec<(PathBuf, FileKind)>> {
+        let key = dir.to_str()?;
+        let listing = self.dirs.get(key)?;
+        if Some(listing.modified) != nanos(modified) {
+            return None;
+        }
+
+        self.used_dirs
+            .lock()
+            .unwrap()
+            .insert(String::from(key), listing.clone());
+        Some(
+            listing
+                .entries
+                .iter()
+                .map(|(name, kind)| (dir.join(name), *kind))
+                .collect(),
+        )
+    }
+
+    pub fn record_listing(
+        &self,
+        dir: &Path,
+        modified: SystemTime,
+        entries: &[(PathBuf, FileKind)],
+    ) {
+        let (key, modified) = match (dir.to_str(), trusted(modified)) {
+            (Some(k), Some(m)) => (k, m),
+            _ => return,
+        };
+
+        // Names that aren't valid UTF-8 can't be stored, the directory is
+        // read again next time
+        let mut names = Vec::with_capacity(entries.len());
+        for (path, kind) in entries {
+            match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) => names.push((String::from(name), *kind)),
+                None => return,
+            }
+        }
+
+        let listing = Listing {
+            modified,
+            entries: names,
+        };
+        self.used_dirs
+            .lock()
+            .unwrap()
+            .insert(String::from(key), listing);
+    }
+
+    // Returns the result of the last run if the file is unchanged
+    pub fn result(&self, path: &Path, metadata: &fs::Metadata) -> Option<FileMatch> {
+        let key = path.to_str()?;
+        let entry = self.files.get(key)?;
+        if entry.len != metadata.len() || Some(entry.modified) != nanos(metadata.modified().ok()?) {
+            return None;
+        }
+
+        self.used_files
+            .lock()
+            .unwrap()
+            .insert(String::from(key), entry.clone());
+        Some(entry.result.clone())
+    }
+
+    pub fn record_result(&self, path: &Path, metadata: &fs::Metadata, result: &FileMatch) {
+        let key = match (&self.path, path.to_str()) {
+            (Some(_), Some(k)) => k,
+            _ => return,
+        };
+        let modified = match metadata.modified().ok().and_then(trusted) {
+            Some(m) => m,
+            None => return,
+        };
+
+        let entry = Entry {
+            modified,
+            len: metadata.len(),
+            result: result.clone(),
+        };
+        self.used_files
+            .lock()
+            .unwrap()
+            .insert(String::from(key), entry);
+    }
+
+    // Writes everything used during this run, with `keep` entries of the
+    // last run which weren't used are kept as well, e.g. if it stopped early
+    // The file is replaced at once, so concurrent runs never see half of it
+    pub fn save(&self, keep: bool) -> io::Result<()> {
+        let target = match &self.path {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        let mut dirs = self.used_dirs.lock().unwrap().clone();
+        let mut files = self.used_files.lock().unwrap().clone();
+        if keep {
+            for (path, listing) in &self.dirs {
+                dirs.entry(path.clone()).or_insert_with(|| listing.clone());
+            }
+            for (path, entry) in &self.files {
+                files.entry(path.clone()).or_insert_with(|| entry.clone());
+            }
+        }
+
+        let dirs: Map<String, Value> = dirs
+            .into_iter()
+            .map(|(path, listing)| (path, listing_to_json(&listing)))
+            .collect();
+        let files: Map<String, Value> = files
+            .into_iter()
+            .map(|(path, entry)| (path, entry_to_json(&entry)))
+            .collect();
+        let value = json!({
+            "version": VERSION,
+            "key": self.key,
+            "dirs": dirs,
+            "files": files,
+        });
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temporary = target.with_extension(format!("{}.tmp", process::id()));
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(value.to_string().as_bytes())?;
+        drop(file);
+        fs::rename(&temporary, target)
+    }
+}
+
+// Names the files kept for a root after its canonical path, the same
+// tree can be reached by different paths
+// Several roots are separated by NUL, which can't be part of a path
+pub fn file_name<P: AsRef<Path>>(roots: &[P], extension: &str) -> String {
+    let roots: Vec<_> = roots
+        .iter()
+        .map(|root| {
+            let root = root.as_ref();
+            let root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+            root.to_string_lossy().into_owned()
+        })
+        .collect();
+    let hash = blake3::hash(roots.join("\0").as_bytes()).to_hex();
+    format!("{}.{}", &hash[..16], extension)
+}
+
+pub fn nanos(time: SystemTime) -> Option<u64> {
+    let since = time.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(since.as_nanos()).ok()
+}
+
+// Modification time in nanoseconds unless it's too recent to rely on
+pub fn trusted(modified: SystemTime) -> Option<u64> {
+    let now = SystemTime::now();
+    match now.duration_since(modified) {
+        Ok(age) if age >= RACY => nanos(modified),
+        _ => None,
+    }
+}
+
+fn kind_letter(kind: FileKind) -> &'static str {
+    match kind {
+        FileKind::File => "f",
+        FileKind::Directory => "d",
+        FileKind::Symlink => "l",
+        FileKind::Socket => "s",
+        FileKind::Pipe => "p",
+        FileKind::BlockDevice => "b",
+        FileKind::CharDevice => "c",
+    }
+}
+
+fn parse_kind(letter: &str) -> Option<FileKind> {
+    Some(match letter {
+        "f" => FileKind::File,
+        "d" => FileKind::Directory,
+        "l" => FileKind::Symlink,
+        "s" => FileKind::Socket,
+        "p" => FileKind::Pipe,
+        "b" => FileKind::BlockDevice,
+        "c" => FileKind::CharDevice,
+        _ => return None,
+    })
+}
+
+fn listing_to_json(listing: &Listing) -> Value {
+    let entries: Vec<Value> = listing
+        .entries
+        .iter()
+        .map(|(name, kind)| json!([name, kind_letter(*kind)]))
+        .collect();
+    json!({ "modified": listing.modified, "entries": entries })
+}
+
+fn parse_listing(value: &Value) -> Option<Listing> {
+    let entries = value["entries"]
+        .as_array()?
+        .iter()
+        .map(|entry| {
+            let name = entry[0].as_str()?;
+            Some((String::from(name), parse_kind(entry[1].as_str()?)?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(Listing {
+        modified: value["modified"].as_u64()?,
+        entries,
+    })
+}
+
+fn entry_to_json(entry: &Entry) -> Value {
+    let result = &entry.result;
+    let lines = result.lines.as_ref().map(|lines| {
+        lines
+            .iter()
+            .map(|l| json!([l.line, l.column, l.end, l.offset, l.content]))
+            .collect::<Vec<_>>()
+    });
+
+    json!({
+        "modified": entry.modified,
+        "len": entry.len,
+        "count": result.count,
+        "binary": result.binary,
+        "lines": lines,
+    })
+}
+
+fn parse_entry(path: &str, value: &Value) -> Option<Entry> {
+    let lines = match &value["lines"] {
+        Value::Null => None,
+        lines => Some(
+            lines
+                .as_array()?
+                .iter()
+                .map(|l| {
+                    Some(LineMatch {
+                        line: l[0].as_u64()? as usize,
+                        column: l[1].as_u64()? as usize,
+                        end: l[2].as_u64()? as usize,
+                        offset: l[3].as_u64()? as usize,
+                        content: String::from(l[4].as_str()?),
+                    })
+                })
+                .collect::<Option<Vec<_>>>()?,
+        ),
+    };
+
+    Some(Entry {
+        modified: value["modified"].as_u64()?,
+        len: value["len"].as_u64()?,
+        result: FileMatch {
+            path: PathBuf::from(path),
+            lines,
+            count: value["count"].as_u64()? as usize,
+            binary: value["binary"].as_bool()?,
+            name_matched: false,
+            digest: None,
+        },
+    })
+}
@@ -0,0 +1,689 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use regex::Regex;
+
+use crate::index;
+use crate::matcher::looks_like_regex;
+use crate::platform;
+use crate::settings;
+use crate::settings::Settings;
+use crate::{
+    Attribute, BinaryFiles, ColumnMode, Config, CountMode, Encoding, FileKind, HashAlgorithm,
+    IndexSummary, LogLevel, LongLines, NameMode, OutputFormat, SortBy, Strategy,
+};
+
+impl Config {
+    // Creates the configuration from the command line arguments
+    // Layers: defaults, config file, environment, command line
+    pub fn new(matches: &clap::ArgMatches) -> Result<Arc<Config>, Box<dyn Error>> {
+        let settings = if matches.is_present("no-config") {
+            Settings::default()
+        } else {
+            Settings::load()?
+        };
+        let settings = settings.with_env()?;
+
+        let fixed = matches.is_present("fixed-strings");
+        let regex = !fixed && (matches.is_present("regex") || settings.regex());
+        let quiet = matches.is_present("quiet");
+
+        let mut search = settings
+            .apply(Config::builder())
+            .ignore_filename_case(matches.is_present("ignore-filename-case"))
+            .smart_filename_case(matches.is_present("smart-case"))
+            .normalize_names(!matches.is_present("no-normalize"))
+            .smart_content_case(matches.is_present("smart-case"))
+            .ignore_content_case(matches.is_present("ignore-content-case"))
+            .regex(regex)
+            .word(matches.is_present("word-regexp"))
+            .line(matches.is_present("line-regexp"))
+            .invert_match(matches.is_present("invert-match"))
+            .multiline(matches.is_present("multiline"))
+            .byte_offset(matches.is_present("byte-offset"))
+            .only_matching(matches.is_present("only-matching"))
+            .files_without_match(matches.is_present("files-without-match"))
+            .files_with_matches(matches.is_present("files-with-matches"))
+            .archives(matches.is_present("archives"))
+            .search_zip(matches.is_present("search-zip"));
+
+        if matches.is_present("no-ignore") {
+            search = search.no_ignore(true);
+        }
+
+        for path in matches.values_of_os("ignore-file").into_iter().flatten() {
+            search = search.ignore_file(path);
+        }
+
+        if matches.is_present("hidden") {
+            search = search.hidden(true);
+        }
+
+        if matches.is_present("follow") {
+            search = search.follow(true);
+        }
+
+        if let Some(s) = matches.value_of("filename") {
+            let mode = if matches.is_present("glob") {
+                NameMode::Glob
+            } else if matches.is_present("name-regex") {
+                NameMode::Regex
+            } else {
+                NameMode::Literal
+            };
+            search = search.name(s, mode);
+        }
+
+        for pattern in matches.values_of("content").into_iter().flatten() {
+            // Regex mode from the config file shouldn't make a literal
+            // like `foo(` fail, it's searched as it is instead
+            if regex && !matches.is_present("regex") && Regex::new(pattern).is_err() {
+                if !quiet {
+                    eprintln!(
+                        "warning: {} isn't a valid regex and is searched literally",
+                        pattern
+                    );
+                }
+                search = search.content(&regex::escape(pattern));
+                continue;
+            }
+
+            if !regex && !fixed && !quiet && looks_like_regex(pattern) {
+                eprintln!(
+                    "warning: {} is searched literally, use -r to search a regex or -F to silence this",
+                    pattern
+                );
+            }
+            search = search.content(pattern);
+        }
+
+        for path in matches.values_of("pattern-file").into_iter().flatten() {
+            search = search.pattern_file(path);
+        }
+
+        search = search.all_patterns(matches.is_present("all"));
+        search = search.name_or_content(matches.is_present("name-or-content"));
+
+        if let Some(s) = matches.value_of("dop") {
+            search = search.dop(parse_threads(s)?);
+        }
+
+        if let Some(s) = matches.value_of("walk-threads") {
+            search = search.walk_threads(parse_threads(s)?);
+        }
+
+        if let Some(s) = matches.value_of("search-threads") {
+            search = search.search_threads(parse_threads(s)?);
+        }
+
+        // More `-v` log more, the command line wins over `LPS_LOG`
+        let verbosity = matches.occurrences_of("verbose");
+        if verbosity > 0 {
+            search = search.log_level(LogLevel::from_count(verbosity));
+        }
+
+        let positional = matches.values_of_os("root").into_iter().flatten();
+        for root in positional.chain(matches.values_of_os("roots").into_iter().flatten()) {
+            search = search.root(root);
+        }
+
+        if let Some(s) = matches.value_of("max-depth") {
+            let depth = match s.parse::<usize>() {
+                Ok(depth) => depth,
+                Err(_) => {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "invalid maximum depth",
+                    )));
+                }
+            };
+            search = search.max_depth(depth);
+        }
+
+        if let Some(text) = matches.value_of("replace") {
+            search = search.replace(
+                text,
+                matches.is_present("dry-run"),
+                matches.is_present("backup"),
+            );
+        }
+
+        if let Some(s) = matches.value_of("file-timeout") {
+            search = search.file_timeout(parse_timeout(s)?);
+        }
+
+        if let Some(s) = matches.value_of("max-count") {
+            search = search.max_count(parse_count(s)?);
+        }
+
+        match matches.value_of("column-mode") {
+            Some("bytes") => search = search.column_mode(ColumnMode::Bytes),
+            Some("graphemes") => search = search.column_mode(ColumnMode::Graphemes),
+            Some(_) => search = search.column_mode(ColumnMode::Chars),
+            None => {}
+        }
+
+        if let Some(s) = matches.value_of("tab-width") {
+            match s.parse::<usize>() {
+                Ok(width) if width > 0 => search = search.tab_width(width),
+                _ => {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid tab width: {}", s),
+                    )))
+                }
+            }
+        }
+
+        if let Some(s) = matches.value_of("max-columns") {
+            match s.parse::<usize>() {
+                Ok(max) if max > 0 => search = search.max_columns(max),
+                _ => {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid number of columns: {}", s),
+                    )))
+                }
+            }
+        }
+
+        if let Some(s) = matches.value_of("memory-limit") {
+            search = search.memory_limit(parse_size(s)?);
+        }
+
+        if let Some(s) = matches.value_of("max-line-length") {
+            let long_lines = match matches.value_of("long-lines") {
+                Some("skip") => LongLines::Skip,
+                _ => LongLines::Truncate,
+            };
+            search = search.max_line_length(parse_size(s)? as usize, long_lines);
+        }
+
+        if let Some(s) = matches.value_of("max-total") {
+            search = search.max_total(parse_count(s)?);
+        }
+
+        if let Some(s) = matches.value_of("min-size") {
+            search = search.min_size(parse_size(s)?);
+        }
+
+        if let Some(s) = matches.value_of("max-size") {
+            search = search.max_size(parse_size(s)?);
+        }
+
+        if let Some(spec) = matches.value_of("perm") {
+            search = search.perm(spec);
+        }
+
+        if let Some(user) = matches.value_of("owner") {
+            search = search.owner(user);
+        }
+
+        if let Some(group) = matches.value_of("group") {
+            search = search.group(group);
+        }
+
+        if let Some(s) = matches.value_of("newer-than") {
+            search = search.newer_than(parse_time(s)?);
+        }
+
+        if let Some(s) = matches.value_of("older-than") {
+            search = search.older_than(parse_time(s)?);
+        }
+
+        for name in matches.values_of("type").into_iter().flatten() {
+            search = search.file_type(name);
+        }
+
+        for definition in matches.values_of("type-add").into_iter().flatten() {
+            search = search.type_add(definition);
+        }
+
+        for name in matches.values_of("attrib").into_iter().flatten() {
+            let (name, set) = match name.strip_prefix('-') {
+                Some(name) => (name, false),
+                None => (name, true),
+            };
+            let attribute = match name {
+                "hidden" => Attribute::Hidden,
+                "system" => Attribute::System,
+                "readonly" => Attribute::ReadOnly,
+                "archive" => Attribute::Archive,
+                _ => {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid attribute: {}", name),
+                    )))
+                }
+            };
+            search = search.attribute(attribute, set);
+        }
+
+        if matches.is_present("allow-special") {
+            search = search.allow_special(true);
+        }
+
+        if matches.is_present("one-file-system") {
+            search = search.one_file_system(true);
+        }
+
+        match matches.value_of("strategy") {
+            Some("bfs") => search = search.strategy(Strategy::BreadthFirst),
+            Some(_) => search = search.strategy(Strategy::DepthFirst),
+            None => {}
+        }
+
+        for kind in matches.values_of("kind").into_iter().flatten() {
+            search = search.kind(match kind {
+                "d" => FileKind::Directory,
+                "l" => FileKind::Symlink,
+                "s" => FileKind::Socket,
+                "p" => FileKind::Pipe,
+                "b" => FileKind::BlockDevice,
+                "c" => FileKind::CharDevice,
+                _ => FileKind::File,
+            });
+        }
+
+        if matches.is_present("empty") {
+            search = search.empty(true);
+        }
+
+        for pattern in matches.values_of("exclude").into_iter().flatten() {
+            search = search.exclude(pattern);
+        }
+
+        for pattern in matches.values_of("exclude-dir").into_iter().flatten() {
+            search = search.exclude_dir(pattern);
+        }
+
+        if matches.is_present("binary") {
+            search = search.binary_files(BinaryFiles::Text);
+        }
+
+        match matches.value_of("binary-files") {
+            Some("text") => search = search.binary_files(BinaryFiles::Text),
+            Some("list") => search = search.binary_files(BinaryFiles::List),
+            Some(_) => search = search.binary_files(BinaryFiles::Skip),
+            None => {}
+        }
+
+        if matches.is_present("count-matches") {
+            search = search.count(CountMode::Matches);
+        } else if matches.is_present("count") {
+            search = search.count(CountMode::Lines);
+        }
+
+        match matches.value_of("encoding") {
+            Some("utf-8") => search = search.encoding(Encoding::Utf8),
+            Some("utf-16le") => search = search.encoding(Encoding::Utf16Le),
+            Some("utf-16be") => search = search.encoding(Encoding::Utf16Be),
+            Some("latin1") => search = search.encoding(Encoding::Latin1),
+            _ => {}
+        }
+
+        if let Some(list) = matches.value_of_os("files-from") {
+            search = search.files_from(list);
+        }
+
+        // Like `cat log | lps -c error`, only if nothing else to search is
+        // given and nothing needs the files themselves
+        let rootless = ["root", "roots", "files-from"]
+            .iter()
+            .all(|a| !matches.is_present(a));
+        let content = matches.is_present("content") || matches.is_present("pattern-file");
+        let needs_files = [
+            "serve",
+            "watch",
+            "interactive",
+            "cat",
+            "exec",
+            "exec-batch",
+            "replace",
+            "why",
+        ]
+        .iter()
+        .any(|a| matches.is_present(a));
+        if rootless && content && !needs_files && platform::stdin_is_piped() {
+            search = search.stdin(true);
+        }
+
+        match matches.value_of("sort") {
+            Some("path") => search = search.sort(SortBy::Path),
+            Some("mtime") => search = search.sort(SortBy::Modified),
+            Some("size") => search = search.sort(SortBy::Size),
+            Some(_) => search = search.sort(SortBy::None),
+            None => {}
+        }
+
+        if matches.is_present("group-by-dir") {
+            search = search.group_by_dir(true);
+        }
+
+        if matches.is_present("quiet") {
+            search = search.quiet(true);
+        }
+
+        if matches.is_present("stats") {
+            search = search.stats(true);
+        }
+
+        if matches.is_present("pattern-stats") {
+            search = search.pattern_stats(true);
+        }
+
+        if matches.is_present("profile") {
+            search = search.profile(true);
+        }
+
+        if matches.is_present("cache") {
+            search = search.cache(cache_dir()?, &cache_key(matches, &settings));
+        }
+
+        if matches.is_present("index") {
+            search = search.index(cache_dir()?);
+        }
+
+        match matches.value_of("hash") {
+            Some("sha256") => search = search.hash(HashAlgorithm::Sha256),
+            Some("md5") => search = search.hash(HashAlgorithm::Md5),
+            Some(_) => search = search.hash(HashAlgorithm::Blake3),
+            None => {}
+        }
+
+        if let Some(command) = matches.value_of("exec") {
+            search = search.exec(command);
+        }
+
+        if let Some(command) = matches.value_of("exec-batch") {
+            search = search.exec_batch(command);
+        }
+
+        if matches.is_present("cat") {
+            search = search.cat(true, matches.is_present("header"));
+        }
+
+        if matches.is_present("duplicates") {
+            search = search.duplicates(true);
+        }
+
+        if matches.is_present("watch") {
+            search = search.watch(true);
+        }
+
+        if matches.is_present("interactive") {
+            search = search.interactive(true);
+        }
+
+        if matches.is_present("progress") {
+            search = search.progress(true);
+        }
+
+        if matches.is_present("json") {
+            search = search.format(OutputFormat::Json);
+        }
+
+        if let Some(template) = matches.value_of("format-template") {
+            search = search.format_template(template);
+        }
+
+        match matches.value_of("format") {
+            Some("json") => search = search.format(OutputFormat::Json),
+            Some("csv") => search = search.format(OutputFormat::Csv),
+            Some("tsv") => search = search.format(OutputFormat::Tsv),
+            Some(_) => search = search.format(OutputFormat::Text),
+            None => {}
+        }
+
+        // Only file names can be printed NUL-separated, so content
+        // search lists matching files
+        if matches.is_present("null") {
+            search = search.format(OutputFormat::Null);
+            if !matches.is_present("files-without-match") {
+                search = search.files_with_matches(true);
+            }
+        }
+
+        if let Some(when) = matches.value_of("color") {
+            search = search.color(use_color(Some(when)));
+        }
+
+        // Headings are for reading, piped output is easier to process
+        // with the file on every line
+        let heading = if matches.is_present("no-heading") {
+            false
+        } else {
+            matches.is_present("heading") || io::stdout().is_terminal()
+        };
+        search = search.heading(heading);
+
+        if matches.is_present("absolute-path") {
+            search = search.absolute_path(true);
+        }
+        if let Some(dir) = matches.value_of_os("relative-to") {
+            search = search.relative_to(dir);
+        }
+
+        if matches.is_present("hyperlink") {
+            search = search.hyperlink(true);
+        }
+
+        search.build()
+    }
+}
+
+// Indexes the files a search with the arguments would find, in the
+// directory of `--cache`, for `lps index build`
+pub fn build_index(matches: &clap::ArgMatches) -> Result<IndexSummary, Box<dyn Error>> {
+    if matches.is_present("patterns") || matches.is_present("index") {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the index is built from all files, patterns are given to lps index query",
+        )));
+    }
+
+    let config = Config::new(matches)?;
+    index::build(config, &cache_dir()?)
+}
+
+fn cache_dir() -> Result<PathBuf, Box<dyn Error>> {
+    match settings::cache_dir() {
+        Some(dir) => Ok(dir),
+        None => Err(Box::new(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no cache directory, set XDG_CACHE_HOME or HOME",
+        ))),
+    }
+}
+
+// Resolves `auto`, `always` or `never`, defaults to auto
+pub fn use_color(when: Option<&str>) -> bool {
+    match when {
+        Some("always") => true,
+        Some("never") => false,
+        // Only color if stdout is a terminal, so piping stays plain
+        _ => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+    }
+}
+
+fn parse_timeout(s: &str) -> Result<Duration, Box<dyn Error>> {
+    match s.parse::<f64>() {
+        Ok(secs) if secs > 0.0 && secs.is_finite() => Ok(Duration::from_secs_f64(secs)),
+        _ => Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid timeout: {}", s),
+        ))),
+    }
+}
+
+fn parse_threads(s: &str) -> Result<usize, Box<dyn Error>> {
+    s.parse::<usize>().map_err(|_| -> Box<dyn Error> {
+        Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid degree of parallelism",
+        ))
+    })
+}
+
+fn parse_count(s: &str) -> Result<usize, Box<dyn Error>> {
+    match s.parse::<usize>() {
+        Ok(count) => Ok(count),
+        Err(_) => Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid number of matches: {}", s),
+        ))),
+    }
+}
+
+// Parses human-readable sizes like `512`, `10K`, `5M` or `1GiB`
+// Suffixes are binary multiples and case-insensitive
+fn parse_size(s: &str) -> Result<u64, Box<dyn Error>> {
+    let upper = s.trim().to_uppercase();
+    let number = upper.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let suffix = &upper[number.len()..];
+
+    let multiplier: u64 = match suffix {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid size: {}", s),
+            )));
+        }
+    };
+
+    match number.parse::<u64>() {
+        Ok(n) if n.checked_mul(multiplier).is_some() => Ok(n * multiplier),
+        _ => Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid size: {}", s),
+        ))),
+    }
+}
+
+// Parses either a duration before now like `2d` or `1h30m`,
+// or a UTC timestamp like `2019-05-20` or `2019-05-20 14:30:00`
+fn parse_time(s: &str) -> Result<SystemTime, Box<dyn Error>> {
+    let invalid = || -> Box<dyn Error> {
+        Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid time: {}", s),
+        ))
+    };
+
+    if let Some(duration) = parse_duration(s) {
+        return SystemTime::now().checked_sub(duration).ok_or_else(invalid);
+    }
+
+    let s = s.trim();
+    let (date, time) = match s.find([' ', 'T']) {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, "00:00:00"),
+    };
+
+    let date: Vec<u64> = date.split('-').filter_map(|p| p.parse().ok()).collect();
+    let time: Vec<u64> = time.split(':').filter_map(|p| p.parse().ok()).collect();
+    if date.len() != 3 || time.len() < 2 || time.len() > 3 {
+        return Err(invalid());
+    }
+
+    let (year, month, day) = (date[0], date[1], date[2]);
+    let (hour, minute, second) = (time[0], time[1], *time.get(2).unwrap_or(&0));
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    if hour > 23 || minute > 59 || second > 59 {
+        return Err(invalid());
+    }
+
+    let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    Ok(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+// Parses durations like `30s`, `15m`, `3h`, `2d`, `1w` or combinations like `1d12h`
+fn parse_duration(s: &str) -> Option<Duration> {
+    let mut seconds = 0u64;
+    let mut number = String::new();
+
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 7 * 86400,
+            _ => return None,
+        };
+
+        let n: u64 = number.parse().ok()?;
+        seconds = seconds.checked_add(n.checked_mul(unit)?)?;
+        number.clear();
+    }
+
+    // A trailing number without unit is not a duration
+    if number.is_empty() && seconds > 0 {
+        Some(Duration::from_secs(seconds))
+    } else {
+        None
+    }
+}
+
+// Days since the unix epoch of a proleptic Gregorian date
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month = (month + 9) % 12;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// Identifies everything results can depend on: the arguments, the
+// environment, the config file and the content of pattern files
+fn cache_key(matches: &clap::ArgMatches, settings: &Settings) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for arg in env::args_os().skip(1) {
+        hasher.update(arg.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+    }
+
+    let mut vars: Vec<_> = env::vars_os()
+        .filter(|(name, _)| name.to_string_lossy().starts_with("LPS_"))
+        .collect();
+    vars.sort();
+    for (name, value) in vars {
+        hasher.update(name.to_string_lossy().as_bytes());
+        hasher.update(b"=");
+        hasher.update(value.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+    }
+
+    hasher.update(settings.source().as_bytes());
+    hasher.update(b"\0");
+
+    // Pattern files can change between runs with the same arguments
+    for path in matches.values_of_os("pattern-file").into_iter().flatten() {
+        if let Ok(data) = fs::read(path) {
+            hasher.update(&data);
+        }
+        hasher.update(b"\0");
+    }
+
+    hasher.finalize().to_hex().to_string()
+}
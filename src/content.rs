@@ -0,0 +1,837 @@
+use std::fs::{File, Metadata};
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use memmap2::Mmap;
+
+use crate::archive;
+use crate::decompress;
+use crate::encoding::Decoder;
+use crate::log;
+use crate::log::Level;
+use crate::matcher::ContentMatcher;
+use crate::memory;
+use crate::multiline;
+use crate::profile::Phase;
+use crate::progress::PatternStats;
+use crate::{Config, Context, FileMatch, LineMatch};
+
+// Name stdin is reported by
+pub const STDIN: &str = "<stdin>";
+
+// Size of the block inspected for NUL bytes
+const BINARY_DETECTION_BLOCK: usize = 8 * 1024;
+
+// Files of at least this size are memory-mapped instead of read line by line
+const MMAP_THRESHOLD: u64 = 8 * 1024 * 1024;
+
+// What is counted in count mode
+pub enum CountMode {
+    // Number of matching lines
+    Lines,
+    // Total number of matches, a line can match multiple times
+    Matches,
+}
+
+// Handling of files detected as binary
+#[derive(PartialEq)]
+pub enum BinaryFiles {
+    // Don't search binary files
+    Skip,
+    // Search binary files like text files
+    Text,
+    // Only report whether a binary file matches
+    List,
+}
+
+// Handling of lines longer than `max_line_length`
+pub enum LongLines {
+    // Only the start of the line is searched and reported
+    Truncate,
+    // The line isn't searched at all
+    Skip,
+}
+
+// Files are shared through a queue fed by the walker, so idle workers pick
+// up the next file instead of waiting on a thread stuck with a large one
+// With `file_timeout` a supervisor replaces workers stuck on a single file,
+// the stuck thread is left behind and its results are dropped
+// Every file is sent as a single result once it's searched completely, so
+// its lines are printed together however many workers there are
+pub fn content_search(
+    config: &Arc<Config>,
+    files: mpsc::Receiver<PathBuf>,
+    sender: mpsc::SyncSender<FileMatch>,
+    context: Context,
+) -> Vec<thread::JoinHandle<()>> {
+    assert!(config.content.is_some());
+
+    let worker = Worker {
+        config: config.clone(),
+        context,
+        queue: Arc::new(Mutex::new(files)),
+    };
+
+    match config.file_timeout {
+        Some(timeout) => vec![log::spawn(String::from("supervise"), move || {
+            supervise(worker, sender, timeout)
+        })],
+        None => (0..config.search_threads)
+            .map(|i| worker.spawn(&sender, i).1)
+            .collect(),
+    }
+}
+
+// Everything a worker thread needs besides its slot, cloned for every thread
+#[derive(Clone)]
+struct Worker {
+    config: Arc<Config>,
+    context: Context,
+    queue: Arc<Mutex<mpsc::Receiver<PathBuf>>>,
+}
+
+// State of a running worker shared with the supervisor
+struct Slot {
+    // Taken away when the worker is abandoned, so it can't send any more
+    // results and doesn't keep the channel open
+    sender: Mutex<Option<mpsc::SyncSender<FileMatch>>>,
+    // File being searched and since when
+    current: Mutex<Option<(PathBuf, Instant)>>,
+}
+
+impl Slot {
+    fn send(&self, result: FileMatch) -> bool {
+        match &*self.sender.lock().unwrap() {
+            Some(sender) => sender.send(result).is_ok(),
+            None => false,
+        }
+    }
+
+    // Waiting for the consumer doesn't count towards the timeout
+    fn wait<T>(&self, wait: impl FnOnce() -> T) -> T {
+        let paused = self.current.lock().unwrap().take();
+        let waiting = Instant::now();
+        let result = wait();
+        if let Some((path, started)) = paused {
+            *self.current.lock().unwrap() = Some((path, started + waiting.elapsed()));
+        }
+        result
+    }
+}
+
+impl Worker {
+    // Workers are numbered for the log, replacements get new numbers
+    fn spawn(
+        &self,
+        sender: &mpsc::SyncSender<FileMatch>,
+        number: usize,
+    ) -> (Arc<Slot>, thread::JoinHandle<()>) {
+        let slot = Arc::new(Slot {
+            sender: Mutex::new(Some(sender.clone())),
+            current: Mutex::new(None),
+        });
+
+        let worker = self.clone();
+        let thread_slot = slot.clone();
+        let handle = log::spawn(format!("search-{}", number), move || {
+            worker.run(&thread_slot)
+        });
+        (slot, handle)
+    }
+
+    fn run(&self, slot: &Slot) {
+        let config = &self.config;
+        let context = &self.context;
+        let mut busy = Duration::from_secs(0);
+
+        loop {
+            // Release the lock before searching
+            // recv fails when the walker has finished
+            let path = match self.queue.lock().unwrap().recv() {
+                Ok(p) => p,
+                Err(_) => break,
+            };
+            if let Some(memory) = &context.memory {
+                memory.paths.release(memory::path_size(&path));
+            }
+
+            if context.cancel.is_cancelled() {
+                break;
+            }
+            config
+                .log
+                .write(Level::Trace, || format!("searching {}", path.display()));
+
+            let name_matched = config.name_or_content
+                && config
+                    .filename
+                    .as_ref()
+                    .is_some_and(|m| m.is_match(&path, config.root_of(&path)));
+            // Directories and special files are only reported by name,
+            // reading a pipe could block forever
+            if !config.kinds.is_empty() && !path.is_file() {
+                context.progress.add_file();
+                if !slot.send(name_only(config, path)) {
+                    break;
+                }
+                continue;
+            }
+
+            let mut reported = false;
+            let metadata = context.cache.as_ref().and_then(|_| path.metadata().ok());
+            let cached = match (&context.cache, &metadata) {
+                // Only searching tells which patterns matched
+                (Some(cache), Some(metadata)) if !context.patterns.enabled() => {
+                    cache.result(&path, metadata)
+                }
+                _ => None,
+            };
+            let hit = cached.is_some();
+            let skipped = config
+                .index
+                .as_ref()
+                .is_some_and(|index| index.skips(&config.roots[0], &path));
+            let mut searched = None;
+
+            let found = &mut |mut result: FileMatch| {
+                // Entries of archives have paths of their own and aren't cached
+                if metadata.is_some() && !hit && result.path == path {
+                    searched = Some(result.clone());
+                }
+                if name_matched && result.path == path {
+                    result.name_matched = true;
+                    reported = true;
+                }
+                context.progress.add_matches(result.count);
+                slot.wait(|| {
+                    // Waits while too many results haven't been taken yet
+                    if let Some(memory) = &context.memory {
+                        memory
+                            .data
+                            .acquire(memory::result_size(&result), &context.cancel);
+                    }
+                    slot.send(result)
+                })
+            };
+            context.progress.add_file();
+            let started = Instant::now();
+            let more = match cached {
+                Some(result) => {
+                    config.log.write(Level::Debug, || {
+                        format!("{}: result of the last run", path.display())
+                    });
+                    found(result)
+                }
+                // Still reported by files without match
+                None if skipped => {
+                    config.log.write(Level::Debug, || {
+                        format!("{}: skipped by the index", path.display())
+                    });
+                    found(unmatched(config, path.clone()))
+                }
+                None => {
+                    *slot.current.lock().unwrap() = Some((path.clone(), started));
+                    let more = search_path(config, context, path.clone(), found);
+                    *slot.current.lock().unwrap() = None;
+                    more
+                }
+            };
+            busy += started.elapsed();
+            config.log.write(Level::Trace, || {
+                format!("{}: done in {:?}", path.display(), started.elapsed())
+            });
+
+            // Results of files cut short aren't complete
+            if let (Some(cache), Some(metadata), Some(result)) =
+                (&context.cache, &metadata, &searched)
+            {
+                if !context.cancel.is_cancelled() {
+                    cache.record_result(&path, metadata, result);
+                }
+            }
+            if !more {
+                break;
+            }
+
+            // Binary and unreadable files still match by name
+            if name_matched && !reported && !slot.send(name_only(config, path)) {
+                break;
+            }
+        }
+
+        context.progress.search_finished(context.started.elapsed());
+        context.profile.add_thread("search", busy);
+        config.log.write(Level::Trace, || String::from("finished"));
+    }
+}
+
+// Runs the workers and replaces those stuck on a file for longer than
+// `timeout`, the file is reported as an error
+// Finishes once all workers which weren't abandoned have finished
+fn supervise(worker: Worker, sender: mpsc::SyncSender<FileMatch>, timeout: Duration) {
+    let mut spawned = worker.config.search_threads;
+    let mut running: Vec<_> = (0..spawned).map(|i| worker.spawn(&sender, i)).collect();
+
+    while !running.is_empty() {
+        thread::sleep(timeout.min(Duration::from_millis(50)));
+
+        let mut abandoned = 0;
+        running.retain(|(slot, handle)| {
+            if handle.is_finished() {
+                return false;
+            }
+
+            let current = slot.current.lock().unwrap();
+            match &*current {
+                Some((path, started)) if started.elapsed() >= timeout => {
+                    let error = io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("search timed out after {:?}", timeout),
+                    );
+                    worker.context.errors.send(path, error, true);
+                    slot.sender.lock().unwrap().take();
+                    worker.config.log.write(Level::Info, || {
+                        format!("replacing the worker stuck on {}", path.display())
+                    });
+                    abandoned += 1;
+                    false
+                }
+                _ => true,
+            }
+        });
+
+        if !worker.context.cancel.is_cancelled() {
+            running.extend((spawned..spawned + abandoned).map(|i| worker.spawn(&sender, i)));
+            spawned += abandoned;
+        }
+    }
+
+    // Abandoned workers keep the queue open, the walker mustn't wait for
+    // room in it after a cancellation
+    drop(sender);
+    worker.queue.lock().unwrap().iter().for_each(drop);
+}
+
+// Result for a file that matched by name but not by content
+fn name_only(config: &Config, path: PathBuf) -> FileMatch {
+    FileMatch {
+        name_matched: true,
+        ..unmatched(config, path)
+    }
+}
+
+fn unmatched(config: &Config, path: PathBuf) -> FileMatch {
+    let keep_lines = config.count.is_none() && !config.lists_files();
+    FileMatch {
+        path,
+        lines: if keep_lines { Some(Vec::new()) } else { None },
+        count: 0,
+        binary: false,
+        name_matched: false,
+        digest: None,
+    }
+}
+
+// Searches stdin on a thread of its own instead of the files of a walk
+pub fn stdin_search(
+    config: &Arc<Config>,
+    sender: mpsc::SyncSender<FileMatch>,
+    context: Context,
+) -> thread::JoinHandle<()> {
+    let config = config.clone();
+    log::spawn(String::from("stdin"), move || {
+        context.progress.walk_finished(context.started.elapsed());
+        context.progress.add_file();
+        let stdin = Counted {
+            inner: io::stdin().lock(),
+            context: &context,
+        };
+        let path = PathBuf::from(STDIN);
+        if let Some(result) = search_reader(&config, &context, path, stdin) {
+            context.progress.add_matches(result.count);
+            let _ = sender.send(result);
+        }
+        context.progress.search_finished(context.started.elapsed());
+    })
+}
+
+// Adds the bytes read to the progress, the length of stdin isn't known
+struct Counted<'a, R> {
+    inner: R,
+    context: &'a Context,
+}
+
+impl<R: Read> Read for Counted<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.context.progress.add_bytes(read as u64);
+        Ok(read)
+    }
+}
+
+// Searches a file or every entry of an archive and passes the results to `found`
+// Returns `false` if `found` doesn't accept any more results
+fn search_path<F: FnMut(FileMatch) -> bool>(
+    config: &Config,
+    context: &Context,
+    path: PathBuf,
+    found: &mut F,
+) -> bool {
+    if config.archives {
+        if let Some(kind) = archive::kind(&path) {
+            return archive::search(config, context, &path, kind, found);
+        }
+    }
+
+    match search_file(config, context, path) {
+        Some(result) => found(result),
+        None => true,
+    }
+}
+
+// Opening is timed apart from searching for `--profile`
+fn search_file(config: &Config, context: &Context, path: PathBuf) -> Option<FileMatch> {
+    let started = context.profile.start();
+    let opened = File::open(&path).and_then(|f| f.metadata().map(|m| (f, m)));
+    context.profile.add(Phase::Open, started);
+
+    let started = context.profile.start();
+    let result = search_opened(config, context, path, opened);
+    context.profile.add(Phase::Match, started);
+    result
+}
+
+fn search_opened(
+    config: &Config,
+    context: &Context,
+    path: PathBuf,
+    opened: io::Result<(File, Metadata)>,
+) -> Option<FileMatch> {
+    let (mut file, len) = match opened {
+        // Symlinks to directories are listed like files when not followed,
+        // but have no content to search
+        Ok((_, m)) if m.is_dir() => return None,
+        Ok((f, m)) => (f, m.len()),
+        Err(err) => {
+            context.errors.skip(&path, err);
+            return None;
+        }
+    };
+    context.progress.add_bytes(len);
+
+    if config.search_zip {
+        if let Some(compression) = decompress::detect(&path, &mut file) {
+            let decoder = match decompress::decoder(compression, file) {
+                Ok(d) => d,
+                Err(err) => {
+                    context.errors.skip(&path, err);
+                    return None;
+                }
+            };
+            return search_reader(config, context, path, decoder);
+        }
+    }
+
+    // Inverted matches need every line, scanning for hits doesn't help
+    if len >= MMAP_THRESHOLD && !config.invert_match {
+        // Safety: the file could be truncated or modified while mapped
+        // which is accepted in exchange for not copying large files
+        if let Ok(map) = unsafe { Mmap::map(&file) } {
+            // Other encodings have to be converted to UTF-8 first
+            if config.encoding.is_utf8(&map[..map.len().min(2)]) {
+                if config.multiline {
+                    let binary = is_binary(config, &map);
+                    if binary && config.binary_files == BinaryFiles::Skip {
+                        context.progress.add_binary();
+                        config
+                            .log
+                            .write(Level::Debug, || format!("{}: binary", path.display()));
+                        return None;
+                    }
+                    return Some(multiline::search(config, context, path, &map, binary));
+                }
+
+                return search_buffer(config, context, path, &map);
+            }
+        }
+    }
+
+    // Literals are found by SIMD substring search over the raw bytes,
+    // which is a lot faster than scanning every line on its own
+    let literal = config.content.as_ref().is_some_and(|m| m.is_literal());
+    // Files that don't fit into the memory limit at once are read in blocks
+    let fits = context
+        .memory
+        .as_ref()
+        .is_none_or(|m| len <= m.data.limit());
+    if literal && !config.invert_match && !config.multiline && fits {
+        let _held = context
+            .memory
+            .as_ref()
+            .map(|m| m.data.hold(len, &context.cancel));
+        let mut data = Vec::with_capacity(len as usize);
+        let read = context
+            .profile
+            .time(Phase::Read, || file.read_to_end(&mut data));
+        if let Err(err) = read {
+            context.errors.skip(&path, err);
+            return None;
+        }
+
+        if config.encoding.is_utf8(&data[..data.len().min(2)]) {
+            return search_buffer(config, context, path, &data);
+        }
+        return search_reader(config, context, path, &data[..]);
+    }
+
+    search_reader(config, context, path, context.profile.reader(file))
+}
+
+// Searches any readable source line by line, `path` is only used for reporting
+pub fn search_reader<R: Read>(
+    config: &Config,
+    context: &Context,
+    path: PathBuf,
+    source: R,
+) -> Option<FileMatch> {
+    let source = Decoder::new(source, config.encoding);
+    let mut reader = BufReader::with_capacity(BINARY_DETECTION_BLOCK, source);
+
+    let binary = match reader.fill_buf() {
+        Ok(block) => is_binary(config, block),
+        Err(err) => {
+            context.errors.skip(&path, err);
+            return None;
+        }
+    };
+
+    if config.multiline {
+        if binary && config.binary_files == BinaryFiles::Skip {
+            context.progress.add_binary();
+            config
+                .log
+                .write(Level::Debug, || format!("{}: binary", path.display()));
+            return None;
+        }
+
+        let mut data = Vec::new();
+        if let Err(err) = reader.read_to_end(&mut data) {
+            context.errors.skip(&path, err);
+            return None;
+        }
+        return Some(multiline::search(config, context, path, &data, binary));
+    }
+
+    let mut search = match LineSearch::new(config, context, binary) {
+        Some(s) => s,
+        None => {
+            context.progress.add_binary();
+            config
+                .log
+                .write(Level::Debug, || format!("{}: binary", path.display()));
+            return None;
+        }
+    };
+
+    // One more byte tells apart lines that are too long, the rest
+    // of a long line isn't kept in memory
+    let line_limit = config.max_line_length.map_or(usize::MAX, |max| max + 1);
+    let mut buffer = Vec::new();
+    let mut offset = 0;
+    for i in 0.. {
+        if context.cancel.is_cancelled() {
+            break;
+        }
+
+        buffer.clear();
+        let read = match read_line(&mut reader, &mut buffer, line_limit) {
+            Ok(0) => break,
+            Ok(n) => n,
+            // Matches found so far are still reported
+            Err(err) => {
+                context.errors.skip(&path, err);
+                break;
+            }
+        };
+
+        if buffer.ends_with(b"\n") {
+            buffer.pop();
+        }
+
+        if !search.line(i + 1, offset, &buffer) {
+            break;
+        }
+
+        offset += read;
+    }
+
+    Some(search.finish(path))
+}
+
+// Like `read_until(b'\n')`, but only keeps the first `limit` bytes of a line
+// and consumes the rest without storing it
+fn read_line<R: BufRead>(reader: &mut R, buffer: &mut Vec<u8>, limit: usize) -> io::Result<usize> {
+    let mut read = 0;
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(a) => a,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if available.is_empty() {
+            return Ok(read);
+        }
+
+        let (done, used) = match memchr::memchr(b'\n', available) {
+            Some(i) => (true, i + 1),
+            None => (false, available.len()),
+        };
+        let keep = used.min(limit.saturating_sub(buffer.len()));
+        buffer.extend_from_slice(&available[..keep]);
+        reader.consume(used);
+        read += used;
+
+        if done {
+            return Ok(read);
+        }
+    }
+}
+
+// Searches a whole file at once, only lines containing a match
+// are decoded and line numbers are counted lazily
+fn search_buffer(
+    config: &Config,
+    context: &Context,
+    path: PathBuf,
+    data: &[u8],
+) -> Option<FileMatch> {
+    let binary = is_binary(config, data);
+    let mut search = match LineSearch::new(config, context, binary) {
+        Some(s) => s,
+        None => {
+            context.progress.add_binary();
+            config
+                .log
+                .write(Level::Debug, || format!("{}: binary", path.display()));
+            return None;
+        }
+    };
+
+    // The byte order mark isn't part of the first line
+    let bom = if data.starts_with(&[0xef, 0xbb, 0xbf]) {
+        3
+    } else {
+        0
+    };
+
+    let mut line_number = 1;
+    let mut counted = bom;
+    let mut pos = bom;
+    while pos < data.len() && !context.cancel.is_cancelled() {
+        let hit = match search.matcher.find_bytes(&data[pos..]) {
+            Some(h) => pos + h,
+            None => break,
+        };
+
+        let start = memchr::memrchr(b'\n', &data[pos..hit]).map_or(pos, |i| pos + i + 1);
+        let end = memchr::memchr(b'\n', &data[hit..]).map_or(data.len(), |i| hit + i);
+
+        line_number += memchr::memchr_iter(b'\n', &data[counted..start]).count();
+        counted = start;
+
+        if !search.line(line_number, start, &data[start..end]) {
+            break;
+        }
+
+        pos = end + 1;
+    }
+
+    Some(search.finish(path))
+}
+
+// Checks the start of a file for NUL bytes
+fn is_binary(config: &Config, data: &[u8]) -> bool {
+    match config.binary_files {
+        BinaryFiles::Text => false,
+        _ => data[..data.len().min(BINARY_DETECTION_BLOCK)].contains(&0),
+    }
+}
+
+// Matches single lines and collects the results of a file
+struct LineSearch<'a> {
+    config: &'a Config,
+    matcher: &'a ContentMatcher,
+    binary: bool,
+    lines: Vec<LineMatch>,
+    count: usize,
+    // Matching lines, `count` can also be the number of matches
+    matched_lines: usize,
+    // Patterns seen so far if all of them are required
+    matched: Vec<bool>,
+    patterns: &'a PatternStats,
+    // Lines matched by every pattern for `--pattern-stats`
+    pattern_lines: Vec<u64>,
+}
+
+impl<'a> LineSearch<'a> {
+    // Returns `None` if the file shouldn't be searched at all
+    fn new(config: &'a Config, context: &'a Context, binary: bool) -> Option<LineSearch<'a>> {
+        if binary {
+            if let BinaryFiles::Skip = config.binary_files {
+                return None;
+            }
+        }
+
+        let matcher = config.content.as_ref().unwrap();
+        let matched = if matcher.requires_all() {
+            vec![false; matcher.len()]
+        } else {
+            Vec::new()
+        };
+
+        let pattern_lines = if context.patterns.enabled() {
+            vec![0; matcher.len()]
+        } else {
+            Vec::new()
+        };
+
+        Some(LineSearch {
+            config,
+            matcher,
+            binary,
+            lines: Vec::new(),
+            count: 0,
+            matched_lines: 0,
+            matched,
+            patterns: &context.patterns,
+            pattern_lines,
+        })
+    }
+
+    // Searches a line without its `\n` terminator at byte `offset` of the file
+    // Returns `false` if the rest of the file doesn't need to be searched
+    fn line(&mut self, number: usize, offset: usize, bytes: &[u8]) -> bool {
+        // Strip line terminator like BufRead::lines does
+        let mut bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+
+        if let Some(max) = self.config.max_line_length {
+            if bytes.len() > max {
+                match self.config.long_lines {
+                    LongLines::Skip => return true,
+                    // Don't cut a character in half
+                    LongLines::Truncate => {
+                        let mut end = max;
+                        while end > 0 && bytes[end] & 0b1100_0000 == 0b1000_0000 {
+                            end -= 1;
+                        }
+                        bytes = &bytes[..end];
+                    }
+                }
+            }
+        }
+
+        // Invalid sequences are replaced instead of skipping the line
+        let line = String::from_utf8_lossy(bytes);
+
+        // Inverted matches report the whole line with an empty span
+        let (start, end) = match (self.matcher.find(&line), self.config.invert_match) {
+            (Some(span), false) => span,
+            (None, true) => (0, 0),
+            _ => return true,
+        };
+
+        if !self.matched.is_empty() {
+            self.matcher.mark_matched(&line, &mut self.matched);
+        }
+
+        // Past the limit lines are only searched for missing patterns
+        if self.limit_reached() {
+            return !self.all_matched();
+        }
+        self.matched_lines += 1;
+        if !self.pattern_lines.is_empty() {
+            self.matcher.count_patterns(&line, &mut self.pattern_lines);
+        }
+
+        match self.config.count {
+            Some(CountMode::Matches) if !self.config.invert_match => {
+                self.count += self.matcher.count(&line)
+            }
+            // Inverted matches count lines for both modes
+            Some(_) => self.count += 1,
+            // Every occurrence is reported on its own when only printing matches
+            None if self.config.only_matching && !self.config.invert_match => {
+                self.count += 1;
+                let content = line.into_owned();
+                // Empty matches have no text to print
+                let spans = self.matcher.find_all(&content);
+                for (start, end) in spans.into_iter().filter(|(s, e)| e > s) {
+                    self.lines.push(LineMatch {
+                        line: number,
+                        column: start,
+                        end,
+                        offset: offset + start,
+                        content: content.clone(),
+                    });
+                }
+            }
+            None => {
+                self.count += 1;
+                self.lines.push(LineMatch {
+                    line: number,
+                    column: start,
+                    end,
+                    offset: offset + start,
+                    content: line.into_owned(),
+                });
+            }
+        }
+
+        // Binary files are only listed, the first match is enough
+        // Same when listing files, one match decides about the file
+        // unless other patterns are still missing or counted
+        let listed = self.config.lists_files() && self.pattern_lines.is_empty();
+        !(self.binary || listed || self.limit_reached()) || !self.all_matched()
+    }
+
+    fn limit_reached(&self) -> bool {
+        self.config
+            .max_count
+            .is_some_and(|max| self.matched_lines >= max)
+    }
+
+    fn all_matched(&self) -> bool {
+        self.matched.iter().all(|&m| m)
+    }
+
+    fn finish(mut self, path: PathBuf) -> FileMatch {
+        // Files missing any of the required patterns don't match at all
+        if !self.all_matched() && !self.config.invert_match {
+            self.lines.clear();
+            self.count = 0;
+        } else {
+            self.patterns.add(&self.pattern_lines);
+        }
+
+        // Counting and listing files don't keep the matched lines
+        let lines = if self.config.count.is_some() || self.config.lists_files() {
+            None
+        } else {
+            Some(self.lines)
+        };
+
+        FileMatch {
+            path,
+            lines,
+            count: self.count,
+            binary: self.binary,
+            name_matched: false,
+            digest: None,
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::cache::Cache;
+use crate::memory::Memory;
+use crate::profile::Profile;
+use crate::progress::{PatternStats, Progress};
+use crate::SearchError;
+
+// State of a running search shared by the walker and all workers
+#[derive(Clone)]
+pub struct Context {
+    pub errors: ErrorSender,
+    pub progress: Arc<Progress>,
+    pub profile: Arc<Profile>,
+    pub patterns: Arc<PatternStats>,
+    // Results and directory listings of the last run, see `Search::cache`
+    pub cache: Option<Arc<Cache>>,
+    // Bounds paths, buffers and results held at once, see `Search::memory_limit`
+    pub memory: Option<Arc<Memory>>,
+    pub cancel: Cancel,
+    // Start of the search, phases are timed relative to it
+    pub started: Instant,
+}
+
+// Passes errors from the walker and the workers to `SearchResults`
+#[derive(Clone)]
+pub struct ErrorSender(pub mpsc::Sender<SearchError>);
+
+impl ErrorSender {
+    pub fn send(&self, path: &Path, error: io::Error, fatal: bool) {
+        // Errors are dropped if nobody is interested in them anymore
+        let _ = self.0.send(SearchError {
+            path: path.to_path_buf(),
+            error,
+            fatal,
+        });
+    }
+
+    pub fn skip(&self, path: &Path, error: io::Error) {
+        self.send(path, error, false);
+    }
+}
+
+// Tells the walker and the workers to stop as soon as possible
+// Checked per directory entry, file and line
+#[derive(Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
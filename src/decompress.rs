@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+// Detects the compression by file extension, falling back to magic bytes
+pub fn detect(path: &Path, file: &mut File) -> Option<Compression> {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+
+    match extension.as_deref() {
+        Some("gz") => return Some(Compression::Gzip),
+        Some("bz2") => return Some(Compression::Bzip2),
+        Some("xz") => return Some(Compression::Xz),
+        Some("zst") => return Some(Compression::Zstd),
+        _ => {}
+    }
+
+    let mut magic = [0; 6];
+    let read = read_prefix(file, &mut magic).ok()?;
+    file.seek(SeekFrom::Start(0)).ok()?;
+
+    let magic = &magic[..read];
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        Some(Compression::Gzip)
+    } else if magic.starts_with(b"BZh") {
+        Some(Compression::Bzip2)
+    } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Some(Compression::Xz)
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Some(Compression::Zstd)
+    } else {
+        None
+    }
+}
+
+pub fn decoder(compression: Compression, file: File) -> io::Result<Box<dyn Read>> {
+    let reader = BufReader::new(file);
+
+    Ok(match compression {
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+        Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+    })
+}
+
+// Reads as many bytes as available up to the buffer size
+fn read_prefix(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match file.read(&mut buffer[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+
+    Ok(read)
+}
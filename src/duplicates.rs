@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{Config, Context, FileMatch};
+
+// Files with identical content, see `Search::duplicates`
+pub struct Duplicates {
+    pub size: u64,
+    // BLAKE3 digest in hex
+    pub hash: String,
+    // Sorted by path
+    pub paths: Vec<PathBuf>,
+}
+
+// Groups the found files by size first, only files sharing their size with
+// another one are hashed, spread over the search threads
+// Empty files are left out, `--empty` finds them
+// Unreadable files are skipped and reported to the context
+pub fn find(config: &Config, context: &Context, results: Vec<FileMatch>) -> Vec<Duplicates> {
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for result in results {
+        match fs::metadata(&result.path) {
+            Ok(m) if m.is_file() && m.len() > 0 => {
+                by_size.entry(m.len()).or_default().push(result.path)
+            }
+            Ok(_) => {}
+            Err(err) => context.errors.skip(&result.path, err),
+        }
+    }
+
+    let candidates = by_size
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(size, paths)| paths.into_iter().map(move |path| (size, path)));
+    let queue = Mutex::new(candidates);
+    let hashed = Mutex::new(HashMap::<_, Vec<PathBuf>>::new());
+
+    thread::scope(|scope| {
+        for _ in 0..config.search_threads {
+            scope.spawn(|| loop {
+                if context.cancel.is_cancelled() {
+                    return;
+                }
+
+                // Release the lock before hashing
+                let next = queue.lock().unwrap().next();
+                let (size, path) = match next {
+                    Some(c) => c,
+                    None => return,
+                };
+
+                match hash(&path) {
+                    Ok(hash) => {
+                        context.progress.add_bytes(size);
+                        let mut hashed = hashed.lock().unwrap();
+                        hashed.entry((size, hash)).or_default().push(path);
+                    }
+                    Err(err) => context.errors.skip(&path, err),
+                }
+            });
+        }
+    });
+
+    let mut groups: Vec<_> = hashed
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|((size, hash), mut paths)| {
+            paths.sort();
+            Duplicates {
+                size,
+                hash: hash.to_hex().to_string(),
+                paths,
+            }
+        })
+        .collect();
+
+    // Largest files first, they waste the most space
+    groups.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.paths.cmp(&b.paths)));
+    groups
+}
+
+fn hash(path: &Path) -> io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(fs::File::open(path)?)?;
+    Ok(hasher.finalize())
+}
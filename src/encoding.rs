@@ -0,0 +1,186 @@
+use std::io;
+use std::io::Read;
+
+const UTF8_BOM: [u8; 3] = [0xef, 0xbb, 0xbf];
+const UTF16LE_BOM: [u8; 2] = [0xff, 0xfe];
+const UTF16BE_BOM: [u8; 2] = [0xfe, 0xff];
+
+// Text encoding of searched files
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+    // UTF-16 if the file starts with a byte order mark, UTF-8 otherwise
+    Auto,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    // Whether files can be searched without transcoding
+    pub fn is_utf8(self, prefix: &[u8]) -> bool {
+        match self {
+            Encoding::Auto => {
+                !prefix.starts_with(&UTF16LE_BOM) && !prefix.starts_with(&UTF16BE_BOM)
+            }
+            Encoding::Utf8 => true,
+            _ => false,
+        }
+    }
+}
+
+// Converts a source in any supported encoding to UTF-8 on the fly
+// Byte order marks are removed, invalid UTF-16 is replaced by U+FFFD
+pub struct Decoder<R: Read> {
+    inner: R,
+    encoding: Encoding,
+    started: bool,
+    // Source bytes not converted yet
+    pending: Vec<u8>,
+    // Converted bytes not returned yet
+    output: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R, encoding: Encoding) -> Decoder<R> {
+        Decoder {
+            inner,
+            encoding,
+            started: false,
+            pending: Vec::new(),
+            output: Vec::new(),
+            position: 0,
+        }
+    }
+
+    // Reads the byte order mark and resolves the encoding
+    fn start(&mut self) -> io::Result<()> {
+        self.started = true;
+
+        let mut prefix = [0; 3];
+        let mut read = 0;
+        while read < prefix.len() {
+            match self.inner.read(&mut prefix[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        let prefix = &prefix[..read];
+
+        let (encoding, bom) = if prefix.starts_with(&UTF8_BOM) {
+            (Encoding::Utf8, UTF8_BOM.len())
+        } else if prefix.starts_with(&UTF16LE_BOM) {
+            (Encoding::Utf16Le, UTF16LE_BOM.len())
+        } else if prefix.starts_with(&UTF16BE_BOM) {
+            (Encoding::Utf16Be, UTF16BE_BOM.len())
+        } else {
+            (Encoding::Utf8, 0)
+        };
+
+        // A byte order mark is only respected without explicit encoding,
+        // or if it confirms the explicit one
+        let bom = match self.encoding {
+            Encoding::Auto => {
+                self.encoding = encoding;
+                bom
+            }
+            explicit if explicit == encoding => bom,
+            _ => 0,
+        };
+
+        self.pending.extend_from_slice(&prefix[bom..]);
+        Ok(())
+    }
+
+    // Converts the next chunk of the source into `output`
+    // Returns `false` at the end of the source
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 8 * 1024];
+        let read = self.inner.read(&mut chunk)?;
+        self.pending.extend_from_slice(&chunk[..read]);
+
+        self.output.clear();
+        self.position = 0;
+
+        match self.encoding {
+            Encoding::Latin1 => {
+                let text: String = self.pending.iter().map(|&b| b as char).collect();
+                self.output.extend_from_slice(text.as_bytes());
+                self.pending.clear();
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let little_endian = self.encoding == Encoding::Utf16Le;
+                let mut units: Vec<u16> = self
+                    .pending
+                    .chunks_exact(2)
+                    .map(|pair| {
+                        if little_endian {
+                            u16::from_le_bytes([pair[0], pair[1]])
+                        } else {
+                            u16::from_be_bytes([pair[0], pair[1]])
+                        }
+                    })
+                    .collect();
+
+                let mut consumed = units.len() * 2;
+
+                // Keep a trailing high surrogate until its pair arrives
+                if read > 0 {
+                    if let Some(&last) = units.last() {
+                        if (0xd800..0xdc00).contains(&last) {
+                            units.pop();
+                            consumed -= 2;
+                        }
+                    }
+                }
+
+                let text: String = char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect();
+                self.output.extend_from_slice(text.as_bytes());
+                self.pending.drain(..consumed);
+
+                // A single byte left at the end of the source is invalid
+                if read == 0 && !self.pending.is_empty() {
+                    self.pending.clear();
+                    self.output
+                        .extend_from_slice(char::REPLACEMENT_CHARACTER.to_string().as_bytes());
+                }
+            }
+            _ => {
+                self.output.append(&mut self.pending);
+            }
+        }
+
+        Ok(read > 0 || !self.output.is_empty())
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if !self.started {
+            self.start()?;
+        }
+
+        // UTF-8 is passed through once the prefix has been returned
+        if self.encoding == Encoding::Utf8
+            && self.pending.is_empty()
+            && self.position == self.output.len()
+        {
+            return self.inner.read(buffer);
+        }
+
+        while self.position == self.output.len() {
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+
+        let available = &self.output[self.position..];
+        let n = available.len().min(buffer.len());
+        buffer[..n].copy_from_slice(&available[..n]);
+        self.position += n;
+        Ok(n)
+    }
+}
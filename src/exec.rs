@@ -0,0 +1,255 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use crate::context::Cancel;
+use crate::SearchError;
+
+// Command run for matched files, see `Search::exec`
+pub struct Exec {
+    args: Vec<String>,
+    // All files are passed to one command instead of one command per file
+    batch: bool,
+}
+
+// Arguments are at most this long in total per batch, below the limits
+// of all common systems
+const MAX_BATCH_BYTES: usize = 128 * 1024;
+
+impl Exec {
+    // Splits the command line into arguments like a shell, quotes and
+    // backslashes are supported but nothing is expanded
+    // Without any placeholder the path is appended as the last argument
+    pub fn parse(command: &str, batch: bool) -> Result<Exec, Box<dyn Error>> {
+        let invalid = |reason: &str| -> Box<dyn Error> {
+            Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid command: {}", reason),
+            ))
+        };
+
+        let mut args = Vec::new();
+        let mut current: Option<String> = None;
+        let mut quote = None;
+        let mut chars = command.chars();
+
+        while let Some(c) = chars.next() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some('"'), '\\') => match chars.next() {
+                    Some(next @ ('"' | '\\')) => current.get_or_insert_with(String::new).push(next),
+                    Some(next) => {
+                        let arg = current.get_or_insert_with(String::new);
+                        arg.push('\\');
+                        arg.push(next);
+                    }
+                    None => return Err(invalid("unterminated quote")),
+                },
+                (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+                (None, '\'' | '"') => {
+                    quote = Some(c);
+                    current.get_or_insert_with(String::new);
+                }
+                (None, '\\') => match chars.next() {
+                    Some(next) => current.get_or_insert_with(String::new).push(next),
+                    None => return Err(invalid("trailing backslash")),
+                },
+                (None, c) if c.is_whitespace() => args.extend(current.take()),
+                (None, c) => current.get_or_insert_with(String::new).push(c),
+            }
+        }
+
+        if quote.is_some() {
+            return Err(invalid("unterminated quote"));
+        }
+        args.extend(current);
+
+        if args.is_empty() {
+            return Err(invalid("empty command"));
+        }
+        if !args[1..].iter().any(|arg| has_placeholder(arg)) {
+            args.push(String::from("{}"));
+        }
+
+        Ok(Exec { args, batch })
+    }
+
+    // Arguments for the given files, arguments with placeholders are
+    // repeated for every file in batch mode
+    fn command(&self, paths: &[PathBuf]) -> process::Command {
+        let mut command = process::Command::new(&self.args[0]);
+        for arg in &self.args[1..] {
+            if has_placeholder(arg) {
+                command.args(paths.iter().map(|path| substitute(arg, path)));
+            } else {
+                command.arg(arg);
+            }
+        }
+        command
+    }
+}
+
+fn has_placeholder(arg: &str) -> bool {
+    ["{}", "{/}", "{//}", "{.}", "{/.}"]
+        .iter()
+        .any(|p| arg.contains(p))
+}
+
+// `{}` is the path, `{/}` the file name, `{//}` the parent directory,
+// `{.}` the path and `{/.}` the file name without extension
+fn substitute(arg: &str, path: &Path) -> OsString {
+    let mut result = OsString::new();
+    let mut rest = arg;
+
+    while let Some(start) = rest.find('{') {
+        result.push(&rest[..start]);
+        rest = &rest[start..];
+
+        let (value, len) = if rest.starts_with("{}") {
+            (path.as_os_str().to_owned(), 2)
+        } else if rest.starts_with("{//}") {
+            let parent = path.parent().unwrap_or(Path::new(""));
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            (parent.as_os_str().to_owned(), 4)
+        } else if rest.starts_with("{/.}") {
+            (path.file_stem().unwrap_or_default().to_owned(), 4)
+        } else if rest.starts_with("{/}") {
+            (path.file_name().unwrap_or_default().to_owned(), 3)
+        } else if rest.starts_with("{.}") {
+            (path.with_extension("").into_os_string(), 3)
+        } else {
+            (OsString::from("{"), 1)
+        };
+
+        result.push(value);
+        rest = &rest[len..];
+    }
+
+    result.push(rest);
+    result
+}
+
+// Runs the command for matched files while the search goes on
+// Single commands run on `threads` threads, their output is captured and
+// printed at once so the output of parallel commands doesn't interleave
+// Batches run after the search with the terminal attached
+pub struct Executor {
+    exec: Arc<Exec>,
+    sender: Option<mpsc::Sender<PathBuf>>,
+    workers: Vec<thread::JoinHandle<()>>,
+    failures: Arc<Mutex<Vec<SearchError>>>,
+    batch: Vec<PathBuf>,
+    cancel: Cancel,
+}
+
+impl Executor {
+    pub fn start(exec: Arc<Exec>, threads: usize, cancel: Cancel) -> Executor {
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let mut executor = Executor {
+            exec,
+            sender: None,
+            workers: Vec::new(),
+            failures,
+            batch: Vec::new(),
+            cancel,
+        };
+        if executor.exec.batch {
+            return executor;
+        }
+
+        let (sender, receiver) = mpsc::channel::<PathBuf>();
+        let queue = Arc::new(Mutex::new(receiver));
+        for _ in 0..threads.max(1) {
+            let exec = executor.exec.clone();
+            let queue = queue.clone();
+            let failures = executor.failures.clone();
+            let cancel = executor.cancel.clone();
+
+            executor.workers.push(thread::spawn(move || loop {
+                // Release the lock before running the command
+                let path = match queue.lock().unwrap().recv() {
+                    Ok(p) => p,
+                    Err(_) => break,
+                };
+
+                if cancel.is_cancelled() {
+                    break;
+                }
+
+                let output = exec.command(std::slice::from_ref(&path)).output();
+                let status = output.map(|output| {
+                    // Locking stdout keeps the output of one command together
+                    let _ = io::stdout().lock().write_all(&output.stdout);
+                    let _ = io::stderr().lock().write_all(&output.stderr);
+                    output.status
+                });
+
+                if let Some(error) = failure(&path, status) {
+                    failures.lock().unwrap().push(error);
+                }
+            }));
+        }
+
+        executor.sender = Some(sender);
+        executor
+    }
+
+    pub fn push(&mut self, path: PathBuf) {
+        match &self.sender {
+            Some(sender) => {
+                let _ = sender.send(path);
+            }
+            None => self.batch.push(path),
+        }
+    }
+
+    // Waits for all commands and returns the files they failed for,
+    // a failed batch is reported for its first file
+    pub fn finish(mut self) -> Vec<SearchError> {
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+
+        let mut failures = self.failures.lock().unwrap().split_off(0);
+        let mut start = 0;
+        while start < self.batch.len() && !self.cancel.is_cancelled() {
+            let mut end = start;
+            let mut bytes = 0;
+            while end < self.batch.len() && (end == start || bytes < MAX_BATCH_BYTES) {
+                bytes += self.batch[end].as_os_str().len() + 1;
+                end += 1;
+            }
+
+            let paths = &self.batch[start..end];
+            let status = self.exec.command(paths).status();
+            failures.extend(failure(&paths[0], status));
+            start = end;
+        }
+
+        failures
+    }
+}
+
+fn failure(path: &Path, status: io::Result<process::ExitStatus>) -> Option<SearchError> {
+    let error = match status {
+        Ok(status) if status.success() => return None,
+        Ok(status) => io::Error::other(format!("command failed with {}", status)),
+        Err(err) => io::Error::new(err.kind(), format!("command failed: {}", err)),
+    };
+
+    Some(SearchError {
+        path: path.to_path_buf(),
+        error,
+        fatal: true,
+    })
+}
@@ -0,0 +1,215 @@
+use std::env;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::ignore;
+use crate::platform::display;
+use crate::printer::absolute;
+use crate::walker;
+use crate::{Config, Strategy};
+
+// Prints what a search with `config` would do instead of running it, see
+// `--explain`: its input, the compiled matchers, the filters and ignore
+// files of the walk and the threads
+// Lines are `name: value` like `--stats`, names repeat for lists
+pub fn explain<W: Write>(config: &Config, out: &mut W) -> io::Result<()> {
+    let mut line =
+        |name: &str, value: &str| writeln!(out, "{:<16} {}", format!("{}:", name), value);
+
+    if config.stdin {
+        line("input", "stdin")?;
+    } else if let Some(list) = &config.files_from {
+        line("input", &format!("files listed in {}", display(list)))?;
+    } else {
+        for (root, &file) in config.roots.iter().zip(&config.file_roots) {
+            let kind = if file { "file" } else { "directory" };
+            line("root", &format!("{} ({})", display(root), kind))?;
+        }
+    }
+
+    match &config.filename {
+        Some(matcher) if config.name_or_content => line("name or content", &matcher.describe())?,
+        Some(matcher) => line("name", &matcher.describe())?,
+        None => {}
+    }
+    if let Some(content) = &config.content {
+        for pattern in content.describe() {
+            line("content", &pattern)?;
+        }
+        if content.requires_all() {
+            line("patterns", "all have to match a file")?;
+        } else if content.is_combined() {
+            line("patterns", "literals searched at once")?;
+        }
+        if config.invert_match {
+            line("inverted", "lines not matching are reported")?;
+        }
+    }
+
+    for matcher in &config.file_types {
+        line("type", &matcher.describe())?;
+    }
+    for matcher in &config.exclude {
+        line("exclude", &matcher.describe())?;
+    }
+    for matcher in &config.exclude_dir {
+        line("exclude dir", &matcher.describe())?;
+    }
+    let kinds = if config.kinds.is_empty() {
+        String::from("all but directories and special files")
+    } else {
+        let names: Vec<_> = config.kinds.iter().map(|k| k.name()).collect();
+        names.join(", ")
+    };
+    line("kinds", &kinds)?;
+    if let Some(size) = config.min_size {
+        line("min size", &format!("{} bytes", size))?;
+    }
+    if let Some(size) = config.max_size {
+        line("max size", &format!("{} bytes", size))?;
+    }
+    if let Some(time) = config.newer_than {
+        line("newer than", &ago(time))?;
+    }
+    if let Some(time) = config.older_than {
+        line("older than", &ago(time))?;
+    }
+
+    // Only the walk reads ignore files
+    let walks = !config.stdin && config.files_from.is_none();
+    let reads_ignores = !config.no_ignore || !config.ignore_files.is_empty();
+    if walks && reads_ignores {
+        let mut any = false;
+        for (root, _) in config
+            .roots
+            .iter()
+            .zip(&config.file_roots)
+            .filter(|(_, &file)| !file)
+        {
+            for path in ignore::root_files(root, &config.ignore_files, !config.no_ignore) {
+                line("ignore file", &display(&path))?;
+                any = true;
+            }
+        }
+        if !any {
+            line("ignore file", "none in the roots")?;
+        }
+        if !config.no_ignore {
+            line("ignore files", "read in every directory below the roots")?;
+        }
+    } else if walks {
+        line("ignore files", "not read")?;
+    }
+
+    if walks {
+        line("hidden", if config.hidden { "searched" } else { "skipped" })?;
+        let symlinks = if config.follow {
+            "followed"
+        } else {
+            "not followed"
+        };
+        line("symlinks", symlinks)?;
+        let depth = config
+            .max_depth
+            .map_or_else(|| String::from("unlimited"), |d| d.to_string());
+        line("max depth", &depth)?;
+        let strategy = match config.strategy {
+            Strategy::DepthFirst => "depth-first",
+            Strategy::BreadthFirst => "breadth-first",
+        };
+        line("strategy", strategy)?;
+        if config.one_file_system {
+            line("filesystems", "only the one of each root")?;
+        }
+    }
+
+    line(
+        "threads",
+        &format!(
+            "{} walk and {} search threads, degree of parallelism {}",
+            config.walk_threads, config.search_threads, config.dop
+        ),
+    )?;
+    if config.index.is_some() {
+        line("index", "skips files without the trigrams of the patterns")?;
+    }
+    if let Some((path, _)) = &config.cache {
+        line("cache", &display(path))?;
+    }
+    if let Some(limit) = config.memory_limit {
+        line("memory limit", &format!("{} bytes", limit))?;
+    }
+    if let Some(timeout) = config.file_timeout {
+        line("file timeout", &format!("{:?}", timeout))?;
+    }
+
+    Ok(())
+}
+
+// Prints for every path whether the search would find it or the rule that
+// skips it, like `git check-ignore -v`: the ignore file, line and rule, the
+// exclude, the hidden entry or the file filter
+// Returns whether all of them would be searched
+pub fn why<W: Write>(config: &Config, paths: &[PathBuf], out: &mut W) -> io::Result<bool> {
+    let mut searched = true;
+    for path in paths {
+        let found = as_found(config, path);
+        let reason = if config.stdin {
+            Ok(Some(String::from("stdin is searched instead")))
+        } else {
+            walker::skipped_by(config, &found)
+        };
+
+        match reason {
+            Ok(None) => writeln!(out, "{}: searched", display(path))?,
+            Ok(Some(reason)) => {
+                searched = false;
+                writeln!(out, "{}: skipped, {}", display(path), reason)?;
+            }
+            Err(err) => {
+                searched = false;
+                writeln!(out, "{}: skipped, {}", display(path), err)?;
+            }
+        }
+    }
+
+    Ok(searched)
+}
+
+// Spells `path` the way the walk finds it, below the innermost root it's in
+// Paths below no root are returned unchanged
+fn as_found(config: &Config, path: &Path) -> PathBuf {
+    let working_dir = env::current_dir().unwrap_or_default();
+    let wanted = absolute(&working_dir, path);
+    config
+        .roots
+        .iter()
+        .filter_map(|root| {
+            let base = absolute(&working_dir, root);
+            let relative = wanted.strip_prefix(&base).ok()?.to_path_buf();
+            Some((base.components().count(), root, relative))
+        })
+        .max_by_key(|(depth, _, _)| *depth)
+        .map_or_else(
+            || path.to_path_buf(),
+            |(_, root, relative)| match relative {
+                r if r.as_os_str().is_empty() => root.clone(),
+                r => root.join(r),
+            },
+        )
+}
+
+// Like `modified 2 hours ago`, in the largest whole unit
+fn ago(time: SystemTime) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(time)
+        .map_or(0, |d| d.as_secs());
+    let units = [(86_400, "days"), (3_600, "hours"), (60, "minutes")];
+    let (count, unit) = units
+        .iter()
+        .find(|(size, _)| seconds >= *size)
+        .map_or((seconds, "seconds"), |(size, unit)| (seconds / size, *unit));
+    format!("modified {} {} ago", count, unit)
+}
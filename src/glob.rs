@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use regex::{Regex, RegexBuilder};
+
+// Translates a shell glob into an anchored regular expression
+// Supported syntax: `*`, `?`, `**`, `[...]`, `[!...]`, `{a,b}` and `\` escapes
+pub fn to_regex(glob: &str, ignore_case: bool) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(&translate(glob))
+        .case_insensitive(ignore_case)
+        .build()
+}
+
+fn translate(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::from("^");
+    let mut brace_depth = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '*' => {
+                if chars.get(i + 1) == Some(&'*') {
+                    let at_start = i == 0 || chars[i - 1] == '/';
+                    if at_start && chars.get(i + 2) == Some(&'/') {
+                        // `**/` matches zero or more directories
+                        regex.push_str("(?:.*/)?");
+                        i += 3;
+                        continue;
+                    }
+
+                    regex.push_str(".*");
+                    i += 2;
+                    continue;
+                }
+
+                regex.push_str("[^/]*");
+            }
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                if let Some((class, next)) = translate_class(&chars, i) {
+                    regex.push_str(&class);
+                    i = next;
+                    continue;
+                }
+
+                regex.push_str("\\[");
+            }
+            '{' => {
+                brace_depth += 1;
+                regex.push_str("(?:");
+            }
+            '}' if brace_depth > 0 => {
+                brace_depth -= 1;
+                regex.push(')');
+            }
+            ',' if brace_depth > 0 => regex.push('|'),
+            '\\' => {
+                i += 1;
+                if let Some(escaped) = chars.get(i) {
+                    regex.push_str(&regex::escape(&escaped.to_string()));
+                } else {
+                    regex.push_str("\\\\");
+                }
+            }
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+
+        i += 1;
+    }
+
+    // Close unterminated alternations so the regex stays valid
+    for _ in 0..brace_depth {
+        regex.push(')');
+    }
+
+    regex.push('$');
+    regex
+}
+
+// Translates a character class starting at `start`
+// Returns the regex class and the index after the closing bracket
+fn translate_class(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let mut i = start + 1;
+    let mut class = String::from("[");
+
+    if let Some('!') | Some('^') = chars.get(i) {
+        class.push('^');
+        i += 1;
+    }
+
+    // A leading `]` is part of the class
+    if chars.get(i) == Some(&']') {
+        class.push_str("\\]");
+        i += 1;
+    }
+
+    while i < chars.len() {
+        match chars[i] {
+            ']' => {
+                class.push(']');
+                return Some((class, i + 1));
+            }
+            '\\' | '[' | '&' | '~' | '^' => {
+                class.push('\\');
+                class.push(chars[i]);
+            }
+            c => class.push(c),
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+// Globs always use `/` as separator, regardless of platform
+pub fn to_slash(path: &Path) -> String {
+    let components: Vec<_> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    components.join("/")
+}
@@ -0,0 +1,121 @@
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::log;
+use crate::memory;
+use crate::{Config, Context, FileMatch};
+
+// Digest printed next to every file with `Search::hash`
+#[derive(Clone, Copy)]
+pub enum HashAlgorithm {
+    Sha256,
+    Md5,
+    Blake3,
+}
+
+// Hashes the found files of a file search in multiple threads, like the
+// workers of a content search
+// Unreadable files are skipped and reported to the context
+pub fn hash_files(
+    config: &Arc<Config>,
+    files: mpsc::Receiver<PathBuf>,
+    sender: mpsc::SyncSender<FileMatch>,
+    context: Context,
+) -> Vec<thread::JoinHandle<()>> {
+    let algorithm = config.hash.expect("hash algorithm");
+    let queue = Arc::new(Mutex::new(files));
+    let mut workers = Vec::with_capacity(config.search_threads);
+
+    for i in 0..config.search_threads {
+        let sender = sender.clone();
+        let queue = queue.clone();
+        let context = context.clone();
+
+        workers.push(log::spawn(format!("hash-{}", i), move || {
+            loop {
+                // Release the lock before hashing
+                let path = match queue.lock().unwrap().recv() {
+                    Ok(p) => p,
+                    Err(_) => break,
+                };
+                if let Some(memory) = &context.memory {
+                    memory.paths.release(memory::path_size(&path));
+                }
+
+                if context.cancel.is_cancelled() {
+                    break;
+                }
+
+                let digest = match digest(&path, algorithm, &context) {
+                    Ok(d) => d,
+                    Err(err) => {
+                        context.errors.skip(&path, err);
+                        continue;
+                    }
+                };
+
+                let result = FileMatch {
+                    path,
+                    lines: None,
+                    count: 0,
+                    binary: false,
+                    name_matched: false,
+                    digest: Some(digest),
+                };
+                if sender.send(result).is_err() {
+                    break;
+                }
+            }
+
+            context.progress.search_finished(context.started.elapsed());
+        }));
+    }
+
+    workers
+}
+
+// Lowercase hex digest of the file content
+fn digest(path: &Path, algorithm: HashAlgorithm, context: &Context) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut buffer = vec![0; 64 * 1024];
+
+    let mut sha256 = Sha256::new();
+    let mut md5 = Md5::new();
+    let mut blake3 = blake3::Hasher::new();
+
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        context.progress.add_bytes(read as u64);
+
+        let chunk = &buffer[..read];
+        match algorithm {
+            HashAlgorithm::Sha256 => sha256.update(chunk),
+            HashAlgorithm::Md5 => md5.update(chunk),
+            HashAlgorithm::Blake3 => {
+                blake3.update(chunk);
+            }
+        }
+    }
+
+    Ok(match algorithm {
+        HashAlgorithm::Sha256 => hex(&sha256.finalize()),
+        HashAlgorithm::Md5 => hex(&md5.finalize()),
+        HashAlgorithm::Blake3 => blake3.finalize().to_hex().to_string(),
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,263 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use regex::Regex;
+
+use crate::glob;
+
+// Per-directory ignore files, ordered from lowest to highest precedence
+// `.lpsignore` only applies to lps and can whitelist files ignored by git
+const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".lpsignore"];
+
+struct Rule {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+    // Anchored rules match the path relative to the ignore file,
+    // others match the file name at any depth
+    anchored: bool,
+    // Where the rule is written, for `--why`
+    line: usize,
+    text: String,
+}
+
+struct IgnoreFile {
+    path: PathBuf,
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+// The rule deciding about a path, like `git check-ignore -v` shows it
+pub struct IgnoreRule {
+    pub file: PathBuf,
+    pub line: usize,
+    pub text: String,
+    // `false` for a negated rule whitelisting the path
+    pub ignored: bool,
+}
+
+impl IgnoreFile {
+    fn from_path(path: &Path, base: &Path) -> Option<IgnoreFile> {
+        let text = fs::read_to_string(path).ok()?;
+        let rules: Vec<Rule> = text
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| parse_rule(line, i + 1))
+            .collect();
+
+        if rules.is_empty() {
+            return None;
+        }
+
+        Some(IgnoreFile {
+            path: path.to_path_buf(),
+            base: base.to_path_buf(),
+            rules,
+        })
+    }
+
+    // Returns the last rule matching the path, which decides whether it's
+    // ignored or explicitly whitelisted
+    fn matched(&self, path: &Path, is_dir: bool) -> Option<&Rule> {
+        let relative = path.strip_prefix(&self.base).ok()?;
+        let relative = glob::to_slash(relative);
+        let name = path.file_name()?.to_string_lossy();
+
+        // Later rules take precedence over earlier ones
+        for rule in self.rules.iter().rev() {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+
+            let target = if rule.anchored {
+                relative.as_str()
+            } else {
+                &name
+            };
+
+            if rule.regex.is_match(target) {
+                return Some(rule);
+            }
+        }
+
+        None
+    }
+}
+
+fn parse_rule(line: &str, number: usize) -> Option<Rule> {
+    let mut pattern = line.trim_end();
+    if pattern.is_empty() || pattern.starts_with('#') {
+        return None;
+    }
+
+    let negated = pattern.starts_with('!');
+    if negated {
+        pattern = &pattern[1..];
+    }
+
+    let dir_only = pattern.ends_with('/');
+    if dir_only {
+        pattern = &pattern[..pattern.len() - 1];
+    }
+
+    // A separator at the start or in the middle anchors the pattern
+    let anchored = pattern.contains('/');
+    let pattern = pattern.trim_start_matches('/');
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let regex = glob::to_regex(pattern, false).ok()?;
+
+    Some(Rule {
+        regex,
+        negated,
+        dir_only,
+        anchored,
+        line: number,
+        text: String::from(line.trim_end()),
+    })
+}
+
+// Ignore files of a directory and all of its parents
+pub struct IgnoreStack {
+    parent: Option<Arc<IgnoreStack>>,
+    files: Vec<IgnoreFile>,
+    // Whether the ignore files of directories are read
+    nested: bool,
+}
+
+impl IgnoreStack {
+    // Creates the bottom of the stack containing the extra ignore files
+    // of `Search::ignore_file` and the global git excludes on top, the
+    // latter only together with the ignore files of directories
+    pub fn root(root: &Path, extra: &[PathBuf], nested: bool) -> Arc<IgnoreStack> {
+        let global = global_excludes().filter(|_| nested);
+        let files = extra
+            .iter()
+            .chain(global.iter())
+            .filter_map(|path| IgnoreFile::from_path(path, root))
+            .collect();
+
+        Arc::new(IgnoreStack {
+            parent: None,
+            files,
+            nested,
+        })
+    }
+
+    // Pushes the ignore files found in `dir`
+    // Returns the parent itself if there are none
+    pub fn push(parent: &Arc<IgnoreStack>, dir: &Path) -> Arc<IgnoreStack> {
+        if !parent.nested {
+            return parent.clone();
+        }
+
+        let mut files = Vec::new();
+
+        let exclude = dir.join(".git").join("info").join("exclude");
+        files.extend(IgnoreFile::from_path(&exclude, dir));
+
+        for name in IGNORE_FILES.iter() {
+            files.extend(IgnoreFile::from_path(&dir.join(name), dir));
+        }
+
+        if files.is_empty() {
+            return parent.clone();
+        }
+
+        Arc::new(IgnoreStack {
+            parent: Some(parent.clone()),
+            files,
+            nested: true,
+        })
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.matched(path, is_dir)
+            .is_some_and(|(_, rule)| !rule.negated)
+    }
+
+    // The rule that ignores or whitelists the path, if any
+    pub fn rule(&self, path: &Path, is_dir: bool) -> Option<IgnoreRule> {
+        self.matched(path, is_dir).map(|(file, rule)| IgnoreRule {
+            file: file.path.clone(),
+            line: rule.line,
+            text: rule.text.clone(),
+            ignored: !rule.negated,
+        })
+    }
+
+    fn matched(&self, path: &Path, is_dir: bool) -> Option<(&IgnoreFile, &Rule)> {
+        // Deeper ignore files take precedence over shallower ones
+        let mut stack = Some(self);
+        while let Some(current) = stack {
+            for file in current.files.iter().rev() {
+                if let Some(rule) = file.matched(path, is_dir) {
+                    return Some((file, rule));
+                }
+            }
+
+            stack = current.parent.as_deref();
+        }
+
+        None
+    }
+}
+
+// Ignore files with rules that apply to the whole tree of `root`, like
+// `IgnoreStack::root` and `push` read them, the ones of directories below
+// the root are only read when the walk reaches them
+pub fn root_files(root: &Path, extra: &[PathBuf], nested: bool) -> Vec<PathBuf> {
+    let mut paths = extra.to_vec();
+    if nested {
+        paths.extend(global_excludes());
+        paths.push(root.join(".git").join("info").join("exclude"));
+        paths.extend(IGNORE_FILES.iter().map(|name| root.join(name)));
+    }
+
+    paths
+        .into_iter()
+        .filter(|path| IgnoreFile::from_path(path, root).is_some())
+        .collect()
+}
+
+// Location of the global git excludes file
+// Either `core.excludesFile` of the user's git config or the XDG default
+fn global_excludes() -> Option<PathBuf> {
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+
+    if let Some(home) = &home {
+        if let Ok(gitconfig) = fs::read_to_string(home.join(".gitconfig")) {
+            let mut in_core = false;
+            for line in gitconfig.lines() {
+                let line = line.trim();
+                if line.starts_with('[') {
+                    in_core = line.eq_ignore_ascii_case("[core]");
+                    continue;
+                }
+
+                let mut parts = line.splitn(2, '=');
+                let key = parts.next().unwrap_or("").trim();
+                if in_core && key.eq_ignore_ascii_case("excludesfile") {
+                    let value = parts.next().unwrap_or("").trim().trim_matches('"');
+                    if let Some(rest) = value.strip_prefix("~/") {
+                        return Some(home.join(rest));
+                    }
+                    return Some(PathBuf::from(value));
+                }
+            }
+        }
+    }
+
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(config) if !config.is_empty() => {
+            Some(PathBuf::from(config).join("git").join("ignore"))
+        }
+        _ => home.map(|home| home.join(".config").join("git").join("ignore")),
+    }
+}
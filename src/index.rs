@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use regex_syntax::hir::literal::Extractor;
+use regex_syntax::ParserBuilder;
+
+use crate::cache;
+use crate::Config;
+
+// Written first, the last byte is the version of the format
+const MAGIC: &[u8; 8] = b"LPSIDX\x00\x01";
+
+// Larger files aren't indexed and always searched
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+// Trigram index of the files below a root, like codesearch
+// Every file is listed with its size and modification time, files that
+// changed since are unindexed and searched like without the index
+// Trigrams are taken from the raw bytes with ASCII letters lowercased,
+// so case insensitive searches can use the index as well
+pub struct Index {
+    files: Vec<IndexedFile>,
+    // Relative to the root of the index
+    ids: HashMap<PathBuf, u32>,
+    postings: HashMap<u32, Vec<u32>>,
+}
+
+struct IndexedFile {
+    path: PathBuf,
+    modified: u64,
+    len: u64,
+    // Too large, too recently modified or not encoded in UTF-8
+    indexed: bool,
+}
+
+// Number of files of a built index and where it was written
+pub struct IndexSummary {
+    pub files: usize,
+    pub trigrams: usize,
+    pub path: PathBuf,
+    // Files and directories that couldn't be indexed
+    pub errors: usize,
+}
+
+// Indexes every file the walk of `config` finds, see `Search::index`
+pub fn build(config: Arc<Config>, dir: &Path) -> Result<IndexSummary, Box<dyn Error>> {
+    if config.roots.len() > 1 {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "an index is built for a single root",
+        )));
+    }
+    if config.file_roots[0] {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "an index is built for a directory",
+        )));
+    }
+    let root = fs::canonicalize(&config.roots[0])?;
+    let mut results = crate::search(config.clone());
+    let paths: Vec<PathBuf> = results.by_ref().map(|r| r.path).collect();
+    let mut errors = 0;
+    for error in results.remaining_errors() {
+        crate::report(&config, &error);
+        errors += 1;
+    }
+
+    let next = AtomicUsize::new(0);
+    let indexed = Mutex::new(Vec::with_capacity(paths.len()));
+    thread::scope(|scope| {
+        for _ in 0..config.search_threads {
+            scope.spawn(|| loop {
+                let id = next.fetch_add(1, Ordering::Relaxed);
+                let path = match paths.get(id) {
+                    Some(p) => p,
+                    None => break,
+                };
+                let file = index_file(&root, path);
+                indexed.lock().unwrap().push((id, file));
+            });
+        }
+    });
+
+    let mut indexed = indexed.into_inner().unwrap();
+    indexed.sort_by_key(|&(id, _)| id);
+
+    // Files that couldn't be read are left out and searched live
+    let mut index = Index {
+        files: Vec::with_capacity(indexed.len()),
+        ids: HashMap::new(),
+        postings: HashMap::new(),
+    };
+    for (file, trigrams) in indexed.into_iter().filter_map(|(_, f)| f) {
+        let id = index.files.len() as u32;
+        for trigram in trigrams {
+            index.postings.entry(trigram).or_default().push(id);
+        }
+        index.ids.insert(file.path.clone(), id);
+        index.files.push(file);
+    }
+
+    let path = dir.join(cache::file_name(&[&root], "index"));
+    index.save(&path)?;
+    Ok(IndexSummary {
+        files: index.files.len(),
+        trigrams: index.postings.len(),
+        path,
+        errors,
+    })
+}
+
+// Path relative to the root and the trigrams of a file, `None` if unreadable
+fn index_file(root: &Path, path: &Path) -> Option<(IndexedFile, Vec<u32>)> {
+    let absolute = fs::canonicalize(path).ok()?;
+    let relative = absolute.strip_prefix(root).ok()?.to_path_buf();
+    // Paths that aren't valid UTF-8 can't be stored and are searched live
+    relative.to_str()?;
+    let metadata = fs::metadata(&absolute).ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+
+    let modified = metadata.modified().ok().and_then(cache::trusted);
+    let mut file = IndexedFile {
+        path: relative,
+        modified: modified.unwrap_or(0),
+        len: metadata.len(),
+        indexed: false,
+    };
+    if modified.is_none() || metadata.len() > MAX_FILE_SIZE {
+        return Some((file, Vec::new()));
+    }
+
+    let data = fs::read(&absolute).ok()?;
+    // Transcoded files don't contain the bytes of the pattern
+    if data.starts_with(&[0xff, 0xfe]) || data.starts_with(&[0xfe, 0xff]) {
+        return Some((file, Vec::new()));
+    }
+
+    file.indexed = true;
+    Some((file, trigrams(&data)))
+}
+
+fn trigrams(data: &[u8]) -> Vec<u32> {
+    let mut trigrams: Vec<u32> = data.windows(3).map(|w| trigram(w[0], w[1], w[2])).collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+fn trigram(a: u8, b: u8, c: u8) -> u32 {
+    u32::from(a.to_ascii_lowercase()) << 16
+        | u32::from(b.to_ascii_lowercase()) << 8
+        | u32::from(c.to_ascii_lowercase())
+}
+
+impl Index {
+    // Finds the index of the root or of one of its parents in `dir`
+    // Returns the index and the root relative to the root of the index
+    pub fn find(dir: &Path, root: &Path) -> Result<(Index, PathBuf), Box<dyn Error>> {
+        let root = fs::canonicalize(root)?;
+        for ancestor in root.ancestors() {
+            let path = dir.join(cache::file_name(&[ancestor], "index"));
+            match Index::load(&path) {
+                Ok(index) => {
+                    let relative = root.strip_prefix(ancestor).unwrap().to_path_buf();
+                    return Ok((index, relative));
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => {
+                    return Err(Box::new(io::Error::new(
+                        err.kind(),
+                        format!("index {}: {}", path.display(), err),
+                    )));
+                }
+            }
+        }
+
+        Err(Box::new(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no index of {}, run lps index build first", root.display()),
+        )))
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        let mut out = BufWriter::new(fs::File::create(&temporary)?);
+        out.write_all(MAGIC)?;
+
+        write_u64(&mut out, self.files.len() as u64)?;
+        for file in &self.files {
+            let path = file.path.to_string_lossy();
+            write_u64(&mut out, path.len() as u64)?;
+            out.write_all(path.as_bytes())?;
+            write_u64(&mut out, file.modified)?;
+            write_u64(&mut out, file.len)?;
+            out.write_all(&[u8::from(file.indexed)])?;
+        }
+
+        write_u64(&mut out, self.postings.len() as u64)?;
+        let mut postings: Vec<_> = self.postings.iter().collect();
+        postings.sort_unstable_by_key(|&(trigram, _)| *trigram);
+        for (trigram, ids) in postings {
+            write_u64(&mut out, u64::from(*trigram))?;
+            write_u64(&mut out, ids.len() as u64)?;
+            // Ids are ascending, the differences are small
+            let mut previous = 0;
+            for &id in ids {
+                write_u64(&mut out, u64::from(id - previous))?;
+                previous = id;
+            }
+        }
+
+        out.into_inner()?.sync_all()?;
+        fs::rename(&temporary, path)
+    }
+
+    fn load(path: &Path) -> io::Result<Index> {
+        let mut input = BufReader::new(fs::File::open(path)?);
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid());
+        }
+
+        let count = read_u64(&mut input)? as usize;
+        let mut index = Index {
+            files: Vec::with_capacity(count.min(1 << 20)),
+            ids: HashMap::new(),
+            postings: HashMap::new(),
+        };
+        for id in 0..count {
+            let len = read_u64(&mut input)? as usize;
+            let mut path = vec![0; len];
+            input.read_exact(&mut path)?;
+            let path = PathBuf::from(String::from_utf8(path).map_err(|_| invalid())?);
+            let modified = read_u64(&mut input)?;
+            let len = read_u64(&mut input)?;
+            let mut indexed = [0];
+            input.read_exact(&mut indexed)?;
+
+            index.ids.insert(path.clone(), id as u32);
+            index.files.push(IndexedFile {
+                path,
+                modified,
+                len,
+                indexed: indexed[0] == 1,
+            });
+        }
+
+        let trigrams = read_u64(&mut input)?;
+        for _ in 0..trigrams {
+            let trigram = u32::try_from(read_u64(&mut input)?).map_err(|_| invalid())?;
+            let len = read_u64(&mut input)? as usize;
+            let mut ids = Vec::with_capacity(len.min(count));
+            let mut id = 0u64;
+            for _ in 0..len {
+                id += read_u64(&mut input)?;
+                if id as usize >= count {
+                    return Err(invalid());
+                }
+                ids.push(id as u32);
+            }
+            index.postings.insert(trigram, ids);
+        }
+
+        Ok(index)
+    }
+
+    // Files containing all trigrams of `literal`, `None` for all files
+    fn containing(&self, literal: &[u8]) -> Option<Vec<bool>> {
+        if literal.len() < 3 {
+            return None;
+        }
+
+        let mut candidates = vec![true; self.files.len()];
+        for w in literal.windows(3) {
+            let mut found = vec![false; self.files.len()];
+            for &id in self.postings.get(&trigram(w[0], w[1], w[2]))?.iter() {
+                found[id as usize] = true;
+            }
+            for (candidate, found) in candidates.iter_mut().zip(found) {
+                *candidate &= found;
+            }
+        }
+
+        Some(candidates)
+    }
+}
+
+// What a pattern needs to match: any of the literals has to occur,
+// `None` if nothing is known about it
+fn required(pattern: &str, regex: bool, ignore_case: bool) -> Option<Vec<Vec<u8>>> {
+    if !regex && !ignore_case {
+        return Some(vec![pattern.as_bytes().to_vec()]);
+    }
+
+    // Casing is folded by Unicode rules, the extracted literals contain every
+    // variant like the Kelvin sign for `k`
+    let pattern = if regex {
+        String::from(pattern)
+    } else {
+        regex::escape(pattern)
+    };
+    let hir = ParserBuilder::new()
+        .case_insensitive(ignore_case)
+        .build()
+        .parse(&pattern)
+        .ok()?;
+    let literals = Extractor::new().extract(&hir);
+
+    let mut literals: Vec<Vec<u8>> = literals
+        .literals()?
+        .iter()
+        .map(|l| l.as_bytes().to_ascii_lowercase())
+        .collect();
+    literals.sort();
+    literals.dedup();
+    Some(literals)
+}
+
+// The files of an index which can match the content patterns of a search
+pub struct IndexFilter {
+    index: Index,
+    // Root of the search relative to the root of the index
+    prefix: PathBuf,
+    candidates: Vec<bool>,
+}
+
+impl IndexFilter {
+    // `patterns` are the content patterns, each a list of alternatives for
+    // pattern files, with `all` a file has to match all of them
+    pub fn new(
+        index: Index,
+        prefix: PathBuf,
+        patterns: &[Vec<String>],
+        regex: bool,
+        ignore_case: &[bool],
+        all: bool,
+    ) -> IndexFilter {
+        let files = index.files.len();
+        let mut candidates = vec![all; files];
+
+        for (alternatives, &ignore_case) in patterns.iter().zip(ignore_case) {
+            let mut matching = vec![false; files];
+            let literals = alternatives
+                .iter()
+                .map(|p| required(p, regex, ignore_case))
+                .collect::<Option<Vec<_>>>();
+            for literal in literals.iter().flatten().flatten() {
+                match index.containing(literal) {
+                    Some(found) => {
+                        for (matching, found) in matching.iter_mut().zip(found) {
+                            *matching |= found;
+                        }
+                    }
+                    None => matching = vec![true; files],
+                }
+            }
+            if literals.is_none() {
+                matching = vec![true; files];
+            }
+
+            for (candidate, matching) in candidates.iter_mut().zip(matching) {
+                if all {
+                    *candidate &= matching;
+                } else {
+                    *candidate |= matching;
+                }
+            }
+        }
+
+        IndexFilter {
+            index,
+            prefix,
+            candidates,
+        }
+    }
+
+    // Whether `path` is indexed, unchanged since and can't match
+    // Anything unknown to the index is searched
+    pub fn skips(&self, root: &Path, path: &Path) -> bool {
+        let relative = match path.strip_prefix(root) {
+            Ok(r) => self.prefix.join(r),
+            Err(_) => return false,
+        };
+        let id = match self.index.ids.get(&relative) {
+            Some(&id) => id as usize,
+            None => return false,
+        };
+
+        let file = &self.index.files[id];
+        if !file.indexed || self.candidates[id] {
+            return false;
+        }
+
+        match fs::metadata(path) {
+            Ok(m) => {
+                m.len() == file.len
+                    && m.modified().ok().and_then(cache::nanos) == Some(file.modified)
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+fn write_u64<W: Write>(out: &mut W, mut value: u64) -> io::Result<()> {
+    // Seven bits at a time, the highest bit marks another byte
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return out.write_all(&[byte]);
+        }
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        input.read_exact(&mut byte)?;
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(invalid())
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid index")
+}
@@ -0,0 +1,36 @@
+use std::process;
+use std::sync::{Mutex, Once};
+
+use crate::context::Cancel;
+
+// Exit status of processes terminated by SIGINT
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+// Search cancelled by Ctrl-C, there's only one handler per process
+static CURRENT: Mutex<Option<Cancel>> = Mutex::new(None);
+static INSTALL: Once = Once::new();
+
+// Cancels `cancel` on Ctrl-C instead of terminating the process
+// while the guard is alive, a second Ctrl-C terminates immediately
+pub struct Guard;
+
+impl Guard {
+    pub fn new(cancel: Cancel) -> Guard {
+        INSTALL.call_once(|| {
+            // Without a handler Ctrl-C just terminates the process as before
+            let _ = ctrlc::set_handler(|| match &*CURRENT.lock().unwrap() {
+                Some(cancel) if !cancel.is_cancelled() => cancel.cancel(),
+                _ => process::exit(EXIT_INTERRUPTED),
+            });
+        });
+
+        *CURRENT.lock().unwrap() = Some(cancel);
+        Guard
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        *CURRENT.lock().unwrap() = None;
+    }
+}
@@ -0,0 +1,68 @@
+use std::fs;
+
+// Type of a directory entry, see `Search::kind`
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+    // The remaining kinds only exist on unix
+    Socket,
+    Pipe,
+    BlockDevice,
+    CharDevice,
+}
+
+impl FileKind {
+    pub fn of(file_type: &fs::FileType) -> FileKind {
+        if file_type.is_dir() {
+            return FileKind::Directory;
+        }
+        if file_type.is_symlink() {
+            return FileKind::Symlink;
+        }
+        special(file_type).unwrap_or(FileKind::File)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Directory => "directory",
+            FileKind::Symlink => "symlink",
+            FileKind::Socket => "socket",
+            FileKind::Pipe => "pipe",
+            FileKind::BlockDevice => "block device",
+            FileKind::CharDevice => "char device",
+        }
+    }
+
+    // Sockets, pipes and devices
+    pub fn is_special(self) -> bool {
+        !matches!(
+            self,
+            FileKind::File | FileKind::Directory | FileKind::Symlink
+        )
+    }
+}
+
+#[cfg(unix)]
+fn special(file_type: &fs::FileType) -> Option<FileKind> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_socket() {
+        Some(FileKind::Socket)
+    } else if file_type.is_fifo() {
+        Some(FileKind::Pipe)
+    } else if file_type.is_block_device() {
+        Some(FileKind::BlockDevice)
+    } else if file_type.is_char_device() {
+        Some(FileKind::CharDevice)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn special(_file_type: &fs::FileType) -> Option<FileKind> {
+    None
+}
@@ -1,262 +1,834 @@
-use std::env;
 use std::error::Error;
-
 use std::fs;
-use std::fs::File;
 use std::io;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
+use std::vec;
+
+mod app;
+mod archive;
+mod builder;
+mod cache;
+mod cli;
+mod content;
+mod context;
+mod decompress;
+mod duplicates;
+mod encoding;
+mod exec;
+mod explain;
+mod glob;
+mod hash;
+mod ignore;
+mod index;
+mod interrupt;
+mod kind;
+mod log;
+mod matcher;
+mod memory;
+mod multiline;
+mod output;
+mod perm;
+mod platform;
+mod printer;
+mod profile;
+mod progress;
+mod replace;
+mod rpc;
+mod serve;
+mod settings;
+mod sort;
+mod template;
+mod types;
+mod ui;
+mod walker;
+mod watch;
+
+pub use app::{app, completions, man};
+pub use builder::{NameMode, Search};
+pub use cli::build_index;
+pub use content::{BinaryFiles, CountMode, LongLines};
+pub use encoding::Encoding;
+pub use explain::{explain, why};
+pub use hash::HashAlgorithm;
+pub use index::IndexSummary;
+pub use interrupt::EXIT_INTERRUPTED;
+pub use kind::FileKind;
+pub use log::Level as LogLevel;
+pub use platform::Attribute;
+pub use printer::{Colors, ColumnMode, OutputFormat};
+use progress::PatternStats;
+pub use progress::Progress;
+pub use serve::serve;
+pub use sort::SortBy;
+pub use walker::Strategy;
+
+use cache::Cache;
+use context::{Cancel, Context, ErrorSender};
+use log::Log;
+use matcher::{ContentMatcher, FilenameMatcher};
+use memory::Memory;
+use printer::PathStyle;
+use profile::{Phase, Profile};
+
+// Paths and results queued per thread of `dop`, the walker and the workers
+// wait once their channel is full instead of buffering without bound
+const QUEUED_PATHS: usize = 256;
+const QUEUED_RESULTS: usize = 16;
 
 pub struct Config {
-    verbose: bool,
-    filename: Option<String>,
-    ignore_filename_case: bool,
-    content: Option<String>,
-    ignore_content_case: bool,
+    log: Log,
+    filename: Option<FilenameMatcher>,
+    content: Option<ContentMatcher>,
     dop: usize,
-    root: PathBuf,
+    // Default to `dop`
+    walk_threads: usize,
+    search_threads: usize,
+    // Never empty, a directory below several roots is walked only once
+    roots: Vec<PathBuf>,
+    // Roots that are files, searched on their own instead of walked
+    file_roots: Vec<bool>,
+    no_ignore: bool,
+    // Rules relative to the root, lower precedence than all others
+    ignore_files: Vec<PathBuf>,
+    hidden: bool,
+    max_depth: Option<usize>,
+    strategy: Strategy,
+    // Directories on other filesystems than the root are not descended into
+    one_file_system: bool,
+    // Sockets, pipes, devices and `/proc`, `/sys` and `/dev` are included
+    allow_special: bool,
+    // Attributes files have to have or must not have
+    attributes: Vec<(Attribute, bool)>,
+    // Content search gives up on files taking longer
+    file_timeout: Option<Duration>,
+    exclude: Vec<FilenameMatcher>,
+    exclude_dir: Vec<FilenameMatcher>,
+    // Files have to match any of these if not empty
+    file_types: Vec<FilenameMatcher>,
+    // Entries have to be any of these kinds, all but directories if empty
+    kinds: Vec<FileKind>,
+    // Only empty files and directories, which are included without kinds
+    empty: bool,
+    format: OutputFormat,
+    color: bool,
+    colors: Colors,
+    // Lines below a header per file instead of prefixed with the file
+    heading: bool,
+    path_style: PathStyle,
+    // File names in text output are terminal hyperlinks
+    hyperlink: bool,
+    byte_offset: bool,
+    only_matching: bool,
+    binary_files: BinaryFiles,
+    count: Option<CountMode>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    max_count: Option<usize>,
+    max_total: Option<usize>,
+    newer_than: Option<SystemTime>,
+    older_than: Option<SystemTime>,
+    follow: bool,
+    invert_match: bool,
+    multiline: bool,
+    replace: Option<replace::Replace>,
+    files_without_match: bool,
+    files_with_matches: bool,
+    archives: bool,
+    search_zip: bool,
+    encoding: Encoding,
+    files_from: Option<PathBuf>,
+    // Content of stdin is searched instead of the roots
+    stdin: bool,
+    progress: bool,
+    interactive: bool,
+    watch: bool,
+    sort: SortBy,
+    group_by_dir: bool,
+    stats: bool,
+    profile: bool,
+    // Names of the content patterns to count matches of, see `--pattern-stats`
+    pattern_stats: Option<Vec<String>>,
+    // Cache file and the options results depend on
+    cache: Option<(PathBuf, String)>,
+    // Listings kept in memory by `--serve`, unless `cache` is set
+    listings: Option<Arc<Cache>>,
+    // Files of the trigram index which can't match
+    index: Option<index::IndexFilter>,
+    // Takes precedence over `format`
+    template: Option<template::Template>,
+    quiet: bool,
+    max_line_length: Option<usize>,
+    // Bytes of paths, file buffers and results held at once
+    memory_limit: Option<u64>,
+    long_lines: LongLines,
+    max_columns: Option<usize>,
+    columns: printer::Columns,
+    // The filename pattern doesn't filter content search, files
+    // matching it are reported on their own
+    name_or_content: bool,
+    perm: Option<perm::Permissions>,
+    owner: Option<u32>,
+    group: Option<u32>,
+    duplicates: bool,
+    hash: Option<HashAlgorithm>,
+    // Matched files are passed to a command instead of being printed
+    exec: Option<Arc<exec::Exec>>,
+    // Matched files are printed with their whole content
+    cat: bool,
+    // `==> path <==` before the content of every file
+    cat_header: bool,
 }
 
 impl Config {
-    pub fn new(matches: &clap::ArgMatches) -> Result<Arc<Config>, Box<dyn Error>> {
-        let verbose = matches.is_present("verbose");
+    // Skipped entries are reported and what the search does is logged
+    fn verbose(&self) -> bool {
+        self.log.enabled(LogLevel::Info)
+    }
 
-        let ignore_filename_case = matches.is_present("ignore-filename-case");
+    // Content search only reports file names
+    fn lists_files(&self) -> bool {
+        self.files_with_matches || self.files_without_match
+    }
 
-        let filename = match matches.value_of("filename") {
-            Some(s) => {
-                if ignore_filename_case {
-                    Some(String::from(s).to_lowercase())
-                } else {
-                    Some(String::from(s))
-                }
-            }
-            None => None,
-        };
+    // The innermost root `path` is below, which the walk found it in and
+    // names are matched relative to
+    // Paths below none of them, e.g. of `files_from`, use the first one
+    // A file given as root is matched relative to its directory
+    fn root_of(&self, path: &Path) -> &Path {
+        let i = self.root_index(path);
+        match self.roots[i].parent().filter(|_| self.file_roots[i]) {
+            Some(dir) => dir,
+            None => &self.roots[i],
+        }
+    }
 
-        let ignore_content_case = matches.is_present("ignore-content-case");
+    fn root_index(&self, path: &Path) -> usize {
+        if self.roots.len() == 1 {
+            return 0;
+        }
 
-        let content = match matches.value_of("content") {
-            Some(s) => {
-                // If case-insensitive content comparison is requested
-                // convert for the whole program lifetime
-                if ignore_content_case {
-                    Some(String::from(s).to_lowercase())
-                } else {
-                    Some(String::from(s))
-                }
-            }
-            None => None,
+        (0..self.roots.len())
+            .filter(|&i| path.starts_with(&self.roots[i]))
+            .max_by_key(|&i| self.roots[i].components().count())
+            .unwrap_or(0)
+    }
+}
+
+// A file found by the search, at most one per file and entry of an archive
+// `lines` is `None` in file search, count and file listing modes
+#[derive(Clone)]
+pub struct FileMatch {
+    // Entries of archives are reported as `archive.zip!path/inside.txt`
+    pub path: PathBuf,
+    pub lines: Option<Vec<LineMatch>>,
+    // Matching lines or matches, depending on `CountMode`
+    pub count: usize,
+    // Binary files only contain their first match, see `BinaryFiles::List`
+    pub binary: bool,
+    // The file name matched on its own, see `Search::name_or_content`
+    pub name_matched: bool,
+    // Hex digest of the content in file search, see `Search::hash`
+    pub digest: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct LineMatch {
+    // 1-based line number
+    pub line: usize,
+    // Byte range of the match within `content`, printed as configured by
+    // `Search::column_mode` and `Search::tab_width`
+    pub column: usize,
+    pub end: usize,
+    // Byte offset of the match within the file, or within
+    // the converted text for files not encoded in UTF-8
+    pub offset: usize,
+    pub content: String,
+}
+
+// A file or directory that couldn't be searched
+pub struct SearchError {
+    pub path: PathBuf,
+    pub error: io::Error,
+    // The root itself couldn't be read, other errors only skip a single entry
+    pub fatal: bool,
+}
+
+// Result of `run`
+pub struct Summary {
+    // At least one file was reported
+    pub matched: bool,
+    // Number of files and directories that couldn't be searched
+    pub errors: usize,
+    // Stopped by Ctrl-C, the results found until then were printed
+    pub interrupted: bool,
+}
+
+// Iterator over the results of a search
+// Content search runs on worker threads, results are yielded as they arrive
+pub struct SearchResults {
+    config: Arc<Config>,
+    source: ResultSource,
+    errors: mpsc::Receiver<SearchError>,
+    context: Context,
+    // Matches yielded so far, for `max_total`
+    total: usize,
+    // Walker and workers, joined once all results are received
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl SearchResults {
+    // Counters updated while the search is running
+    pub fn progress(&self) -> &Arc<Progress> {
+        &self.context.progress
+    }
+
+    // Stops the walker and the workers early, results already
+    // found can still be received
+    pub fn cancel(&self) {
+        self.context.cancel.cancel();
+    }
+
+    // Applies `max_total` to a result, returns `None` once it's reached
+    fn limit(&mut self, mut result: FileMatch) -> Option<FileMatch> {
+        let max = match self.config.max_total {
+            Some(max) => max,
+            None => return Some(result),
         };
 
-        let dop = match matches.value_of("dop") {
-            Some(s) => String::from(s),
-            None => num_cpus::get().to_string(),
+        if self.total >= max {
+            return None;
+        }
+
+        // Listed files count as a single match
+        let matches = if self.config.lists_files() {
+            1
+        } else {
+            result.count
         };
 
-        let dop = match dop.parse::<usize>() {
-            Ok(dop) => dop,
-            Err(_) => {
-                return Err(Box::new(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "invalid degree of parallelism",
-                )));
+        let remaining = max - self.total;
+        if matches >= remaining {
+            if let Some(lines) = &mut result.lines {
+                lines.truncate(remaining);
+            }
+            if !self.config.lists_files() {
+                result.count = remaining;
             }
-        };
 
-        let root = match matches.value_of("root") {
-            Some(s) => {
-                let path = PathBuf::from(s);
-                if !path.is_dir() {
-                    return Err(Box::new(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "working directory is not a directory",
-                    )));
-                }
+            self.total = max;
+            self.cancel();
+        } else {
+            self.total += matches;
+        }
+
+        Some(result)
+    }
+
+    // Errors reported so far, doesn't wait for the search to finish
+    pub fn errors(&self) -> mpsc::TryIter<'_, SearchError> {
+        self.errors.try_iter()
+    }
+
+    // Waits for the search to finish and returns all remaining errors
+    pub fn remaining_errors(mut self) -> vec::IntoIter<SearchError> {
+        self.join();
+        let errors: Vec<_> = self.errors.try_iter().collect();
+        errors.into_iter()
+    }
+
+    fn join(&mut self) {
+        if self.threads.is_empty() {
+            return;
+        }
 
-                path
+        // Threads waiting for room in a full channel only finish once it's
+        // emptied, results not taken yet are dropped
+        match &self.source {
+            ResultSource::Files(files) => files.iter().for_each(drop),
+            ResultSource::Content(receiver) | ResultSource::Hashed(receiver) => {
+                receiver.iter().for_each(drop)
             }
-            None => env::current_dir()?,
-        };
+        }
+        for thread in self.threads.drain(..) {
+            // A panicking worker only loses its current file
+            let _ = thread.join();
+        }
 
-        Ok(Arc::new(Config {
-            verbose,
-            filename,
-            ignore_filename_case,
-            content,
-            ignore_content_case,
-            dop,
-            root,
-        }))
+        // Entries not seen because the search stopped early are still valid
+        if let Some(cache) = &self.context.cache {
+            if let Err(err) = cache.save(self.context.cancel.is_cancelled()) {
+                let path = &self.config.cache.as_ref().unwrap().0;
+                self.context.errors.skip(path, err);
+            }
+        }
     }
 }
 
-struct LpsResult {
-    file: String,
-    lines: Option<Vec<LpsLineResult>>,
+enum ResultSource {
+    // Bounded, see `QUEUED_PATHS` and `QUEUED_RESULTS`
+    Files(mpsc::Receiver<PathBuf>),
+    Content(mpsc::Receiver<FileMatch>),
+    // Every hashed file is a result
+    Hashed(mpsc::Receiver<FileMatch>),
 }
 
-struct LpsLineResult {
-    line: usize,
-    column: usize,
-    content: String,
-}
+impl Iterator for SearchResults {
+    type Item = FileMatch;
 
-pub fn run(config: Arc<Config>) -> Result<(), Box<dyn Error>> {
-    if config.verbose {
-        let root_path = config.root.to_str();
-        if root_path.is_none() {
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "invalid working directory",
-            )));
+    fn next(&mut self) -> Option<FileMatch> {
+        match &mut self.source {
+            ResultSource::Files(files) => {
+                if let Ok(path) = files.recv() {
+                    if let Some(memory) = &self.context.memory {
+                        memory.paths.release(memory::path_size(&path));
+                    }
+                    return Some(FileMatch {
+                        path,
+                        lines: None,
+                        count: 0,
+                        binary: false,
+                        name_matched: false,
+                        digest: None,
+                    });
+                }
+
+                self.join();
+                None
+            }
+            ResultSource::Hashed(receiver) => {
+                if let Ok(result) = receiver.recv() {
+                    return Some(result);
+                }
+
+                self.join();
+                None
+            }
+            ResultSource::Content(receiver) => {
+                // recv fails when all threads have finished
+                while let Ok(result) = receiver.recv() {
+                    if let Some(memory) = &self.context.memory {
+                        memory.data.release(memory::result_size(&result));
+                    }
+                    let matched = if self.config.files_without_match {
+                        result.count == 0
+                    } else {
+                        result.count > 0 || result.name_matched
+                    };
+
+                    if matched {
+                        return self.limit(result);
+                    }
+                }
+
+                self.join();
+                None
+            }
         }
+    }
+}
 
-        println!("working directory: {}", root_path.unwrap());
-        println!("DoP was set to {} threads", config.dop);
+// Dropping the results early stops the search, no threads are left behind
+impl Drop for SearchResults {
+    fn drop(&mut self) {
+        if !self.threads.is_empty() {
+            self.cancel();
+            self.join();
+        }
     }
+}
 
+// Starts the search in the background, results stream in while
+// the tree is still being walked
+pub fn search(config: Arc<Config>) -> SearchResults {
+    start(config, None)
+}
+
+// Only searches `paths` instead of walking the tree if given
+fn start(config: Arc<Config>, paths: Option<Vec<PathBuf>>) -> SearchResults {
     // Get all files that match name, size, attributes, ...
-    let files = find_files_by_name(&config, &config.root);
+    let (file_sender, files) = mpsc::sync_channel::<PathBuf>(config.dop * QUEUED_PATHS);
+    let (error_sender, errors) = mpsc::channel::<SearchError>();
+    let context = Context {
+        errors: ErrorSender(error_sender),
+        progress: Arc::new(Progress::default()),
+        profile: Arc::new(Profile::new(config.profile)),
+        patterns: Arc::new(PatternStats::new(
+            config.pattern_stats.clone().unwrap_or_default(),
+        )),
+        cache: config
+            .cache
+            .as_ref()
+            .map(|(path, key)| Arc::new(Cache::load(path.clone(), key)))
+            .or_else(|| config.listings.clone()),
+        memory: config
+            .memory_limit
+            .map(|limit| Arc::new(Memory::new(limit))),
+        cancel: Cancel::default(),
+        started: Instant::now(),
+    };
 
-    if config.content.is_some() {
+    // Nothing is walked, stdin is the only file
+    if config.stdin {
+        let (sender, receiver) = mpsc::sync_channel::<FileMatch>(1);
+        let thread = content::stdin_search(&config, sender, context.clone());
+        return SearchResults {
+            config,
+            source: ResultSource::Content(receiver),
+            errors,
+            context,
+            total: 0,
+            threads: vec![thread],
+        };
+    }
+
+    let walker_config = config.clone();
+    let walker_context = context.clone();
+    let count_files = config.content.is_none();
+    let walker = log::spawn(String::from("walk"), move || {
+        let progress = &walker_context.progress;
+        let found = |path: PathBuf| {
+            // Found files are counted by the workers in content search
+            if count_files {
+                progress.add_file();
+            }
+            // Waits while too many paths are queued
+            if let Some(memory) = &walker_context.memory {
+                memory
+                    .paths
+                    .acquire(memory::path_size(&path), &walker_context.cancel);
+            }
+            file_sender.send(path).is_ok()
+        };
+        match (paths, &walker_config.files_from) {
+            (Some(paths), _) => walker::filter_paths(&walker_config, paths, &walker_context, found),
+            (None, Some(list)) => {
+                walker::read_file_list(&walker_config, list, &walker_context, found)
+            }
+            (None, None) => walker::find_files_by_name(
+                &walker_config,
+                &walker_context,
+                walker_config.walk_threads,
+                found,
+            ),
+        }
+        progress.walk_finished(walker_context.started.elapsed());
+    });
+
+    let mut threads = vec![walker];
+    let source = if config.content.is_some() {
         // Check content in multiple threads
-        let (sender, receiver) = mpsc::channel::<LpsResult>();
+        let (sender, receiver) = mpsc::sync_channel::<FileMatch>(config.dop * QUEUED_RESULTS);
+        threads.extend(content::content_search(
+            &config,
+            files,
+            sender,
+            context.clone(),
+        ));
+        ResultSource::Content(receiver)
+    } else if config.hash.is_some() {
+        let (sender, receiver) = mpsc::sync_channel::<FileMatch>(config.dop * QUEUED_RESULTS);
+        threads.extend(hash::hash_files(&config, files, sender, context.clone()));
+        ResultSource::Hashed(receiver)
+    } else {
+        ResultSource::Files(files)
+    };
 
-        content_search(&config, files, sender);
+    SearchResults {
+        config,
+        source,
+        errors,
+        context,
+        total: 0,
+        threads,
+    }
+}
 
-        // Aggregate results
-        loop {
-            let result = match receiver.recv() {
-                Ok(res) => res,
-                Err(_) => {
-                    // This will occur when all threads have finished
-                    break;
-                }
-            };
+// Fatal errors are always printed, skipped entries only in verbose mode
+// Nothing is printed in quiet mode
+fn report(config: &Config, error: &SearchError) {
+    if config.quiet {
+        return;
+    }
 
-            assert!(result.lines.is_some());
-            let lines = result.lines.unwrap();
-            if !lines.is_empty() {
-                println!("{}", result.file);
-                for line in lines {
-                    println!("  {}:{} {}", line.line, line.column, line.content);
-                }
+    if error.fatal {
+        eprintln!("error: {}: {}", platform::display(&error.path), error.error);
+    } else if config.verbose() {
+        eprintln!(
+            "warning: {}: {}",
+            platform::display(&error.path),
+            error.error
+        );
+    }
+}
+
+fn print(
+    config: &Config,
+    printer: &mut dyn printer::ResultPrinter,
+    result: &FileMatch,
+    summary: &mut Summary,
+) -> io::Result<()> {
+    if config.quiet {
+        return Ok(());
+    }
+
+    if let Some(replace) = &config.replace {
+        match replace::rewrite(config, replace, &result.path) {
+            Ok(changes) => printer.changes(&result.path, &changes)?,
+            Err(error) => {
+                let error = SearchError {
+                    path: result.path.clone(),
+                    error,
+                    fatal: true,
+                };
+                report(config, &error);
+                summary.errors += 1;
             }
         }
-    } else {
-        // Just yield results
-        for file in files {
-            println!("{}", file.to_string_lossy());
+    } else if config.count.is_some() {
+        printer.count(result)?;
+    } else if config.cat {
+        if let Err(error) = cat(config, &result.path)? {
+            let error = SearchError {
+                path: result.path.clone(),
+                error,
+                fatal: true,
+            };
+            report(config, &error);
+            summary.errors += 1;
         }
+    } else if let Some(digest) = &result.digest {
+        printer.digest(&result.path, digest)?;
+    } else if result.lines.is_some() {
+        printer.matches(result)?;
+    } else {
+        printer.file(&result.path)?;
     }
 
     Ok(())
 }
 
-fn find_files_by_name(config: &Config, path: &PathBuf) -> Vec<PathBuf> {
-    let mut result = Vec::new();
+// Copies the content of a file to stdout, the inner error is
+// from reading the file, the outer one from writing stdout
+fn cat(config: &Config, path: &Path) -> io::Result<io::Result<()>> {
+    let mut file = match fs::File::open(path) {
+        Ok(f) => f,
+        Err(err) => return Ok(Err(err)),
+    };
+
+    let mut out = io::stdout().lock();
+    if config.cat_header {
+        let paths = printer::Paths::new(&config.path_style);
+        writeln!(out, "==> {} <==", paths.show(path))?;
+    }
 
-    let dir = match fs::read_dir(&path) {
-        Ok(d) => d,
-        Err(err) => {
-            eprintln!("{}", err);
-            return result;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut last = b'\n';
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Ok(Err(err)),
+        };
+        out.write_all(&buffer[..read])?;
+        last = buffer[read - 1];
+    }
+
+    // The next header starts on a line of its own
+    if config.cat_header && last != b'\n' {
+        writeln!(out)?;
+    }
+
+    Ok(Ok(()))
+}
+
+pub fn run(config: Arc<Config>) -> Result<Summary, Box<dyn Error>> {
+    for root in &config.roots {
+        config.log.write(LogLevel::Info, || {
+            format!("searching {}", platform::display(root))
+        });
+    }
+    config.log.write(LogLevel::Info, || {
+        format!(
+            "{} walk and {} search threads",
+            config.walk_threads, config.search_threads
+        )
+    });
+
+    if config.interactive {
+        let (found, errors) = ui::browse(search(config.clone()))?;
+        for error in &errors {
+            report(&config, error);
         }
+
+        return Ok(Summary {
+            matched: found > 0,
+            errors: errors.len(),
+            interrupted: false,
+        });
+    }
+
+    let mut summary = Summary {
+        matched: false,
+        errors: 0,
+        interrupted: false,
     };
+    // A reader closing the pipe early, like `lps -c error | head`, only
+    // stops the search, what matched so far decides the exit code
+    match print_results(config, &mut summary) {
+        Err(err) if is_broken_pipe(&*err) => Ok(summary),
+        Err(err) => Err(err),
+        Ok(()) => Ok(summary),
+    }
+}
 
-    for entry in dir {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(err) => {
-                eprintln!("{}", err);
-                continue;
-            }
-        };
+// Whether writing failed since the reader of stdout went away
+pub fn is_broken_pipe(error: &(dyn Error + 'static)) -> bool {
+    error
+        .downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+}
+
+// Searches and prints the results of `run` while they are found
+fn print_results(config: Arc<Config>, summary: &mut Summary) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    let output = output::Printer::start();
+    let mut printer = printer::new_printer(&config, output.output());
+
+    let mut results = search(config.clone());
+    let cancel = results.context.cancel.clone();
+    let progress = results.progress().clone();
+    let profile = results.context.profile.clone();
+    let patterns = results.context.patterns.clone();
+    let guard = interrupt::Guard::new(cancel.clone());
+    let reporter = if config.progress {
+        Some(progress::Reporter::start(results.progress().clone()))
+    } else {
+        None
+    };
+
+    let mut executor = config
+        .exec
+        .clone()
+        .map(|exec| exec::Executor::start(exec, config.search_threads, cancel.clone()));
 
-        let path = entry.path();
+    // Matches of the initial search aren't reported again when watching
+    let mut seen = watch::Seen::default();
+    // Sorted and grouped results can only be printed once all of them are known
+    let mut buffered = Vec::new();
 
-        if path.is_dir() {
-            result.append(&mut find_files_by_name(&config, &path));
-            continue;
+    // Results of every root, reported in verbose mode
+    let mut per_root = vec![0; config.roots.len()];
+
+    while let Some(result) = results.next() {
+        summary.matched = true;
+        per_root[config.root_index(&result.path)] += 1;
+
+        if config.watch {
+            seen.record(&result);
+        }
+        if let Some(executor) = &mut executor {
+            executor.push(result.path);
+        } else if config.sort == SortBy::None && !config.group_by_dir && !config.duplicates {
+            let printing = profile.start();
+            print(&config, &mut *printer, &result, summary)?;
+            profile.add(Phase::Print, printing);
+        } else {
+            buffered.push(result);
         }
 
-        if let Some(search) = &config.filename {
-            let mut file_name = String::from(path.to_string_lossy());
-            if config.ignore_filename_case {
-                file_name = file_name.to_lowercase();
-            }
-            if file_name.contains(search) {
-                result.push(path);
+        for error in results.errors() {
+            report(&config, &error);
+            summary.errors += 1;
+        }
+    }
+
+    if config.duplicates {
+        let groups = duplicates::find(&config, &results.context, buffered);
+        summary.matched = !groups.is_empty();
+        if !config.quiet {
+            for group in &groups {
+                printer.duplicates(group)?;
             }
-        } else {
-            result.push(path);
         }
+        buffered = Vec::new();
     }
 
-    result
-}
+    sort::sort(&mut buffered, &config.sort);
+    if config.group_by_dir {
+        // Files keep their order within a directory
+        buffered.sort_by(|a, b| a.path.parent().cmp(&b.path.parent()));
+    }
 
-fn content_search(config: &Arc<Config>, files: Vec<PathBuf>, sender: mpsc::Sender<LpsResult>) {
-    assert!(config.content.is_some());
-    for chunk in files.chunks(files.len() / config.dop) {
-        let config = config.clone();
-        let sender = sender.clone();
-        let chunk = chunk.to_vec();
-
-        thread::spawn(move || {
-            for file in chunk {
-                let file_path = file.to_string_lossy().to_string();
-                let file = match File::open(file) {
-                    Ok(f) => f,
-                    Err(_) => {
-                        continue;
-                    }
-                };
+    let mut directory = None;
+    for result in &buffered {
+        if config.group_by_dir && directory != result.path.parent() {
+            directory = result.path.parent();
+            printer.directory(directory.unwrap_or_else(|| Path::new("")))?;
+        }
+        let printing = profile.start();
+        print(&config, &mut *printer, result, summary)?;
+        profile.add(Phase::Print, printing);
+    }
 
-                let mut found_lines = Vec::new();
-                for (i, line) in BufReader::new(file).lines().enumerate() {
-                    let line = match line {
-                        Ok(l) => l,
-                        Err(_) => {
-                            continue;
-                        }
-                    };
+    if let Some(executor) = executor {
+        for error in executor.finish() {
+            report(&config, &error);
+            summary.errors += 1;
+        }
+    }
 
-                    if config.ignore_content_case {
-                        let comp_line = line.to_lowercase();
-                        if let Some(pos) = comp_line.find(config.content.as_ref().unwrap()) {
-                            found_lines.push(LpsLineResult {
-                                line: i + 1,
-                                column: pos,
-                                content: line,
-                            });
-                        }
-                    } else {
-                        if let Some(pos) = line.find(config.content.as_ref().unwrap()) {
-                            found_lines.push(LpsLineResult {
-                                line: i + 1,
-                                column: pos,
-                                content: line,
-                            });
-                        }
-                    }
-                }
+    // `max_total` cancels as well, but isn't an interruption
+    let limited = config.max_total.is_some_and(|max| results.total >= max);
 
-                if sender
-                    .send(LpsResult {
-                        file: file_path,
-                        lines: Some(found_lines),
-                    })
-                    .is_err()
-                {
-                    break;
-                }
-            }
-        });
+    for error in results.remaining_errors() {
+        report(&config, &error);
+        summary.errors += 1;
+    }
+
+    if config.verbose() && config.roots.len() > 1 {
+        // Printed after the results
+        output.output().flush()?;
+        for (root, results) in config.roots.iter().zip(&per_root) {
+            config.log.write(LogLevel::Info, || {
+                format!("root {}: {} results", platform::display(root), results)
+            });
+        }
+    }
+
+    if let Some(reporter) = reporter {
+        reporter.finish();
+    }
+
+    if config.stats {
+        printer.stats(&progress, summary.errors, started.elapsed())?;
     }
+
+    if config.profile {
+        profile.report(&mut io::stderr().lock(), started.elapsed())?;
+    }
+
+    if patterns.enabled() {
+        output.output().flush()?;
+        patterns.report(&mut io::stderr().lock())?;
+    }
+
+    if config.watch && !cancel.is_cancelled() {
+        watch::watch(&config, &mut *printer, seen, &cancel, summary)?;
+    }
+
+    output.finish()?;
+    if cancel.is_cancelled() && !limited {
+        summary.interrupted = true;
+        eprintln!("interrupted: {}", progress.status());
+    }
+    drop(guard);
+
+    Ok(())
 }
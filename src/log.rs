@@ -0,0 +1,111 @@
+use std::thread;
+use std::time::Instant;
+
+// Verbosity of the log on stderr, every level includes the ones before
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Off,
+    // What the search does as a whole, roots, threads and skipped entries
+    Info,
+    // Why entries are skipped, e.g. ignored, binary or cached
+    Debug,
+    // What every thread works on and waits for
+    Trace,
+}
+
+impl Level {
+    // Number of times `-v` was given
+    pub fn from_count(count: u64) -> Level {
+        match count {
+            0 => Level::Off,
+            1 => Level::Info,
+            2 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+
+    // A name like `debug` or the number of `-v`
+    pub fn parse(text: &str) -> Option<Level> {
+        match text.to_ascii_lowercase().as_str() {
+            "off" => Some(Level::Off),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            n => n.parse().ok().map(Level::from_count),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Off => "off",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+// Writes lines like `[   0.012 debug walk-1] ./target: ignored` to stderr,
+// with the seconds since the configuration was built and the thread
+// Lines are written at once, so threads don't interleave within a line
+#[derive(Clone, Copy)]
+pub struct Log {
+    level: Level,
+    started: Instant,
+}
+
+impl Log {
+    pub fn new(level: Level) -> Log {
+        Log {
+            level,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn enabled(&self, level: Level) -> bool {
+        level != Level::Off && level <= self.level
+    }
+
+    // The message is only formatted if the level is enabled
+    pub fn write<F: FnOnce() -> String>(&self, level: Level, message: F) {
+        if !self.enabled(level) {
+            return;
+        }
+
+        let current = thread::current();
+        eprintln!(
+            "[{:>8.3} {} {}] {}",
+            self.started.elapsed().as_secs_f64(),
+            level.name(),
+            current.name().unwrap_or("thread"),
+            message()
+        );
+    }
+}
+
+// Threads are named like `walk-0`, so their lines can be told apart
+pub fn spawn<F, T>(name: String, f: F) -> thread::JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    thread::Builder::new()
+        .name(name)
+        .spawn(f)
+        .expect("failed to spawn thread")
+}
+
+pub fn spawn_scoped<'scope, 'env, F, T>(
+    scope: &'scope thread::Scope<'scope, 'env>,
+    name: String,
+    f: F,
+) -> thread::ScopedJoinHandle<'scope, T>
+where
+    F: FnOnce() -> T + Send + 'scope,
+    T: Send + 'scope,
+{
+    thread::Builder::new()
+        .name(name)
+        .spawn_scoped(scope, f)
+        .expect("failed to spawn thread")
+}
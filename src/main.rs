@@ -1,70 +1,203 @@
-use clap::{App, Arg};
-use lps::Config;
+use std::env;
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::App;
+use lps::{app, Config};
 
 fn main() {
-    let matches = App::new("lps")
-        .version("1.0.1")
-        .author("Loris Leitner (Loris156)")
-        .about("High-speed parallelized searching")
-        .arg(
-            Arg::with_name("filename")
-                .short("n")
-                .long("name")
-                .value_name("FILENAME")
-                .help("Filename pattern")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("ignore-filename-case")
-                .short("b")
-                .long("ignore-filename-case")
-                .requires("filename")
-                .help("Ignores casing of filename"),
-        )
-        .arg(
-            Arg::with_name("content")
-                .short("c")
-                .long("content")
-                .value_name("TEXT")
-                .help("File content")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("ignore-content-case")
-                .short("x")
-                .long("ignore-content-case")
-                .requires("content")
-                .help("Ignores casing of content"),
-        )
-        .arg(
-            Arg::with_name("dop")
-                .short("d")
-                .long("dop")
-                .value_name("THREAD COUNT")
-                .requires("content")
-                .help("Degree of parallelism (defaults to logical core count)")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("verbose")
-                .short("v")
-                .long("verbose")
-                .help("Enable verbose output"),
-        )
-        .arg(
-            Arg::with_name("root")
-                .help("Root search directory")
-                .value_name("ROOT"),
-        )
-        .get_matches();
+    let mut args: Vec<_> = std::env::args_os().collect();
+    if args.get(1).is_some_and(|a| a == "completions") {
+        return completions(&args[2..]);
+    }
+    if args.get(1).is_some_and(|a| a == "man") {
+        if let Err(e) = lps::man(&mut io::stdout().lock()) {
+            fail(&e);
+        }
+        return;
+    }
+    if args.get(1).is_some_and(|a| a == "index") {
+        match args.get(2).and_then(|a| a.to_str()) {
+            Some("build") => {
+                args.drain(1..3);
+                return build_index(args);
+            }
+            // Searches like `lps --index`
+            Some("query") => {
+                args.splice(1..3, [OsString::from("--index")]);
+            }
+            _ => {
+                eprintln!("usage: lps index build|query [OPTIONS]");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let matches = parse(app(), args);
 
     let config = Config::new(&matches).unwrap_or_else(|e| {
         eprintln!("error: {}", e);
-        std::process::exit(1);
+        std::process::exit(2);
     });
 
-    lps::run(config).unwrap_or_else(|e| {
+    if matches.is_present("explain") {
+        if let Err(e) = lps::explain(&config, &mut io::stdout().lock()) {
+            fail(&e);
+        }
+        std::process::exit(0);
+    }
+
+    // Like the search itself, 0 if all paths would be found and 1 if not
+    if let Some(paths) = matches.values_of_os("why") {
+        let paths: Vec<PathBuf> = paths.map(PathBuf::from).collect();
+        match lps::why(&config, &paths, &mut io::stdout().lock()) {
+            Ok(searched) => std::process::exit(if searched { 0 } else { 1 }),
+            Err(e) => fail(&e),
+        }
+    }
+
+    if let Some(address) = matches.value_of("serve") {
+        let mut roots: Vec<&OsStr> = matches
+            .values_of_os("root")
+            .into_iter()
+            .flatten()
+            .chain(matches.values_of_os("roots").into_iter().flatten())
+            .collect();
+        // Requests without a root search the working directory, never
+        // the stdin of the server
+        let working_dir = env::current_dir().unwrap_or_default();
+        if roots.is_empty() {
+            roots.push(working_dir.as_os_str());
+        }
+        // Only returns once stdin is closed with `--serve -`
+        let served = lps::serve(config, address, |request| request_config(request, &roots));
+        if let Err(e) = served {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+        std::process::exit(0);
+    }
+
+    let summary = lps::run(config).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(2);
+    });
+
+    // Like grep: 0 if anything matched, 1 if nothing matched, 2 on errors
+    // and 130 like a process terminated by SIGINT
+    // A match in quiet mode succeeds despite errors
+    let quiet = matches.is_present("quiet");
+    let code = if summary.interrupted {
+        lps::EXIT_INTERRUPTED
+    } else if summary.matched && quiet {
+        0
+    } else if summary.errors > 0 {
+        2
+    } else if summary.matched {
+        0
+    } else {
+        1
+    };
+    std::process::exit(code);
+}
+
+// Exits with 2 after printing the error, unless the reader of stdout went
+// away early like with `lps man | head`, which isn't an error
+fn fail(error: &(dyn Error + 'static)) -> ! {
+    if lps::is_broken_pipe(error) {
+        std::process::exit(0);
+    }
+
+    eprintln!("error: {}", error);
+    std::process::exit(2);
+}
+
+fn parse(app: App<'static, 'static>, args: Vec<OsString>) -> clap::ArgMatches<'static> {
+    app.get_matches_from_safe(args).unwrap_or_else(|e| {
+        // Help and version aren't errors
+        if !e.use_stderr() {
+            println!("{}", e.message);
+            std::process::exit(0);
+        }
+
+        eprintln!("{}", e.message);
+        std::process::exit(2);
+    })
+}
+
+// `lps completions SHELL [--type-add DEFINITION]...` prints the script
+fn completions(args: &[OsString]) {
+    let usage = || -> ! {
+        eprintln!("usage: lps completions bash|zsh|fish|powershell [--type-add DEFINITION]...");
+        std::process::exit(2);
+    };
+
+    let args: Vec<&str> = args
+        .iter()
+        .map(|a| a.to_str().unwrap_or_else(|| usage()))
+        .collect();
+    let (shell, mut rest) = match args.split_first() {
+        Some((shell, rest)) => (*shell, rest),
+        None => usage(),
+    };
+
+    let mut definitions = Vec::new();
+    while let Some((flag, tail)) = rest.split_first() {
+        match (*flag, tail.split_first()) {
+            ("--type-add", Some((definition, tail))) => {
+                definitions.push(String::from(*definition));
+                rest = tail;
+            }
+            _ => usage(),
+        }
+    }
+
+    if let Err(e) = lps::completions(shell, &definitions, &mut io::stdout().lock()) {
+        fail(&*e);
+    }
+}
+
+// `lps index build [OPTIONS] [ROOT]`, the options select the files like a
+// file search
+fn build_index(args: Vec<OsString>) {
+    let matches = parse(app().name("lps index build"), args);
+    let summary = lps::build_index(&matches).unwrap_or_else(|e| {
         eprintln!("error: {}", e);
-        std::process::exit(1);
+        std::process::exit(2);
     });
+
+    println!(
+        "indexed {} files with {} trigrams in {}",
+        summary.files,
+        summary.trigrams,
+        summary.path.display()
+    );
+    std::process::exit(if summary.errors > 0 { 2 } else { 0 });
+}
+
+// Parses the arguments of a `--serve` request, searches the roots of the
+// server unless others are given
+fn request_config(request: &[String], roots: &[&OsStr]) -> Result<Arc<Config>, Box<dyn Error>> {
+    let mut args = vec![OsString::from("lps")];
+    args.extend(request.iter().map(OsString::from));
+
+    let mut matches = app().get_matches_from_safe(&args)?;
+    if !matches.is_present("root") && !matches.is_present("roots") {
+        for root in roots {
+            args.push(OsString::from("--root"));
+            args.push(OsString::from(root));
+        }
+        matches = app().get_matches_from_safe(&args)?;
+    }
+    if matches.is_present("serve") {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a request can't start another server",
+        )));
+    }
+
+    Config::new(&matches)
 }
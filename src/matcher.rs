@@ -0,0 +1,659 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::io;
+use std::path::Path;
+
+use aho_corasick::{AhoCorasick, Input, MatchKind};
+use memchr::memmem;
+use regex::{bytes, Regex, RegexBuilder};
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+use crate::glob;
+
+// Combines one or more content patterns
+// With `all` a file only matches if every pattern occurs somewhere in it,
+// otherwise any pattern is enough
+pub struct ContentMatcher {
+    patterns: Vec<Pattern>,
+    all: bool,
+    // Multiple literals are searched at once by a single automaton
+    // instead of scanning for every pattern on its own
+    automaton: Option<AhoCorasick>,
+}
+
+impl ContentMatcher {
+    pub fn new(patterns: Vec<Pattern>, all: bool) -> ContentMatcher {
+        let literals: Vec<&str> = patterns
+            .iter()
+            .filter_map(|p| match p {
+                Pattern::Literal { text, .. } if !text.is_empty() => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        // Leftmost-longest is how a match is picked among separate patterns
+        let automaton = if patterns.len() > 1 && literals.len() == patterns.len() {
+            AhoCorasick::builder()
+                .match_kind(MatchKind::LeftmostLongest)
+                .build(&literals)
+                .ok()
+        } else {
+            None
+        };
+
+        ContentMatcher {
+            patterns,
+            all,
+            automaton,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    // Files have to be checked for every pattern
+    pub fn requires_all(&self) -> bool {
+        self.all && self.patterns.len() > 1
+    }
+
+    // Returns the byte range of the leftmost match of any pattern
+    pub fn find(&self, line: &str) -> Option<(usize, usize)> {
+        if let Some(automaton) = &self.automaton {
+            return automaton.find(line).map(|m| (m.start(), m.end()));
+        }
+
+        self.patterns
+            .iter()
+            .filter_map(|p| p.find(line))
+            .min_by_key(|&(start, end)| (start, usize::MAX - end))
+    }
+
+    // Returns the byte ranges of all non-overlapping matches of any pattern
+    pub fn find_all(&self, line: &str) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        self.each_match(line, |start, end| spans.push((start, end)));
+        spans
+    }
+
+    // Calls `found` for the matches `find_all` returns, left to right
+    fn each_match<F: FnMut(usize, usize)>(&self, line: &str, mut found: F) {
+        let mut pos = 0;
+
+        while pos <= line.len() {
+            let (start, end) = match self.find_at(line, pos) {
+                Some(s) => s,
+                None => break,
+            };
+            found(start, end);
+
+            // Empty matches would be found again, skip to the next char
+            pos = if end > start {
+                end
+            } else {
+                match line[end..].chars().next() {
+                    Some(c) => end + c.len_utf8(),
+                    None => break,
+                }
+            };
+        }
+    }
+
+    fn find_at(&self, line: &str, pos: usize) -> Option<(usize, usize)> {
+        if let Some(automaton) = &self.automaton {
+            return automaton
+                .find(Input::new(line).range(pos..))
+                .map(|m| (m.start(), m.end()));
+        }
+
+        self.pattern_at(line, pos)
+            .map(|(_, start, end)| (start, end))
+    }
+
+    // Like `find_at` with the index of the pattern that matched
+    fn pattern_at(&self, line: &str, pos: usize) -> Option<(usize, usize, usize)> {
+        self.patterns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.find_at(line, pos).map(|(s, e)| (i, s, e)))
+            .min_by_key(|&(_, start, end)| (start, usize::MAX - end))
+    }
+
+    // Replaces the matches of every pattern, returns `None` if nothing matched
+    // With `expand` groups like `$1` are expanded for regex patterns
+    // Matches are the ones `find_all` reports, replaced in a single pass
+    // over the line so replacements are never matched again
+    pub fn replace_all(&self, line: &str, replacement: &str, expand: bool) -> Option<String> {
+        if let Some(automaton) = &self.automaton {
+            automaton.find(line)?;
+            let mut replaced = String::with_capacity(line.len());
+            automaton.replace_all_with(line, &mut replaced, |_, _, dst| {
+                dst.push_str(replacement);
+                true
+            });
+            return Some(replaced);
+        }
+
+        let mut replaced = String::with_capacity(line.len());
+        let mut copied = 0;
+        let mut pos = 0;
+        let mut matched = false;
+        while pos <= line.len() {
+            let (index, start, end) = match self.pattern_at(line, pos) {
+                Some(m) => m,
+                None => break,
+            };
+            matched = true;
+            replaced.push_str(&line[copied..start]);
+            self.patterns[index].expand(line, start, replacement, expand, &mut replaced);
+            copied = end;
+
+            // Empty matches would be found again, skip to the next char
+            pos = if end > start {
+                end
+            } else {
+                match line[end..].chars().next() {
+                    Some(c) => end + c.len_utf8(),
+                    None => break,
+                }
+            };
+        }
+
+        if !matched {
+            return None;
+        }
+        replaced.push_str(&line[copied..]);
+        Some(replaced)
+    }
+
+    // Returns the number of matches `find_all` reports, matches of
+    // different patterns overlapping each other count once
+    pub fn count(&self, line: &str) -> usize {
+        let mut count = 0;
+        self.each_match(line, |_, _| count += 1);
+        count
+    }
+
+    // Returns the start of the first match of any pattern in a buffer of multiple lines
+    // The match still has to be confirmed on its line with `find`
+    pub fn find_bytes(&self, haystack: &[u8]) -> Option<usize> {
+        if let Some(automaton) = &self.automaton {
+            return automaton.find(haystack).map(|m| m.start());
+        }
+
+        self.patterns
+            .iter()
+            .filter_map(|p| p.find_bytes(haystack))
+            .min()
+    }
+
+    // Returns the index of the pattern and the byte range of the leftmost
+    // match at or after `start`, used to search whole buffers at once
+    pub fn find_bytes_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize, usize)> {
+        if let Some(automaton) = &self.automaton {
+            return automaton
+                .find(Input::new(haystack).range(start..))
+                .map(|m| (m.pattern().as_usize(), m.start(), m.end()));
+        }
+
+        self.patterns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.find_bytes_at(haystack, start).map(|(s, e)| (i, s, e)))
+            .min_by_key(|&(_, s, e)| (s, usize::MAX - e))
+    }
+
+    // How every pattern is searched, for `--explain`
+    pub fn describe(&self) -> Vec<String> {
+        self.patterns.iter().map(Pattern::describe).collect()
+    }
+
+    // Several literals are searched at once instead of one after the other
+    pub fn is_combined(&self) -> bool {
+        self.automaton.is_some()
+    }
+
+    // Whether all patterns are plain literals found by substring search
+    pub fn is_literal(&self) -> bool {
+        self.patterns
+            .iter()
+            .all(|p| matches!(p, Pattern::Literal { .. } | Pattern::Literals(_)))
+    }
+
+    // Counts `line` for every pattern occurring in it
+    pub fn count_patterns(&self, line: &str, lines: &mut [u64]) {
+        for (pattern, lines) in self.patterns.iter().zip(lines.iter_mut()) {
+            if pattern.find(line).is_some() {
+                *lines += 1;
+            }
+        }
+    }
+
+    // Marks the patterns occurring in `line`
+    pub fn mark_matched(&self, line: &str, matched: &mut [bool]) {
+        for (pattern, matched) in self.patterns.iter().zip(matched.iter_mut()) {
+            if !*matched && pattern.find(line).is_some() {
+                *matched = true;
+            }
+        }
+    }
+}
+
+// Where matches of a content pattern have to start and end
+#[derive(Clone, Copy)]
+pub enum Anchor {
+    None,
+    // Bounded by non-word characters or the start and end of the line
+    Word,
+    // The whole line
+    Line,
+}
+
+// A single content pattern
+pub enum Pattern {
+    // Case-sensitive literal text
+    Literal {
+        text: String,
+        finder: Box<memmem::Finder<'static>>,
+    },
+    // Regular expression, also used for case-insensitive literals
+    // since it folds case based on Unicode rules and reports spans
+    // within the original line
+    // `bytes` searches whole buffers, `^` and `$` match at line boundaries
+    Regex {
+        regex: Regex,
+        bytes: bytes::Regex,
+        ignore_case: bool,
+    },
+    // Case-sensitive literals matching if any of them occurs, searched
+    // at once with a single automaton, e.g. from a pattern file
+    Literals(Box<AhoCorasick>),
+}
+
+impl Pattern {
+    // The text of a literal or the compiled regex with its flags
+    fn describe(&self) -> String {
+        match self {
+            Pattern::Literal { text, .. } => format!("literal {:?}", text),
+            Pattern::Regex {
+                regex,
+                ignore_case: true,
+                ..
+            } => format!("regex {}, ignoring case", regex.as_str()),
+            Pattern::Regex { regex, .. } => format!("regex {}", regex.as_str()),
+            Pattern::Literals(automaton) => format!("any of {} literals", automaton.patterns_len()),
+        }
+    }
+
+    pub fn new(
+        pattern: &str,
+        regex: bool,
+        ignore_case: bool,
+        anchor: Anchor,
+    ) -> Result<Pattern, Box<dyn Error>> {
+        if !regex && !ignore_case {
+            if let Anchor::None = anchor {
+                return Ok(Pattern::Literal {
+                    text: String::from(pattern),
+                    finder: Box::new(memmem::Finder::new(pattern).into_owned()),
+                });
+            }
+        }
+
+        Pattern::compile(&source(pattern, regex, anchor), ignore_case)
+    }
+
+    // Matches if any of the patterns matches, all of them are searched at
+    // once instead of one after the other
+    pub fn any(
+        patterns: &[String],
+        regex: bool,
+        ignore_case: bool,
+        anchor: Anchor,
+    ) -> Result<Pattern, Box<dyn Error>> {
+        if !regex && !ignore_case {
+            if let Anchor::None = anchor {
+                // Leftmost-longest like the matches of separate patterns are chosen
+                let automaton = match AhoCorasick::builder()
+                    .match_kind(MatchKind::LeftmostLongest)
+                    .build(patterns)
+                {
+                    Ok(a) => a,
+                    Err(err) => {
+                        return Err(Box::new(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("invalid patterns: {}", err),
+                        )));
+                    }
+                };
+                return Ok(Pattern::Literals(Box::new(automaton)));
+            }
+        }
+
+        let alternation = patterns
+            .iter()
+            .map(|p| format!("(?:{})", source(p, regex, anchor)))
+            .collect::<Vec<_>>()
+            .join("|");
+        Pattern::compile(&alternation, ignore_case)
+    }
+
+    fn compile(pattern: &str, ignore_case: bool) -> Result<Pattern, Box<dyn Error>> {
+        let invalid = |err: regex::Error| -> Box<dyn Error> {
+            Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid content regex: {}", err),
+            ))
+        };
+
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(invalid)?;
+
+        let bytes = bytes::RegexBuilder::new(pattern)
+            .case_insensitive(ignore_case)
+            .multi_line(true)
+            .crlf(true)
+            .build()
+            .map_err(invalid)?;
+
+        Ok(Pattern::Regex {
+            regex,
+            bytes,
+            ignore_case,
+        })
+    }
+
+    // Returns the byte range of the first match
+    pub fn find(&self, line: &str) -> Option<(usize, usize)> {
+        match self {
+            Pattern::Literal { text, .. } => {
+                line.find(text.as_str()).map(|pos| (pos, pos + text.len()))
+            }
+            Pattern::Regex { regex, .. } => regex.find(line).map(|m| (m.start(), m.end())),
+            Pattern::Literals(automaton) => automaton.find(line).map(|m| (m.start(), m.end())),
+        }
+    }
+
+    // Returns the byte range of the first match at or after `start`
+    pub fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        match self {
+            Pattern::Literal { text, .. } => line[start..]
+                .find(text.as_str())
+                .map(|pos| (start + pos, start + pos + text.len())),
+            Pattern::Regex { regex, .. } => {
+                regex.find_at(line, start).map(|m| (m.start(), m.end()))
+            }
+            Pattern::Literals(automaton) => automaton
+                .find(Input::new(line).range(start..))
+                .map(|m| (m.start(), m.end())),
+        }
+    }
+
+    // Appends the replacement of the match at `start` to `dst`, with
+    // `expand` groups like `$1` of regex patterns are expanded
+    fn expand(&self, line: &str, start: usize, replacement: &str, expand: bool, dst: &mut String) {
+        match self {
+            Pattern::Regex { regex, .. } if expand => {
+                if let Some(captures) = regex.captures_at(line, start) {
+                    captures.expand(replacement, dst);
+                }
+            }
+            _ => dst.push_str(replacement),
+        }
+    }
+
+    // Returns the start of the first match in a buffer of multiple lines
+    // The match still has to be confirmed on its line with `find`
+    pub fn find_bytes(&self, haystack: &[u8]) -> Option<usize> {
+        match self {
+            Pattern::Literal { finder, .. } => finder.find(haystack),
+            Pattern::Regex { bytes, .. } => bytes.find(haystack).map(|m| m.start()),
+            Pattern::Literals(automaton) => automaton.find(haystack).map(|m| m.start()),
+        }
+    }
+
+    // Returns the byte range of the first match at or after `start`
+    // in a buffer of multiple lines, the match can span lines
+    pub fn find_bytes_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+        match self {
+            Pattern::Literal { text, finder } => finder
+                .find(&haystack[start..])
+                .map(|pos| (start + pos, start + pos + text.len())),
+            Pattern::Regex { bytes, .. } => {
+                bytes.find_at(haystack, start).map(|m| (m.start(), m.end()))
+            }
+            Pattern::Literals(automaton) => automaton
+                .find(Input::new(haystack).range(start..))
+                .map(|m| (m.start(), m.end())),
+        }
+    }
+}
+
+// Returns the regular expression for a content pattern with its anchor
+fn source(pattern: &str, regex: bool, anchor: Anchor) -> String {
+    match anchor {
+        Anchor::None if regex => String::from(pattern),
+        Anchor::None => regex::escape(pattern),
+        // Literals starting or ending with non-word characters can't
+        // have a word boundary there
+        Anchor::Word if !regex => {
+            let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+            let start = if is_word(pattern.chars().next()) {
+                "\\b"
+            } else {
+                ""
+            };
+            let end = if is_word(pattern.chars().last()) {
+                "\\b"
+            } else {
+                ""
+            };
+            format!("{}{}{}", start, regex::escape(pattern), end)
+        }
+        Anchor::Word => format!("\\b(?:{})\\b", pattern),
+        Anchor::Line if regex => format!("^(?:{})$", pattern),
+        Anchor::Line => format!("^{}$", regex::escape(pattern)),
+    }
+}
+
+pub struct FilenameMatcher {
+    kind: NameKind,
+    // Names are brought into NFC before they are matched, see `normalized`
+    normalize: bool,
+}
+
+enum NameKind {
+    // Substring of the full path
+    Literal(String),
+    // Glob against the file name, or against the path relative
+    // to the root if the pattern contains a separator
+    Glob { regex: Regex, match_path: bool },
+    // Regular expression searched in the full path
+    Regex(Regex),
+}
+
+impl From<NameKind> for FilenameMatcher {
+    fn from(kind: NameKind) -> FilenameMatcher {
+        FilenameMatcher {
+            kind,
+            normalize: false,
+        }
+    }
+}
+
+impl FilenameMatcher {
+    // What is matched against what, globs by the regex they became
+    pub fn describe(&self) -> String {
+        let described = match &self.kind {
+            NameKind::Literal(pattern) => format!("{:?} in the path", pattern),
+            NameKind::Glob { regex, match_path } => {
+                let against = if *match_path {
+                    "path below the root"
+                } else {
+                    "file name"
+                };
+                format!("glob {} against the {}", regex.as_str(), against)
+            }
+            NameKind::Regex(regex) => format!("regex {} in the path", regex.as_str()),
+        };
+        if self.normalize {
+            format!("{}, in NFC", described)
+        } else {
+            described
+        }
+    }
+
+    // Matches names regardless of their Unicode normalization form, `é` is
+    // either one code point (NFC) or `e` and a combining accent (NFD) like
+    // macOS stores names, while typed patterns are usually in NFC
+    // `pattern` has to be brought into NFC with `nfc` before it's compiled
+    pub fn normalized(mut self, normalize: bool) -> FilenameMatcher {
+        self.normalize = normalize;
+        self
+    }
+
+    pub fn literal(pattern: &str, ignore_case: bool) -> FilenameMatcher {
+        if !ignore_case {
+            return NameKind::Literal(String::from(pattern)).into();
+        }
+
+        // Lowercasing both sides breaks on characters with several forms
+        // like the final sigma, the regex engine folds them properly
+        let regex = RegexBuilder::new(&regex::escape(pattern))
+            .case_insensitive(true)
+            .build()
+            .expect("escaped literal is a valid regex");
+        NameKind::Regex(regex).into()
+    }
+
+    pub fn glob(pattern: &str, ignore_case: bool) -> Result<FilenameMatcher, Box<dyn Error>> {
+        let regex = match glob::to_regex(pattern, ignore_case) {
+            Ok(r) => r,
+            Err(err) => {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid glob pattern: {}", err),
+                )));
+            }
+        };
+
+        Ok(NameKind::Glob {
+            regex,
+            match_path: pattern.contains('/'),
+        }
+        .into())
+    }
+
+    pub fn regex(pattern: &str, ignore_case: bool) -> Result<FilenameMatcher, Box<dyn Error>> {
+        match RegexBuilder::new(pattern)
+            .case_insensitive(ignore_case)
+            .build()
+        {
+            Ok(r) => Ok(NameKind::Regex(r).into()),
+            Err(err) => Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid filename regex: {}", err),
+            ))),
+        }
+    }
+
+    pub fn is_match(&self, path: &Path, root: &Path) -> bool {
+        let text = match &self.kind {
+            NameKind::Glob {
+                match_path: true, ..
+            } => Cow::Owned(glob::to_slash(path.strip_prefix(root).unwrap_or(path))),
+            NameKind::Glob { .. } => match path.file_name() {
+                Some(name) => name.to_string_lossy(),
+                None => return false,
+            },
+            NameKind::Literal(_) | NameKind::Regex(_) => path.to_string_lossy(),
+        };
+        let normalized;
+        let text = if self.normalize {
+            normalized = nfc(&text);
+            &normalized
+        } else {
+            &text
+        };
+
+        match &self.kind {
+            NameKind::Literal(pattern) => text.contains(pattern.as_str()),
+            NameKind::Glob { regex, .. } | NameKind::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+// Only allocates for text that isn't in NFC already, like names with
+// combining characters, which is quick to rule out for ASCII
+pub fn nfc(text: &str) -> Cow<'_, str> {
+    if is_nfc_quick(text.chars()) == IsNormalized::Yes {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(text.nfc().collect())
+    }
+}
+
+// Decides the casing for smart case, a pattern is only case sensitive if it
+// contains an uppercase letter. Escapes like `\W` or `\p{Lu}` in regular
+// expressions don't count as they don't match the letter itself
+pub fn has_uppercase(pattern: &str, regex: bool) -> bool {
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            match chars.next() {
+                Some('p') | Some('P') => {
+                    if chars.clone().next() == Some('{') {
+                        chars.by_ref().find(|&c| c == '}');
+                    } else {
+                        chars.next();
+                    }
+                }
+                Some(_) => {}
+                None => break,
+            }
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+
+    false
+}
+
+// Whether a literal pattern was probably meant as a regular expression
+// Single metacharacters like `.` or `(` are common in code, so only
+// constructs that rarely occur in plain text count
+pub fn looks_like_regex(pattern: &str) -> bool {
+    const CONSTRUCTS: [&str; 5] = [".*", ".+", ".?", "(?", "\\|"];
+    const CLASSES: &str = "dDwWsSbB";
+
+    if pattern.len() > 1 && (pattern.starts_with('^') || pattern.ends_with('$')) {
+        return true;
+    }
+
+    if CONSTRUCTS.iter().any(|c| pattern.contains(c)) {
+        return true;
+    }
+
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek().is_some_and(|c| CLASSES.contains(*c)) => return true,
+            // Character ranges like `[a-z]` or `[0-9]`
+            '[' => {
+                let class: Vec<char> = chars.clone().take_while(|&c| c != ']').collect();
+                if class
+                    .windows(3)
+                    .any(|w| w[1] == '-' && w[0].is_alphanumeric() && w[2].is_alphanumeric())
+                {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    false
+}
@@ -0,0 +1,108 @@
+use std::mem;
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use crate::context::Cancel;
+use crate::FileMatch;
+
+// Share of the limit for paths found by the walker but not yet searched
+const PATHS_SHARE: u64 = 8;
+
+// Bytes the threads of a search may hold at once, see `Search::memory_limit`
+// The walker waits for the workers to take paths and the workers wait for
+// the consumer of the results, never the other way around, so waiting
+// can't deadlock
+pub struct Memory {
+    // Found paths waiting for a worker, an eighth of the limit
+    pub paths: Budget,
+    // Files read at once and results waiting to be printed
+    pub data: Budget,
+}
+
+impl Memory {
+    pub fn new(limit: u64) -> Memory {
+        let paths = limit / PATHS_SHARE;
+        Memory {
+            paths: Budget::new(paths),
+            data: Budget::new(limit - paths),
+        }
+    }
+}
+
+pub struct Budget {
+    limit: u64,
+    used: Mutex<u64>,
+    released: Condvar,
+}
+
+impl Budget {
+    fn new(limit: u64) -> Budget {
+        Budget {
+            limit,
+            used: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    // Waits until `bytes` fit into the budget, more than the whole budget
+    // is granted once nothing else is held so it can't wait forever
+    // Stops waiting once the search is cancelled
+    pub fn acquire(&self, bytes: u64, cancel: &Cancel) {
+        let mut used = self.used.lock().unwrap();
+        while *used > 0 && *used + bytes > self.limit && !cancel.is_cancelled() {
+            // Woken up regularly to notice cancellation
+            used = self
+                .released
+                .wait_timeout(used, Duration::from_millis(50))
+                .unwrap()
+                .0;
+        }
+        *used += bytes;
+    }
+
+    pub fn release(&self, bytes: u64) {
+        let mut used = self.used.lock().unwrap();
+        *used = used.saturating_sub(bytes);
+        self.released.notify_all();
+    }
+
+    // Released when the guard is dropped
+    pub fn hold(&self, bytes: u64, cancel: &Cancel) -> Held<'_> {
+        self.acquire(bytes, cancel);
+        Held {
+            budget: self,
+            bytes,
+        }
+    }
+}
+
+pub struct Held<'a> {
+    budget: &'a Budget,
+    bytes: u64,
+}
+
+impl Drop for Held<'_> {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+pub fn path_size(path: &Path) -> u64 {
+    (mem::size_of::<std::path::PathBuf>() + path.as_os_str().len()) as u64
+}
+
+// Roughly the heap and inline size of a result
+pub fn result_size(result: &FileMatch) -> u64 {
+    let lines: usize = result
+        .lines
+        .iter()
+        .flatten()
+        .map(|l| mem::size_of_val(l) + l.content.len())
+        .sum();
+    path_size(&result.path) + (mem::size_of::<FileMatch>() + lines) as u64
+}
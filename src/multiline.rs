@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+
+use crate::content::CountMode;
+use crate::context::Context;
+use crate::{Config, FileMatch, LineMatch};
+
+// Searches a whole buffer at once so matches can span multiple lines
+// Match positions are mapped back to lines through an index of line starts,
+// `content` of a match contains every line it touches
+pub fn search(
+    config: &Config,
+    context: &Context,
+    path: PathBuf,
+    data: &[u8],
+    binary: bool,
+) -> FileMatch {
+    let matcher = config.content.as_ref().unwrap();
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(memchr::memchr_iter(b'\n', data).map(|i| i + 1))
+        .collect();
+
+    // Counting and listing files don't keep the matched lines
+    let keep_lines = config.count.is_none() && !config.lists_files();
+
+    let mut lines = Vec::new();
+    let mut count = 0;
+    let mut last_line = None;
+    let mut matched = vec![false; matcher.len()];
+    // Lines every pattern matched on and the last of them for `--pattern-stats`
+    let mut pattern_lines = vec![(0, None); matcher.len()];
+    let all_matched = |matched: &[bool]| !matcher.requires_all() || matched.iter().all(|&m| m);
+
+    // The byte order mark isn't part of the first line
+    let mut pos = if data.starts_with(&[0xef, 0xbb, 0xbf]) {
+        3
+    } else {
+        0
+    };
+
+    while let Some((index, start, end)) = matcher.find_bytes_at(data, pos) {
+        if context.cancel.is_cancelled() {
+            break;
+        }
+
+        matched[index] = true;
+
+        let limit_reached = config.max_count.is_some_and(|max| count >= max);
+        if !limit_reached {
+            let line = line_index(&line_starts, start);
+
+            // Matches starting on the same line count once unless counting matches
+            let counted = match config.count {
+                Some(CountMode::Matches) | None => true,
+                Some(CountMode::Lines) => last_line != Some(line),
+            };
+            if counted {
+                count += 1;
+            }
+            last_line = Some(line);
+
+            let (lines, last) = &mut pattern_lines[index];
+            if *last != Some(line) {
+                *lines += 1;
+                *last = Some(line);
+            }
+        }
+
+        if !limit_reached && keep_lines {
+            let line = line_index(&line_starts, start);
+            let line_start = line_starts[line];
+            let line_end = memchr::memchr(b'\n', &data[end..]).map_or(data.len(), |i| end + i);
+            let content = &data[line_start..line_end];
+            let content = content.strip_suffix(b"\r").unwrap_or(content);
+
+            lines.push(LineMatch {
+                line: line + 1,
+                column: start - line_start,
+                end: end - line_start,
+                offset: start,
+                content: String::from_utf8_lossy(content).into_owned(),
+            });
+        }
+
+        // Same as line search: binary files and listed files only need
+        // one match, unless other patterns are still missing or counted
+        let listed = config.lists_files() && !context.patterns.enabled();
+        let done = binary || listed || limit_reached;
+        if done && all_matched(&matched) {
+            break;
+        }
+
+        // Empty matches would be found again at the same position
+        pos = if end > start { end } else { end + 1 };
+        if pos > data.len() {
+            break;
+        }
+    }
+
+    // Files missing any of the required patterns don't match at all
+    if !all_matched(&matched) {
+        lines.clear();
+        count = 0;
+    } else if context.patterns.enabled() {
+        let pattern_lines: Vec<u64> = pattern_lines.iter().map(|&(lines, _)| lines).collect();
+        context.patterns.add(&pattern_lines);
+    }
+
+    let lines = if keep_lines { Some(lines) } else { None };
+
+    FileMatch {
+        path,
+        lines,
+        count,
+        binary,
+        name_matched: false,
+        digest: None,
+    }
+}
+
+// Returns the 0-based line containing the byte at `offset`
+fn line_index(line_starts: &[usize], offset: usize) -> usize {
+    line_starts.partition_point(|&start| start <= offset) - 1
+}
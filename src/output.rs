@@ -0,0 +1,183 @@
+use std::io;
+use std::io::{BufWriter, Write};
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Handed to the printer thread at once, also the size of its buffer
+const CHUNK: usize = 64 * 1024;
+
+// Printing waits once this much output is pending
+const PENDING: usize = 16 * CHUNK;
+
+// Output is written at least this often while results trickle in
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+// Writes stdout on a thread of its own, printing a result only appends it
+// to a buffer in memory
+// stdout is locked once per chunk, not for the whole search, so `--cat` and
+// the output of `--exec` can still write to it
+pub struct Printer {
+    output: Output,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+// What the printers write to, waits while the printer thread falls behind
+#[derive(Clone)]
+pub struct Output {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    pending: Vec<u8>,
+    // Taken by the printer thread and not written yet
+    writing: bool,
+    // Skips waiting for more output
+    flush: bool,
+    done: bool,
+    // Writing stdout failed, e.g. because the pipe was closed
+    error: Option<io::Error>,
+}
+
+impl Printer {
+    pub fn start() -> Printer {
+        let output = Output {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State::default()),
+                changed: Condvar::new(),
+            }),
+        };
+
+        let shared = output.shared.clone();
+        let thread = thread::spawn(move || shared.run());
+        Printer {
+            output,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn output(&self) -> Output {
+        self.output.clone()
+    }
+
+    // Waits until all output is written
+    pub fn finish(mut self) -> io::Result<()> {
+        self.stop();
+        match self.output.shared.state.lock().unwrap().error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.output.shared.state.lock().unwrap().done = true;
+            self.output.shared.changed.notify_all();
+            let _ = thread.join();
+        }
+    }
+}
+
+// Output is never lost, even if printing stopped with an error
+impl Drop for Printer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Shared {
+    fn run(&self) {
+        let mut out = BufWriter::with_capacity(CHUNK, Stdout);
+        loop {
+            let mut state = self.state.lock().unwrap();
+            while state.pending.is_empty() && !state.done {
+                state = self.changed.wait(state).unwrap();
+            }
+
+            // Collects a whole chunk unless the output is needed now
+            let since = Instant::now();
+            while state.pending.len() < CHUNK && !state.flush && !state.done {
+                let left = match FLUSH_INTERVAL.checked_sub(since.elapsed()) {
+                    Some(l) => l,
+                    None => break,
+                };
+                state = self.changed.wait_timeout(state, left).unwrap().0;
+            }
+
+            let chunk = mem::take(&mut state.pending);
+            let done = state.done;
+            state.flush = false;
+            state.writing = true;
+            drop(state);
+            self.changed.notify_all();
+
+            let written = out.write_all(&chunk).and_then(|_| out.flush());
+            let mut state = self.state.lock().unwrap();
+            state.writing = false;
+            if let Err(err) = written {
+                state.error = Some(err);
+            }
+            let stop = done || state.error.is_some();
+            drop(state);
+            self.changed.notify_all();
+            if stop {
+                return;
+            }
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.shared.state.lock().unwrap();
+        while state.pending.len() >= PENDING && state.error.is_none() {
+            state = self.shared.changed.wait(state).unwrap();
+        }
+        if let Some(err) = &state.error {
+            return Err(io::Error::new(err.kind(), err.to_string()));
+        }
+
+        // The printer thread only waits for the first bytes and full chunks
+        let wake = state.pending.is_empty() || state.pending.len() + buf.len() >= CHUNK;
+        state.pending.extend_from_slice(buf);
+        drop(state);
+        if wake {
+            self.shared.changed.notify_all();
+        }
+        Ok(buf.len())
+    }
+
+    // Waits until everything written so far is on stdout
+    fn flush(&mut self) -> io::Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.flush = true;
+        self.shared.changed.notify_all();
+        while (!state.pending.is_empty() || state.writing) && state.error.is_none() {
+            state = self.shared.changed.wait(state).unwrap();
+        }
+        match &state.error {
+            Some(err) => Err(io::Error::new(err.kind(), err.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+// Locks stdout for every chunk written by `BufWriter`
+struct Stdout;
+
+impl Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stdout().lock().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
@@ -0,0 +1,149 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+
+// Filter on the permission bits of files, `--perm`
+#[derive(Clone, Copy)]
+pub enum Permissions {
+    // Exactly these bits, e.g. `0644`
+    Exact(u32),
+    // All of these bits, e.g. `-0600`
+    All(u32),
+    // Any of these bits, e.g. `/0022`, `+x` or `o+w`
+    Any(u32),
+}
+
+impl Permissions {
+    pub fn parse(spec: &str) -> Result<Permissions, Box<dyn Error>> {
+        let invalid = || -> Box<dyn Error> {
+            Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid permissions: {}", spec),
+            ))
+        };
+
+        let octal = |s: &str| {
+            if s.is_empty() || s.len() > 4 {
+                return Err(invalid());
+            }
+            u32::from_str_radix(s, 8).map_err(|_| invalid())
+        };
+
+        if let Some(mode) = spec.strip_prefix('-') {
+            return Ok(Permissions::All(octal(mode)?));
+        }
+
+        if let Some(mode) = spec.strip_prefix('/') {
+            return Ok(Permissions::Any(octal(mode)?));
+        }
+
+        if spec.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(Permissions::Exact(octal(spec)?));
+        }
+
+        // Symbolic like chmod, without classes all of them are meant
+        let (who, what) = spec.split_once('+').ok_or_else(invalid)?;
+        let who = if who.is_empty() { "a" } else { who };
+
+        let mut classes = 0;
+        for c in who.chars() {
+            classes |= match c {
+                'u' => 0o700,
+                'g' => 0o070,
+                'o' => 0o007,
+                'a' => 0o777,
+                _ => return Err(invalid()),
+            };
+        }
+
+        let mut bits = 0;
+        for c in what.chars() {
+            bits |= match c {
+                'r' => 0o444 & classes,
+                'w' => 0o222 & classes,
+                'x' => 0o111 & classes,
+                // setuid and setgid only exist for user and group
+                's' => {
+                    (if classes & 0o700 != 0 { 0o4000 } else { 0 })
+                        | if classes & 0o070 != 0 { 0o2000 } else { 0 }
+                }
+                't' => 0o1000,
+                _ => return Err(invalid()),
+            };
+        }
+
+        if bits == 0 {
+            return Err(invalid());
+        }
+        Ok(Permissions::Any(bits))
+    }
+
+    fn matches(self, mode: u32) -> bool {
+        let mode = mode & 0o7777;
+        match self {
+            Permissions::Exact(bits) => mode == bits,
+            Permissions::All(bits) => mode & bits == bits,
+            Permissions::Any(bits) => mode & bits != 0,
+        }
+    }
+}
+
+// Resolves a user name from `/etc/passwd`, numeric ids are taken as they are
+pub fn user_id(name: &str) -> Result<u32, Box<dyn Error>> {
+    lookup("/etc/passwd", name).ok_or_else(|| -> Box<dyn Error> {
+        Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown user: {}", name),
+        ))
+    })
+}
+
+// Resolves a group name from `/etc/group`, numeric ids are taken as they are
+pub fn group_id(name: &str) -> Result<u32, Box<dyn Error>> {
+    lookup("/etc/group", name).ok_or_else(|| -> Box<dyn Error> {
+        Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown group: {}", name),
+        ))
+    })
+}
+
+// Both files have the name in the first and the id in the third field
+fn lookup(file: &str, name: &str) -> Option<u32> {
+    if let Ok(id) = name.parse() {
+        return Some(id);
+    }
+
+    fs::read_to_string(file)
+        .ok()?
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 2 && fields[0] == name)
+        .and_then(|fields| fields[2].parse().ok())
+}
+
+// Checks permissions, owner and group of a file
+#[cfg(unix)]
+pub fn is_match(
+    metadata: &fs::Metadata,
+    perm: Option<Permissions>,
+    owner: Option<u32>,
+    group: Option<u32>,
+) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    perm.is_none_or(|p| p.matches(metadata.mode()))
+        && owner.is_none_or(|uid| metadata.uid() == uid)
+        && group.is_none_or(|gid| metadata.gid() == gid)
+}
+
+// Rejected by `Search::build`
+#[cfg(not(unix))]
+pub fn is_match(
+    _metadata: &fs::Metadata,
+    _perm: Option<Permissions>,
+    _owner: Option<u32>,
+    _group: Option<u32>,
+) -> bool {
+    true
+}
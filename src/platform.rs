@@ -0,0 +1,146 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// File attribute for `Search::attribute`
+#[derive(Clone, Copy, PartialEq)]
+pub enum Attribute {
+    // Dotfiles on unix
+    Hidden,
+    // Only set on Windows
+    System,
+    // Without any write permission on unix
+    ReadOnly,
+    // Only set on Windows
+    Archive,
+}
+
+// Dotfiles are hidden on unix
+#[cfg(not(windows))]
+pub fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+// Windows has an attribute for hidden files instead
+#[cfg(windows)]
+pub fn is_hidden(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|m| has_attribute(&m, path, Attribute::Hidden))
+}
+
+#[cfg(not(windows))]
+pub fn has_attribute(metadata: &fs::Metadata, path: &Path, attribute: Attribute) -> bool {
+    match attribute {
+        Attribute::Hidden => is_hidden(path),
+        Attribute::ReadOnly => metadata.permissions().readonly(),
+        Attribute::System | Attribute::Archive => false,
+    }
+}
+
+#[cfg(windows)]
+pub fn has_attribute(metadata: &fs::Metadata, _path: &Path, attribute: Attribute) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    let flag = match attribute {
+        Attribute::ReadOnly => 0x1,
+        Attribute::Hidden => 0x2,
+        Attribute::System => 0x4,
+        Attribute::Archive => 0x20,
+    };
+    metadata.file_attributes() & flag != 0
+}
+
+// Converts an absolute path to an extended-length path like
+// `\\?\C:\dir` or `\\?\UNC\server\share\dir`, which isn't limited to
+// 260 characters like other paths on Windows
+// Such paths aren't normalized by Windows, so `.` and `..` are resolved
+// and all separators become backslashes
+#[cfg(windows)]
+pub fn extended(path: &Path) -> Cow<'_, Path> {
+    use std::ffi::OsString;
+    use std::path::{Component, PathBuf, Prefix};
+
+    let mut components = path.components();
+    let prefix = match components.next() {
+        Some(Component::Prefix(prefix)) => prefix,
+        _ => return Cow::Borrowed(path),
+    };
+
+    let mut extended = OsString::from(r"\\?\");
+    match prefix.kind() {
+        Prefix::Disk(disk) => extended.push(format!("{}:", disk as char)),
+        Prefix::UNC(server, share) => {
+            extended.push(r"UNC\");
+            extended.push(server);
+            extended.push(r"\");
+            extended.push(share);
+        }
+        // Already extended or a device path
+        _ => return Cow::Borrowed(path),
+    }
+
+    if components.next() != Some(Component::RootDir) {
+        // Relative to the current directory of a drive like `C:dir`
+        return Cow::Borrowed(path);
+    }
+
+    let mut extended = PathBuf::from(extended);
+    extended.push(r"\");
+    for component in components {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                extended.pop();
+            }
+            Component::Normal(name) => extended.push(name),
+            Component::Prefix(_) | Component::RootDir => {}
+        }
+    }
+
+    Cow::Owned(extended)
+}
+
+#[cfg(not(windows))]
+pub fn extended(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+// Text of a path in the output, extended-length paths are printed
+// without their prefix like `C:\dir` and `\\server\share\dir`
+pub fn display(path: &Path) -> Cow<'_, str> {
+    let text = path.to_string_lossy();
+    if !cfg!(windows) {
+        return text;
+    }
+
+    if let Some(rest) = text.strip_prefix(r"\\?\UNC\") {
+        return Cow::Owned(format!(r"\\{}", rest));
+    }
+    match text.strip_prefix(r"\\?\") {
+        // Only drive paths, other verbatim paths need their prefix
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => Cow::Owned(String::from(rest)),
+        _ => text,
+    }
+}
+
+// Whether stdin is a pipe or a redirected file, not a terminal or a
+// closed or null device like in scripts and services
+#[cfg(unix)]
+pub fn stdin_is_piped() -> bool {
+    use std::os::fd::AsFd;
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = io::stdin()
+        .as_fd()
+        .try_clone_to_owned()
+        .and_then(|fd| fs::File::from(fd).metadata());
+    metadata.is_ok_and(|m| m.file_type().is_fifo() || m.is_file())
+}
+
+#[cfg(not(unix))]
+pub fn stdin_is_piped() -> bool {
+    use std::io::IsTerminal;
+
+    !io::stdin().is_terminal()
+}
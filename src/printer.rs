@@ -0,0 +1,1075 @@
+use std::borrow::Cow;
+use std::env;
+use std::error::Error;
+use std::io;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use serde_json::json;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::content::STDIN;
+use crate::duplicates::Duplicates;
+use crate::output::Output;
+use crate::platform::display;
+use crate::replace::Change;
+use crate::template::Template;
+use crate::{Config, FileMatch, LineMatch, Progress};
+
+pub enum OutputFormat {
+    Text,
+    Json,
+    // One row per match with the columns file, line, column and match
+    Csv,
+    Tsv,
+    // Only file names, each terminated by NUL for `xargs -0`
+    Null,
+}
+
+// How reported columns are counted, `LineMatch` keeps byte offsets
+// Characters are Unicode scalar values, graphemes what is perceived as
+// one character like `e` with a combining accent or a flag emoji
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColumnMode {
+    Bytes,
+    Chars,
+    Graphemes,
+}
+
+impl ColumnMode {
+    fn count(self, text: &str) -> usize {
+        match self {
+            ColumnMode::Bytes => text.len(),
+            ColumnMode::Chars => text.chars().count(),
+            ColumnMode::Graphemes => text.graphemes(true).count(),
+        }
+    }
+}
+
+// Counts columns like `mode`, with `tab_width` tabs advance to the next
+// multiple of it like in an editor, so columns are visual ones
+#[derive(Clone, Copy)]
+pub struct Columns {
+    pub mode: ColumnMode,
+    pub tab_width: Option<usize>,
+}
+
+impl Columns {
+    // The column of the byte offset `byte` within `line`
+    pub fn column(self, line: &str, byte: usize) -> usize {
+        let before = line.get(..byte).unwrap_or(line);
+        let width = match self.tab_width {
+            Some(w) => w,
+            None => return self.mode.count(before),
+        };
+
+        let mut parts = before.split('\t');
+        let first = parts.next().map_or(0, |p| self.mode.count(p));
+        parts.fold(first, |column, part| {
+            (column / width + 1) * width + self.mode.count(part)
+        })
+    }
+}
+
+const COLOR_RESET: &str = "\x1b[0m";
+
+// ANSI escape sequences used for colored text output
+#[derive(Clone)]
+pub struct Colors {
+    file: String,
+    position: String,
+    matched: String,
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            file: String::from("\x1b[35m"),
+            position: String::from("\x1b[32m"),
+            matched: String::from("\x1b[1;31m"),
+        }
+    }
+}
+
+impl Colors {
+    // Parses a spec like `file=35:position=32:match=1;31`, values are SGR
+    // parameters, missing entries keep their default
+    pub fn parse(spec: &str) -> Result<Colors, Box<dyn Error>> {
+        let mut colors = Colors::default();
+
+        for entry in spec.split(':').filter(|e| !e.is_empty()) {
+            let invalid = || -> Box<dyn Error> {
+                Box::new(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid color: {}", entry),
+                ))
+            };
+
+            let (name, value) = entry.split_once('=').ok_or_else(invalid)?;
+            if value.is_empty() || !value.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                return Err(invalid());
+            }
+
+            let sequence = format!("\x1b[{}m", value);
+            match name {
+                "file" => colors.file = sequence,
+                "position" => colors.position = sequence,
+                "match" => colors.matched = sequence,
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(colors)
+    }
+}
+
+// How printed paths relate to the paths found, see `Search::absolute_path`
+#[derive(Clone)]
+pub enum PathStyle {
+    // Relative to the current directory if the root is
+    Found,
+    Absolute,
+    RelativeTo(PathBuf),
+}
+
+// Turns found paths into the paths printed, used by every printer
+// Paths are normalized without touching the filesystem, so `..` is resolved
+// lexically and entries of archives work like any other path
+#[derive(Clone)]
+pub struct Paths {
+    style: PathStyle,
+    // Relative paths are resolved against the current directory
+    base: PathBuf,
+}
+
+impl Paths {
+    pub fn new(style: &PathStyle) -> Paths {
+        let base = env::current_dir().unwrap_or_default();
+        let style = match style {
+            PathStyle::RelativeTo(dir) => PathStyle::RelativeTo(absolute(&base, dir)),
+            style => style.clone(),
+        };
+        Paths { style, base }
+    }
+
+    pub fn apply<'a>(&self, path: &'a Path) -> Cow<'a, Path> {
+        if path == Path::new(STDIN) {
+            return Cow::Borrowed(path);
+        }
+        match &self.style {
+            PathStyle::Found => Cow::Borrowed(path),
+            PathStyle::Absolute => Cow::Owned(absolute(&self.base, path)),
+            PathStyle::RelativeTo(dir) => Cow::Owned(relative(dir, &absolute(&self.base, path))),
+        }
+    }
+
+    pub fn show<'a>(&self, path: &'a Path) -> Cow<'a, str> {
+        match self.apply(path) {
+            Cow::Borrowed(path) => display(path),
+            Cow::Owned(path) => Cow::Owned(display(&path).into_owned()),
+        }
+    }
+}
+
+// `path` resolved against `base` without `.` and `..`
+pub fn absolute(base: &Path, path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in base.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+// The way from the directory `from` to `to`, both absolute
+// Paths on another drive stay absolute
+fn relative(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return to.iter().collect();
+    }
+
+    let mut path: PathBuf = from[common..]
+        .iter()
+        .map(|_| Component::ParentDir)
+        .collect();
+    path.extend(&to[common..]);
+    if path.as_os_str().is_empty() {
+        path.push(Component::CurDir);
+    }
+    path
+}
+
+pub trait ResultPrinter {
+    // Prints matched file in file search mode
+    fn file(&mut self, path: &Path) -> io::Result<()>;
+
+    // Prints matched lines of a file in content search mode
+    fn matches(&mut self, result: &FileMatch) -> io::Result<()>;
+
+    // Prints the amount of matches of a file in count mode
+    fn count(&mut self, result: &FileMatch) -> io::Result<()>;
+
+    // Prints the lines changed by `--replace`
+    fn changes(&mut self, path: &Path, changes: &[Change]) -> io::Result<()>;
+
+    // Starts a group of files in the same directory
+    fn directory(&mut self, path: &Path) -> io::Result<()>;
+
+    // Prints a found file together with its digest
+    fn digest(&mut self, path: &Path, digest: &str) -> io::Result<()>;
+
+    // Prints a group of files with identical content
+    fn duplicates(&mut self, group: &Duplicates) -> io::Result<()>;
+
+    // Prints the summary of `--stats` after all results
+    fn stats(&mut self, progress: &Progress, errors: usize, elapsed: Duration) -> io::Result<()>;
+}
+
+pub fn new_printer(config: &Config, out: Output) -> Box<dyn ResultPrinter + '_> {
+    let paths = Paths::new(&config.path_style);
+    if let Some(template) = &config.template {
+        return Box::new(TemplatePrinter {
+            out,
+            paths,
+            template,
+            columns: config.columns,
+        });
+    }
+
+    match config.format {
+        OutputFormat::Text => Box::new(TextPrinter {
+            out,
+            paths,
+            colors: if config.color {
+                Some(config.colors.clone())
+            } else {
+                None
+            },
+            heading: config.heading,
+            links: if config.hyperlink {
+                Some(Links::new())
+            } else {
+                None
+            },
+            byte_offset: config.byte_offset,
+            only_matching: config.only_matching,
+            grouped: config.group_by_dir,
+            max_columns: config.max_columns,
+            labels: config.name_or_content,
+            columns: config.columns,
+        }),
+        OutputFormat::Json => Box::new(JsonPrinter {
+            out,
+            paths,
+            columns: config.columns,
+        }),
+        OutputFormat::Null => Box::new(NullPrinter { out, paths }),
+        OutputFormat::Csv => Box::new(DelimitedPrinter {
+            out,
+            paths,
+            separator: ',',
+            header: false,
+            columns: config.columns,
+        }),
+        OutputFormat::Tsv => Box::new(DelimitedPrinter {
+            out,
+            paths,
+            separator: '\t',
+            header: false,
+            columns: config.columns,
+        }),
+    }
+}
+
+pub struct TextPrinter<W: Write> {
+    out: W,
+    paths: Paths,
+    // Text output is only colored if `colors` is given
+    colors: Option<Colors>,
+    // Lines are printed below a header instead of after their file
+    heading: bool,
+    // File names and positions are hyperlinks if given
+    links: Option<Links>,
+    // Prints `line:column:offset` instead of `line:column`
+    byte_offset: bool,
+    // Prints the matched text instead of the whole line
+    only_matching: bool,
+    // Files are listed below a directory header by their name only
+    grouped: bool,
+    // Longer lines are cut to a window around the match
+    max_columns: Option<usize>,
+    // Files are labeled with what matched, see `label`
+    labels: bool,
+    columns: Columns,
+}
+
+impl<W: Write> TextPrinter<W> {
+    // Indentation of matched lines
+    fn indent(&self) -> &'static str {
+        if self.grouped {
+            "    "
+        } else {
+            "  "
+        }
+    }
+
+    fn header(&mut self, path: &Path, label: Option<&str>) -> io::Result<()> {
+        let file = self.linked(path, None);
+        let indent = &self.indent()[2..];
+        let label = label.map(|l| format!(" [{}]", l)).unwrap_or_default();
+        if let Some(colors) = &self.colors {
+            writeln!(
+                self.out,
+                "{}{}{}{}{}",
+                indent, colors.file, file, COLOR_RESET, label
+            )
+        } else {
+            writeln!(self.out, "{}{}{}", indent, file, label)
+        }
+    }
+
+    // Starts every line of a file, the indentation below a heading or
+    // the file itself like `src/main.rs:`
+    fn prefix(&self, path: &Path, line: Option<usize>) -> String {
+        if self.heading {
+            return String::from(self.indent());
+        }
+
+        let file = self.linked(path, line);
+        let indent = &self.indent()[2..];
+        match &self.colors {
+            Some(colors) => format!("{}{}{}{}:", indent, colors.file, file, COLOR_RESET),
+            None => format!("{}{}:", indent, file),
+        }
+    }
+
+    fn name<'a>(&self, path: &'a Path) -> Cow<'a, str> {
+        match path.file_name() {
+            Some(name) if self.grouped => name.to_string_lossy(),
+            _ => self.paths.show(path),
+        }
+    }
+
+    // The name of a file, linked to `line` of it with `links`
+    fn linked<'a>(&self, path: &'a Path, line: Option<usize>) -> Cow<'a, str> {
+        let name = self.name(path);
+        match &self.links {
+            Some(links) => Cow::Owned(links.wrap(path, line, &name)),
+            None => name,
+        }
+    }
+}
+
+// OSC 8 escape sequences around text, linking to a file and optionally
+// a line of it, see `Search::hyperlink`
+struct Links {
+    // Relative paths are resolved against it
+    base: PathBuf,
+}
+
+impl Links {
+    fn new() -> Links {
+        Links {
+            base: env::current_dir().unwrap_or_default(),
+        }
+    }
+
+    fn wrap(&self, path: &Path, line: Option<usize>, text: &str) -> String {
+        // Nothing to open
+        if path == Path::new(STDIN) {
+            return String::from(text);
+        }
+        format!(
+            "\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\",
+            self.url(path, line),
+            text
+        )
+    }
+
+    // Like `file:///home/dev/src/main.rs#12`, the host is left out so the
+    // link is to the machine the terminal runs on
+    fn url(&self, path: &Path, line: Option<usize>) -> String {
+        let path = absolute(&self.base, path);
+        let mut text = display(&path).into_owned();
+        if cfg!(windows) {
+            text = text.replace('\\', "/");
+        }
+
+        let mut url = String::from("file://");
+        if !text.starts_with('/') {
+            url.push('/');
+        }
+        for byte in text.bytes() {
+            if byte.is_ascii_alphanumeric() || b"/-._~:".contains(&byte) {
+                url.push(char::from(byte));
+            } else {
+                url.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        if let Some(line) = line {
+            url.push_str(&format!("#{}", line));
+        }
+        url
+    }
+}
+
+impl<W: Write> ResultPrinter for TextPrinter<W> {
+    fn count(&mut self, result: &FileMatch) -> io::Result<()> {
+        let file = self.linked(&result.path, None);
+        let indent = &self.indent()[2..];
+        if let Some(colors) = &self.colors {
+            writeln!(
+                self.out,
+                "{}{}{}{}:{}",
+                indent, colors.file, file, COLOR_RESET, result.count
+            )
+        } else {
+            writeln!(self.out, "{}{}:{}", indent, file, result.count)
+        }
+    }
+
+    fn file(&mut self, path: &Path) -> io::Result<()> {
+        self.header(path, None)
+    }
+
+    // Same layout as sha256sum, so the output can be checked with `-c`
+    fn digest(&mut self, path: &Path, digest: &str) -> io::Result<()> {
+        writeln!(self.out, "{}  {}", digest, self.paths.show(path))
+    }
+
+    // Groups are separated by an empty line
+    fn duplicates(&mut self, group: &Duplicates) -> io::Result<()> {
+        for path in &group.paths {
+            self.file(path)?;
+        }
+        writeln!(self.out)
+    }
+
+    fn stats(&mut self, progress: &Progress, errors: usize, elapsed: Duration) -> io::Result<()> {
+        writeln!(self.out)?;
+        write_stats(&mut self.out, progress, errors, elapsed)
+    }
+
+    fn directory(&mut self, path: &Path) -> io::Result<()> {
+        let dir = self.paths.apply(path).join("");
+        if let Some(colors) = &self.colors {
+            writeln!(self.out, "{}{}{}", colors.file, display(&dir), COLOR_RESET)
+        } else {
+            writeln!(self.out, "{}", display(&dir))
+        }
+    }
+
+    fn changes(&mut self, path: &Path, changes: &[Change]) -> io::Result<()> {
+        if self.heading {
+            self.file(path)?;
+        }
+
+        let (removed, added, reset) = match &self.colors {
+            Some(_) => ("\x1b[31m", "\x1b[32m", COLOR_RESET),
+            None => ("", "", ""),
+        };
+
+        let indent = self.prefix(path, None);
+        for change in changes {
+            writeln!(
+                self.out,
+                "{}{}{} - {}{}",
+                indent, removed, change.line, change.before, reset
+            )?;
+            writeln!(
+                self.out,
+                "{}{}{} + {}{}",
+                indent, added, change.line, change.after, reset
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn matches(&mut self, result: &FileMatch) -> io::Result<()> {
+        let label = if self.labels {
+            Some(label(result))
+        } else {
+            None
+        };
+        // Files matched by name alone have no lines to carry the file
+        let lines = result.lines.as_ref().map_or(0, |l| l.len());
+        if self.heading || (lines == 0 && !result.binary) {
+            self.header(&result.path, label)?;
+        }
+        let mut indent = self.prefix(&result.path, None);
+        let gap = if self.heading { " " } else { ":" };
+        if result.binary {
+            return writeln!(self.out, "{}binary file matches", indent);
+        }
+
+        for line in result.lines.iter().flatten() {
+            let column = self.columns.column(&line.content, line.column);
+            let mut position = if self.byte_offset {
+                format!("{}:{}:{}", line.line, column, line.offset)
+            } else {
+                format!("{}:{}", line.line, column)
+            };
+            // Every line links to itself
+            match &self.links {
+                Some(links) if self.heading => {
+                    position = links.wrap(&result.path, Some(line.line), &position)
+                }
+                Some(_) => indent = self.prefix(&result.path, Some(line.line)),
+                None => {}
+            }
+
+            let (text, start, end) = match self.max_columns {
+                Some(max) if !self.only_matching => {
+                    window(&line.content, line.column, line.end, max)
+                }
+                _ => (Cow::Borrowed(line.content.as_str()), line.column, line.end),
+            };
+
+            let content = match text.get(start..end) {
+                Some(matched) if self.only_matching => matched,
+                _ => &text,
+            };
+
+            let colors = match &self.colors {
+                Some(c) => c,
+                None => {
+                    writeln!(self.out, "{}{}{}{}", indent, position, gap, content)?;
+                    continue;
+                }
+            };
+
+            if self.only_matching {
+                writeln!(
+                    self.out,
+                    "{}{}{}{}{}{}{}{}",
+                    indent,
+                    colors.position,
+                    position,
+                    COLOR_RESET,
+                    gap,
+                    colors.matched,
+                    content,
+                    COLOR_RESET
+                )?;
+                continue;
+            }
+
+            // Fall back to the plain line if the span isn't on a char boundary
+            match (text.get(..start), text.get(start..end), text.get(end..)) {
+                (Some(before), Some(matched), Some(after)) => writeln!(
+                    self.out,
+                    "{}{}{}{}{}{}{}{}{}{}",
+                    indent,
+                    colors.position,
+                    position,
+                    COLOR_RESET,
+                    gap,
+                    before,
+                    colors.matched,
+                    matched,
+                    COLOR_RESET,
+                    after
+                )?,
+                _ => writeln!(
+                    self.out,
+                    "{}{}{}{}{}{}",
+                    indent, colors.position, position, COLOR_RESET, gap, text
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// What matched with `Search::name_or_content`
+fn label(result: &FileMatch) -> &'static str {
+    match (result.name_matched, result.count > 0) {
+        (true, true) => "name, content",
+        (true, false) => "name",
+        _ => "content",
+    }
+}
+
+// Cuts `line` to `max` characters around the match at `start..end`,
+// cut ends are marked with `...`
+// Returns the text and the span of the match within it
+fn window(line: &str, start: usize, end: usize, max: usize) -> (Cow<'_, str>, usize, usize) {
+    let total = line.chars().count();
+    if total <= max || !line.is_char_boundary(start) || !line.is_char_boundary(end) {
+        return (Cow::Borrowed(line), start, end);
+    }
+
+    // Center the match, long matches are cut at the end instead
+    let match_start = line[..start].chars().count();
+    let match_len = line[start..end].chars().count();
+    let padding = max.saturating_sub(match_len) / 2;
+    let first = match_start.saturating_sub(padding).min(total - max);
+
+    let byte = |chars: usize| {
+        line.char_indices()
+            .nth(chars)
+            .map_or(line.len(), |(i, _)| i)
+    };
+    let (from, to) = (byte(first), byte(first + max));
+
+    let prefix = if from > 0 { "..." } else { "" };
+    let suffix = if to < line.len() { "..." } else { "" };
+    let text = format!("{}{}{}", prefix, &line[from..to], suffix);
+
+    let shift = |pos: usize| pos.clamp(from, to) - from + prefix.len();
+    (Cow::Owned(text), shift(start), shift(end))
+}
+
+// Prints one JSON object per line for every match
+pub struct JsonPrinter<W: Write> {
+    out: W,
+    paths: Paths,
+    columns: Columns,
+}
+
+impl<W: Write> JsonPrinter<W> {
+    pub fn new(out: W, config: &Config) -> JsonPrinter<W> {
+        JsonPrinter {
+            out,
+            paths: Paths::new(&config.path_style),
+            columns: config.columns,
+        }
+    }
+
+    // Errors of `--serve` go to the client instead of stderr
+    pub fn error(&mut self, path: &Path, message: &str) -> io::Result<()> {
+        let object = json!({
+            "file": self.paths.show(path),
+            "error": message,
+        });
+        writeln!(self.out, "{}", object)
+    }
+}
+
+impl<W: Write> ResultPrinter for JsonPrinter<W> {
+    fn count(&mut self, result: &FileMatch) -> io::Result<()> {
+        let object = json!({
+            "file": self.paths.show(&result.path),
+            "count": result.count,
+        });
+        writeln!(self.out, "{}", object)
+    }
+
+    fn file(&mut self, path: &Path) -> io::Result<()> {
+        writeln!(self.out, "{}", json!({ "file": self.paths.show(path) }))
+    }
+
+    fn digest(&mut self, path: &Path, digest: &str) -> io::Result<()> {
+        let object = json!({
+            "file": self.paths.show(path),
+            "hash": digest,
+        });
+        writeln!(self.out, "{}", object)
+    }
+
+    fn duplicates(&mut self, group: &Duplicates) -> io::Result<()> {
+        let files: Vec<_> = group.paths.iter().map(|p| self.paths.show(p)).collect();
+        let object = json!({
+            "duplicates": files,
+            "size": group.size,
+            "hash": group.hash,
+        });
+        writeln!(self.out, "{}", object)
+    }
+
+    // Every object contains the whole path already
+    fn directory(&mut self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn stats(&mut self, progress: &Progress, errors: usize, elapsed: Duration) -> io::Result<()> {
+        let stats: serde_json::Map<_, _> = stats(progress, errors, elapsed)
+            .into_iter()
+            .map(|(name, value)| (name.replace(' ', "_"), value))
+            .collect();
+        writeln!(self.out, "{}", json!({ "stats": stats }))
+    }
+
+    fn changes(&mut self, path: &Path, changes: &[Change]) -> io::Result<()> {
+        for change in changes {
+            let object = json!({
+                "file": self.paths.show(path),
+                "line": change.line,
+                "before": change.before,
+                "after": change.after,
+            });
+            writeln!(self.out, "{}", object)?;
+        }
+
+        Ok(())
+    }
+
+    fn matches(&mut self, result: &FileMatch) -> io::Result<()> {
+        if result.name_matched {
+            let object = json!({
+                "file": self.paths.show(&result.path),
+                "name": true,
+            });
+            writeln!(self.out, "{}", object)?;
+        }
+
+        if result.binary {
+            let object = json!({
+                "file": self.paths.show(&result.path),
+                "binary": true,
+            });
+            return writeln!(self.out, "{}", object);
+        }
+
+        for line in result.lines.iter().flatten() {
+            let object = json!({
+                "file": self.paths.show(&result.path),
+                "line": line.line,
+                "column": self.columns.column(&line.content, line.column),
+                "text": line.content.get(line.column..line.end).unwrap_or(""),
+                "offset": line.offset,
+            });
+            writeln!(self.out, "{}", object)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Prints every file name terminated by NUL instead of a newline,
+// so paths containing spaces or newlines survive `xargs -0`
+pub struct NullPrinter<W: Write> {
+    out: W,
+    paths: Paths,
+}
+
+impl<W: Write> ResultPrinter for NullPrinter<W> {
+    fn count(&mut self, result: &FileMatch) -> io::Result<()> {
+        self.file(&result.path)
+    }
+
+    fn file(&mut self, path: &Path) -> io::Result<()> {
+        self.out.write_all(&path_bytes(&self.paths.apply(path)))?;
+        self.out.write_all(b"\0")
+    }
+
+    fn digest(&mut self, path: &Path, digest: &str) -> io::Result<()> {
+        write!(self.out, "{}  ", digest)?;
+        self.file(path)
+    }
+
+    // Groups end with an empty name
+    fn duplicates(&mut self, group: &Duplicates) -> io::Result<()> {
+        for path in &group.paths {
+            self.file(path)?;
+        }
+        self.out.write_all(b"\0")
+    }
+
+    fn changes(&mut self, path: &Path, _changes: &[Change]) -> io::Result<()> {
+        self.file(path)
+    }
+
+    fn directory(&mut self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Only file names can be printed
+    fn stats(
+        &mut self,
+        _progress: &Progress,
+        _errors: usize,
+        _elapsed: Duration,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn matches(&mut self, result: &FileMatch) -> io::Result<()> {
+        self.file(&result.path)
+    }
+}
+
+// Prints one row per match as comma or tab separated values, the first row
+// names the columns
+// CSV fields are quoted if necessary, TSV escapes tabs, newlines and
+// backslashes instead
+pub struct DelimitedPrinter<W: Write> {
+    out: W,
+    paths: Paths,
+    separator: char,
+    // Whether the header row has been printed
+    header: bool,
+    columns: Columns,
+}
+
+impl<W: Write> DelimitedPrinter<W> {
+    fn row(&mut self, columns: &[&str], fields: &[&str]) -> io::Result<()> {
+        if !self.header {
+            self.header = true;
+            self.row(columns, columns)?;
+        }
+
+        let fields: Vec<_> = fields.iter().map(|f| self.escape(f)).collect();
+        writeln!(self.out, "{}", fields.join(&self.separator.to_string()))
+    }
+
+    fn escape<'a>(&self, field: &'a str) -> Cow<'a, str> {
+        if self.separator == '\t' {
+            if !field.contains(['\t', '\n', '\r', '\\']) {
+                return Cow::Borrowed(field);
+            }
+
+            return Cow::Owned(
+                field
+                    .replace('\\', "\\\\")
+                    .replace('\t', "\\t")
+                    .replace('\n', "\\n")
+                    .replace('\r', "\\r"),
+            );
+        }
+
+        if field.contains([self.separator, '"', '\n', '\r']) {
+            Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+        } else {
+            Cow::Borrowed(field)
+        }
+    }
+}
+
+impl<W: Write> ResultPrinter for DelimitedPrinter<W> {
+    fn count(&mut self, result: &FileMatch) -> io::Result<()> {
+        let file = self.paths.show(&result.path);
+        self.row(&["file", "count"], &[&file, &result.count.to_string()])
+    }
+
+    fn file(&mut self, path: &Path) -> io::Result<()> {
+        self.row(&["file"], &[&self.paths.show(path)])
+    }
+
+    fn digest(&mut self, path: &Path, digest: &str) -> io::Result<()> {
+        self.row(&["file", "hash"], &[&self.paths.show(path), digest])
+    }
+
+    // The hash tells the groups apart
+    fn duplicates(&mut self, group: &Duplicates) -> io::Result<()> {
+        let size = group.size.to_string();
+        for path in &group.paths {
+            self.row(
+                &["hash", "size", "file"],
+                &[&group.hash, &size, &self.paths.show(path)],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn changes(&mut self, path: &Path, changes: &[Change]) -> io::Result<()> {
+        let file = self.paths.show(path);
+        for change in changes {
+            self.row(
+                &["file", "line", "before", "after"],
+                &[
+                    &file,
+                    &change.line.to_string(),
+                    &change.before,
+                    &change.after,
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // Every row contains the whole path already
+    fn directory(&mut self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Printed to stderr so stdout stays a valid table
+    fn stats(&mut self, progress: &Progress, errors: usize, elapsed: Duration) -> io::Result<()> {
+        write_stats(&mut io::stderr(), progress, errors, elapsed)
+    }
+
+    fn matches(&mut self, result: &FileMatch) -> io::Result<()> {
+        const COLUMNS: [&str; 4] = ["file", "line", "column", "match"];
+
+        let file = self.paths.show(&result.path);
+        // Binary files only report that they match, same for file names
+        if result.binary || result.name_matched {
+            self.row(&COLUMNS, &[&file, "", "", ""])?;
+        }
+        if result.binary {
+            return Ok(());
+        }
+
+        for line in result.lines.iter().flatten() {
+            let matched = line.content.get(line.column..line.end).unwrap_or("");
+            let column = self.columns.column(&line.content, line.column);
+            self.row(
+                &COLUMNS,
+                &[&file, &line.line.to_string(), &column.to_string(), matched],
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// Prints every match, file or count with a user defined layout
+pub struct TemplatePrinter<'a, W: Write> {
+    out: W,
+    paths: Paths,
+    template: &'a Template,
+    columns: Columns,
+}
+
+impl<'a, W: Write> ResultPrinter for TemplatePrinter<'a, W> {
+    fn count(&mut self, result: &FileMatch) -> io::Result<()> {
+        let text = self.template.render(
+            &self.paths.apply(&result.path),
+            None,
+            Some(result.count),
+            self.columns,
+        );
+        writeln!(self.out, "{}", text)
+    }
+
+    fn file(&mut self, path: &Path) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{}",
+            self.template
+                .render(&self.paths.apply(path), None, None, self.columns)
+        )
+    }
+
+    // Templates have no placeholder for the digest
+    fn digest(&mut self, path: &Path, _digest: &str) -> io::Result<()> {
+        self.file(path)
+    }
+
+    fn duplicates(&mut self, group: &Duplicates) -> io::Result<()> {
+        for path in &group.paths {
+            self.file(path)?;
+        }
+        writeln!(self.out)
+    }
+
+    // `{text}` is the changed line
+    fn changes(&mut self, path: &Path, changes: &[Change]) -> io::Result<()> {
+        for change in changes {
+            let line = LineMatch {
+                line: change.line,
+                column: 0,
+                end: change.after.len(),
+                offset: 0,
+                content: change.after.clone(),
+            };
+            writeln!(
+                self.out,
+                "{}",
+                self.template
+                    .render(&self.paths.apply(path), Some(&line), None, self.columns)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn directory(&mut self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn stats(&mut self, progress: &Progress, errors: usize, elapsed: Duration) -> io::Result<()> {
+        writeln!(self.out)?;
+        write_stats(&mut self.out, progress, errors, elapsed)
+    }
+
+    fn matches(&mut self, result: &FileMatch) -> io::Result<()> {
+        if result.name_matched {
+            self.file(&result.path)?;
+        }
+        if result.binary || result.lines.is_none() {
+            return if result.name_matched {
+                Ok(())
+            } else {
+                self.file(&result.path)
+            };
+        }
+
+        for line in result.lines.iter().flatten() {
+            let text = self.template.render(
+                &self.paths.apply(&result.path),
+                Some(line),
+                None,
+                self.columns,
+            );
+            writeln!(self.out, "{}", text)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_stats<W: Write>(
+    out: &mut W,
+    progress: &Progress,
+    errors: usize,
+    elapsed: Duration,
+) -> io::Result<()> {
+    for (name, value) in stats(progress, errors, elapsed) {
+        let value = match value.as_f64() {
+            Some(seconds) if value.is_f64() => format!("{:.3}", seconds),
+            _ => value.to_string(),
+        };
+        writeln!(out, "{:<16} {}", format!("{}:", name), value)?;
+    }
+
+    Ok(())
+}
+
+// Counters of `--stats` in the order they are printed, times are in seconds
+fn stats(
+    progress: &Progress,
+    errors: usize,
+    elapsed: Duration,
+) -> Vec<(String, serde_json::Value)> {
+    let seconds = |time: Option<Duration>| json!(time.unwrap_or(elapsed).as_secs_f64());
+
+    vec![
+        (String::from("files traversed"), json!(progress.traversed())),
+        (String::from("files searched"), json!(progress.files())),
+        (String::from("files ignored"), json!(progress.ignored())),
+        (String::from("files binary"), json!(progress.binary())),
+        (String::from("errors"), json!(errors)),
+        (String::from("matches"), json!(progress.matches())),
+        (String::from("bytes read"), json!(progress.bytes())),
+        (String::from("walk time"), seconds(progress.walk_time())),
+        (String::from("search time"), seconds(progress.search_time())),
+        (String::from("total time"), seconds(Some(elapsed))),
+    ]
+}
+
+// File names aren't necessarily valid UTF-8 on unix, print them unchanged
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    display(path).into_owned().into_bytes()
+}
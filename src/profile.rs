@@ -0,0 +1,163 @@
+use std::io;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Phases a search spends its time in, summed over all threads
+#[derive(Clone, Copy)]
+pub enum Phase {
+    // Reading directories and the types of their entries
+    Traverse,
+    // Hidden files, excludes, ignore files and the file filters
+    Filter,
+    // Opening files and reading their metadata
+    Open,
+    // Reading files, only measured for files not memory-mapped
+    Read,
+    // Searching the content, including `Read` until the report
+    Match,
+    // Printing the results
+    Print,
+}
+
+const PHASES: [(Phase, &str); 6] = [
+    (Phase::Traverse, "traversal"),
+    (Phase::Filter, "filtering"),
+    (Phase::Open, "open"),
+    (Phase::Read, "read"),
+    (Phase::Match, "match"),
+    (Phase::Print, "print"),
+];
+
+// Timings of `--profile`, nothing is measured if it's disabled
+#[derive(Default)]
+pub struct Profile {
+    enabled: bool,
+    // Nanoseconds per phase
+    phases: [AtomicU64; 6],
+    // Time each walk and search thread was busy, in the order they finished
+    threads: Mutex<Vec<(&'static str, Duration)>>,
+}
+
+impl Profile {
+    pub fn new(enabled: bool) -> Profile {
+        Profile {
+            enabled,
+            ..Profile::default()
+        }
+    }
+
+    pub fn start(&self) -> Option<Instant> {
+        if self.enabled {
+            Some(Instant::now())
+        } else {
+            None
+        }
+    }
+
+    // Adds the time since `started`, which is `None` if disabled
+    pub fn add(&self, phase: Phase, started: Option<Instant>) {
+        if let Some(started) = started {
+            let elapsed = started.elapsed().as_nanos() as u64;
+            self.phases[phase as usize].fetch_add(elapsed, Ordering::Relaxed);
+        }
+    }
+
+    pub fn time<T, F: FnOnce() -> T>(&self, phase: Phase, f: F) -> T {
+        let started = self.start();
+        let result = f();
+        self.add(phase, started);
+        result
+    }
+
+    pub fn add_thread(&self, kind: &'static str, busy: Duration) {
+        if self.enabled {
+            self.threads.lock().unwrap().push((kind, busy));
+        }
+    }
+
+    // Measures the time spent in `read` of files that are read in chunks
+    pub fn reader<R: Read>(&self, inner: R) -> Timed<'_, R> {
+        Timed {
+            inner,
+            profile: self,
+        }
+    }
+
+    fn phase(&self, phase: Phase) -> Duration {
+        Duration::from_nanos(self.phases[phase as usize].load(Ordering::Relaxed))
+    }
+
+    // Prints the phases with their share of the time all threads were
+    // busy, and how much of `elapsed` every thread was busy
+    pub fn report<W: Write>(&self, out: &mut W, elapsed: Duration) -> io::Result<()> {
+        let times: Vec<(&str, Duration)> = PHASES
+            .iter()
+            .map(|&(phase, name)| match phase {
+                Phase::Match => (
+                    name,
+                    self.phase(phase).saturating_sub(self.phase(Phase::Read)),
+                ),
+                _ => (name, self.phase(phase)),
+            })
+            .collect();
+        let total: Duration = times.iter().map(|&(_, time)| time).sum();
+        let share = |time: Duration, of: Duration| {
+            if of.is_zero() {
+                0.0
+            } else {
+                time.as_secs_f64() / of.as_secs_f64() * 100.0
+            }
+        };
+
+        writeln!(out, "{:<16} {:>10} {:>8}", "phase", "time", "share")?;
+        for (name, time) in times {
+            writeln!(
+                out,
+                "{:<16} {:>10.3} {:>7.1}%",
+                name,
+                time.as_secs_f64(),
+                share(time, total)
+            )?;
+        }
+
+        let mut threads = self.threads.lock().unwrap().clone();
+        threads.sort_by_key(|&(kind, _)| kind != "walk");
+
+        writeln!(out)?;
+        writeln!(out, "{:<16} {:>10} {:>8}", "thread", "busy", "usage")?;
+        let mut numbers = (0, 0);
+        for (kind, busy) in threads {
+            let number = if kind == "walk" {
+                &mut numbers.0
+            } else {
+                &mut numbers.1
+            };
+            *number += 1;
+
+            writeln!(
+                out,
+                "{:<16} {:>10.3} {:>7.1}%",
+                format!("{} {}", kind, number),
+                busy.as_secs_f64(),
+                share(busy, elapsed)
+            )?;
+        }
+        writeln!(out, "{:<16} {:>10.3}", "total", elapsed.as_secs_f64())
+    }
+}
+
+pub struct Timed<'a, R> {
+    inner: R,
+    profile: &'a Profile,
+}
+
+impl<R: Read> Read for Timed<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let started = self.profile.start();
+        let read = self.inner.read(buf);
+        self.profile.add(Phase::Read, started);
+        read
+    }
+}
@@ -0,0 +1,251 @@
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Counters shared between the walker and the workers
+// Relaxed ordering is enough, the values are only displayed
+#[derive(Default)]
+pub struct Progress {
+    files: AtomicU64,
+    matches: AtomicU64,
+    bytes: AtomicU64,
+    traversed: AtomicU64,
+    ignored: AtomicU64,
+    binary: AtomicU64,
+    // Nanoseconds since the start, 0 while still running
+    walk_time: AtomicU64,
+    search_time: AtomicU64,
+}
+
+impl Progress {
+    // Number of files searched, or found in file search
+    pub fn files(&self) -> u64 {
+        self.files.load(Ordering::Relaxed)
+    }
+
+    // Number of matching lines, or matches in count mode
+    pub fn matches(&self) -> u64 {
+        self.matches.load(Ordering::Relaxed)
+    }
+
+    // Number of bytes of searched files
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    // Number of files checked against the filters
+    pub fn traversed(&self) -> u64 {
+        self.traversed.load(Ordering::Relaxed)
+    }
+
+    // Number of files and directories skipped for being hidden,
+    // excluded or ignored, directories count once
+    pub fn ignored(&self) -> u64 {
+        self.ignored.load(Ordering::Relaxed)
+    }
+
+    // Number of files skipped for being binary
+    pub fn binary(&self) -> u64 {
+        self.binary.load(Ordering::Relaxed)
+    }
+
+    // Time it took to walk the tree, `None` while still walking
+    pub fn walk_time(&self) -> Option<Duration> {
+        nanos(&self.walk_time)
+    }
+
+    // Time until the last file was searched, `None` while still searching
+    pub fn search_time(&self) -> Option<Duration> {
+        nanos(&self.search_time).max(self.walk_time())
+    }
+
+    pub(crate) fn add_traversed(&self) {
+        self.traversed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_ignored(&self) {
+        self.ignored.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_binary(&self) {
+        self.binary.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn walk_finished(&self, elapsed: Duration) {
+        self.walk_time
+            .store(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    // Called by every worker, the last one determines the time
+    pub(crate) fn search_finished(&self, elapsed: Duration) {
+        self.search_time
+            .fetch_max(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_file(&self) {
+        self.files.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_matches(&self, matches: usize) {
+        self.matches.fetch_add(matches as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn status(&self) -> String {
+        format!(
+            "{} files, {} matches, {}",
+            self.files(),
+            self.matches(),
+            format_size(self.bytes())
+        )
+    }
+}
+
+// Prints the progress to stderr until `finish` is called
+// On a terminal the status line is updated in place, otherwise
+// a new line is printed every few seconds
+pub struct Reporter {
+    progress: Arc<Progress>,
+    done: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+    terminal: bool,
+}
+
+impl Reporter {
+    pub fn start(progress: Arc<Progress>) -> Reporter {
+        let terminal = io::stderr().is_terminal();
+        let done = Arc::new(AtomicBool::new(false));
+
+        let interval = if terminal {
+            Duration::from_millis(100)
+        } else {
+            Duration::from_secs(5)
+        };
+
+        let thread = {
+            let progress = progress.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut elapsed = Duration::from_secs(0);
+                while !done.load(Ordering::Relaxed) {
+                    // Sleep in short steps to finish without delay
+                    thread::sleep(Duration::from_millis(50));
+                    elapsed += Duration::from_millis(50);
+                    if elapsed < interval {
+                        continue;
+                    }
+
+                    elapsed = Duration::from_secs(0);
+                    if terminal {
+                        eprint!("\r\x1b[K{}", progress.status());
+                        let _ = io::stderr().flush();
+                    } else {
+                        eprintln!("{}", progress.status());
+                    }
+                }
+            })
+        };
+
+        Reporter {
+            progress,
+            done,
+            thread: Some(thread),
+            terminal,
+        }
+    }
+
+    // Stops updating and prints the final status
+    pub fn finish(mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        if self.terminal {
+            eprintln!("\r\x1b[K{}", self.progress.status());
+        } else {
+            eprintln!("{}", self.progress.status());
+        }
+    }
+}
+
+// Files and lines every content pattern matched, see `--pattern-stats`
+// Without any names nothing is counted
+#[derive(Default)]
+pub struct PatternStats {
+    names: Vec<String>,
+    files: Vec<AtomicU64>,
+    lines: Vec<AtomicU64>,
+}
+
+impl PatternStats {
+    pub fn new(names: Vec<String>) -> PatternStats {
+        let counters = || names.iter().map(|_| AtomicU64::new(0)).collect();
+        PatternStats {
+            files: counters(),
+            lines: counters(),
+            names,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.names.is_empty()
+    }
+
+    // Adds the lines of a file matched by every pattern, in their order
+    pub fn add(&self, lines: &[u64]) {
+        let counters = self.files.iter().zip(&self.lines);
+        for ((files, total), &lines) in counters.zip(lines).filter(|(_, &l)| l > 0) {
+            files.fetch_add(1, Ordering::Relaxed);
+            total.fetch_add(lines, Ordering::Relaxed);
+        }
+    }
+
+    // Every pattern with its files and lines, like `--profile`
+    pub fn report<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let width = self.names.iter().map(|n| n.chars().count()).max();
+        let width = width.unwrap_or(0).max("pattern".len());
+        writeln!(out, "{:<width$} {:>10} {:>10}", "pattern", "files", "lines")?;
+        for ((name, files), lines) in self.names.iter().zip(&self.files).zip(&self.lines) {
+            writeln!(
+                out,
+                "{:<width$} {:>10} {:>10}",
+                name,
+                files.load(Ordering::Relaxed),
+                lines.load(Ordering::Relaxed)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn nanos(value: &AtomicU64) -> Option<Duration> {
+    match value.load(Ordering::Relaxed) {
+        0 => None,
+        n => Some(Duration::from_nanos(n)),
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
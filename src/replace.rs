@@ -0,0 +1,125 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::Config;
+
+// Replacement for every match, `$1` or `$name` refer to groups
+// of regex patterns, literal patterns are replaced verbatim
+pub struct Replace {
+    pub text: String,
+    pub expand: bool,
+    // Only print the changes without writing them
+    pub dry_run: bool,
+    // Keep the original file as `<file>.bak`
+    pub backup: bool,
+}
+
+// A line changed by the replacement
+pub struct Change {
+    pub line: usize,
+    pub before: String,
+    pub after: String,
+}
+
+// Replaces every match in a file, returns the changed lines
+// The file is written to a temporary file next to it first,
+// which is then renamed over the original so it's never half-written
+// The backup is named after `path`, even if it's a symlink
+pub fn rewrite(config: &Config, replace: &Replace, path: &Path) -> io::Result<Vec<Change>> {
+    let matcher = config.content.as_ref().unwrap();
+    let data = fs::read(path)?;
+
+    if !config.encoding.is_utf8(&data[..data.len().min(2)]) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "replacing is only supported in UTF-8 files",
+        ));
+    }
+
+    let mut changes = Vec::new();
+    let mut output = Vec::with_capacity(data.len());
+
+    for (i, line) in data.split_inclusive(|&b| b == b'\n').enumerate() {
+        let (text, terminator) = split_terminator(line);
+
+        // Lines that aren't valid UTF-8 are kept as they are
+        let replaced = match std::str::from_utf8(text) {
+            Ok(text) => matcher.replace_all(text, &replace.text, replace.expand),
+            Err(_) => None,
+        };
+
+        match replaced {
+            Some(after) if after.as_bytes() != text => {
+                output.extend_from_slice(after.as_bytes());
+                changes.push(Change {
+                    line: i + 1,
+                    before: String::from_utf8_lossy(text).into_owned(),
+                    after,
+                });
+            }
+            _ => output.extend_from_slice(text),
+        }
+        output.extend_from_slice(terminator);
+    }
+
+    if !changes.is_empty() && !replace.dry_run {
+        write(path, &output, replace.backup)?;
+    }
+
+    Ok(changes)
+}
+
+fn split_terminator(line: &[u8]) -> (&[u8], &[u8]) {
+    let length = if line.ends_with(b"\r\n") {
+        2
+    } else if line.ends_with(b"\n") {
+        1
+    } else {
+        0
+    };
+
+    line.split_at(line.len() - length)
+}
+
+// Symlinks are resolved first, renaming over the link itself would replace
+// it by a regular file and leave its target untouched
+fn write(path: &Path, data: &[u8], backup: bool) -> io::Result<()> {
+    let target = fs::canonicalize(path)?;
+    let permissions = fs::metadata(&target)?.permissions();
+    let temporary = temporary_path(&target);
+
+    let result = (|| {
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::set_permissions(&temporary, permissions)?;
+
+        if backup {
+            let mut name = path.as_os_str().to_owned();
+            name.push(".bak");
+            fs::copy(path, PathBuf::from(name))?;
+        }
+
+        fs::rename(&temporary, &target)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+
+    result
+}
+
+// Temporary files are created in the same directory, renames
+// across file systems aren't atomic
+fn temporary_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(".{}.lps-{}.tmp", name, process::id()))
+}
@@ -0,0 +1,211 @@
+use std::collections::hash_map::Entry;
+use std::io;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::context::Cancel;
+use crate::printer::JsonPrinter;
+use crate::serve::{strings, unsupported, Session};
+
+// Error codes of the JSON-RPC 2.0 specification
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// Between two `progress` notifications
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+// Handles a JSON-RPC 2.0 message of a `--serve` client, one per line
+// `search` runs in the background so it can be cancelled, its results are
+// sent as `result` notifications before the response
+// `cancel` stops the search with the id in its parameters
+// Messages without an id are notifications and never answered
+pub fn message<'scope, 'env>(
+    scope: &'scope thread::Scope<'scope, 'env>,
+    session: &'env Session<'env>,
+    line: &str,
+) -> io::Result<()> {
+    let message: Value = match serde_json::from_str(line) {
+        Ok(m) => m,
+        Err(err) => return session.send(&error(&Value::Null, PARSE_ERROR, &err.to_string())),
+    };
+
+    let id = message.get("id").cloned();
+    let method = match (&message["jsonrpc"], message["method"].as_str()) {
+        (Value::String(version), Some(method)) if version == "2.0" => method,
+        _ => {
+            let id = id.unwrap_or(Value::Null);
+            return session.send(&error(&id, INVALID_REQUEST, "invalid request"));
+        }
+    };
+    let params = &message["params"];
+
+    match (method, id) {
+        ("cancel", id) => {
+            let cancelled = match session
+                .running
+                .lock()
+                .unwrap()
+                .get(&params["id"].to_string())
+            {
+                Some(cancel) => {
+                    cancel.cancel();
+                    true
+                }
+                None => false,
+            };
+            match id {
+                Some(id) => {
+                    session.send(&json!({ "jsonrpc": "2.0", "id": id, "result": cancelled }))
+                }
+                None => Ok(()),
+            }
+        }
+        ("search", Some(id)) => {
+            // Registered before it starts, so it can be cancelled right away
+            let key = id.to_string();
+            match session.running.lock().unwrap().entry(key) {
+                Entry::Occupied(_) => {
+                    let message = "a search with this id is running";
+                    return session.send(&error(&id, INVALID_REQUEST, message));
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(Cancel::default());
+                }
+            }
+
+            let params = params.clone();
+            scope.spawn(move || {
+                // Only fails if the client went away
+                let response = search(session, &id, &params);
+                session.running.lock().unwrap().remove(&id.to_string());
+                if let Ok(response) = response {
+                    let _ = session.send(&response);
+                }
+            });
+            Ok(())
+        }
+        (_, None) => Ok(()),
+        (method, Some(id)) => {
+            let message = format!("unknown method {}", method);
+            session.send(&error(&id, METHOD_NOT_FOUND, &message))
+        }
+    }
+}
+
+// `{"args": [...], "progress": bool}` with the arguments like on the
+// command line, answered with `{"matched", "errors", "cancelled"}`
+// Returns the response once all results were sent
+fn search(session: &Session<'_>, id: &Value, params: &Value) -> io::Result<Value> {
+    let args = match strings(&params["args"]) {
+        Some(a) => a,
+        None => {
+            return Ok(error(
+                id,
+                INVALID_PARAMS,
+                "args has to be an array of strings",
+            ))
+        }
+    };
+    let config = match session.server.parse(&args) {
+        Ok(c) => c,
+        Err(err) => return Ok(error(id, INVALID_PARAMS, &err.to_string())),
+    };
+    if let Some(message) = unsupported(&config) {
+        return Ok(error(id, INVALID_PARAMS, message));
+    }
+
+    let key = id.to_string();
+    let progress = params["progress"].as_bool().unwrap_or(false);
+    let done = AtomicBool::new(false);
+    let mut notifier = Notifier {
+        session,
+        id,
+        line: Vec::new(),
+    };
+
+    let mut printer = JsonPrinter::new(&mut notifier, &config);
+
+    let summary = thread::scope(|scope| {
+        let summary = session.server.answer(config, &mut printer, |results| {
+            let mut running = session.running.lock().unwrap();
+            if running.get(&key).is_some_and(|c| c.is_cancelled()) {
+                results.cancel();
+            }
+            running.insert(key, results.context.cancel.clone());
+            drop(running);
+
+            if progress {
+                let counters = results.progress().clone();
+                let done = &done;
+                scope.spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        thread::sleep(PROGRESS_INTERVAL);
+                        let params = json!({
+                            "id": id,
+                            "files": counters.files(),
+                            "matches": counters.matches(),
+                            "bytes": counters.bytes(),
+                        });
+                        if session.send(&notification("progress", params)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        done.store(true, Ordering::Relaxed);
+        summary
+    });
+
+    let summary = summary?;
+    let result = json!({
+        "matched": summary.matched,
+        "errors": summary.errors,
+        "cancelled": summary.interrupted,
+    });
+    Ok(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn error(id: &Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+// Sends every line `JsonPrinter` writes as a `result` notification
+struct Notifier<'a> {
+    session: &'a Session<'a>,
+    id: &'a Value,
+    line: Vec<u8>,
+}
+
+impl Write for Notifier<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        while let Some(end) = self.line.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.line.drain(..=end).collect();
+            let result: Value = serde_json::from_slice(&line)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            let params = json!({ "id": self.id, "result": result });
+            self.session.send(&notification("result", params))?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use serde_json::{json, Value};
+
+use crate::cache::Cache;
+use crate::context::Cancel;
+use crate::printer::{JsonPrinter, ResultPrinter};
+use crate::rpc;
+use crate::{sort, Config, SearchError, SearchResults, SortBy, Summary};
+
+// `host:port` or the path of a Unix socket
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+type Connection = (Box<dyn Read + Send>, Box<dyn Write + Send>);
+
+impl Listener {
+    fn bind(address: &str) -> io::Result<Listener> {
+        let tcp = !address.contains('/')
+            && address.rsplit_once(':').is_some_and(|(_, port)| {
+                !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit())
+            });
+        if tcp {
+            return TcpListener::bind(address).map(Listener::Tcp);
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+
+            // Left behind by a server that was killed, nobody answers on it
+            let stale = std::fs::symlink_metadata(address).is_ok_and(|m| m.file_type().is_socket())
+                && UnixStream::connect(address).is_err();
+            if stale {
+                std::fs::remove_file(address)?;
+            }
+            UnixListener::bind(address).map(Listener::Unix)
+        }
+
+        #[cfg(not(unix))]
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unix sockets are only supported on unix, use host:port",
+        ))
+    }
+
+    fn accept(&self) -> io::Result<Connection> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+            }
+        }
+    }
+}
+
+// Turns the arguments of a request into a configuration
+pub type Parse<'a> = dyn Fn(&[String]) -> Result<Arc<Config>, Box<dyn Error>> + Sync + 'a;
+
+// What the connections of a server share
+pub struct Server<'a> {
+    // Listings of all searches so far
+    warm: Mutex<Arc<Cache>>,
+    parse: &'a Parse<'a>,
+}
+
+// One client, requests are answered in order unless sent with JSON-RPC
+pub struct Session<'a> {
+    pub server: &'a Server<'a>,
+    out: Mutex<BufWriter<Box<dyn Write + Send>>>,
+    // Cancels the searches of JSON-RPC requests by their id
+    pub running: Mutex<HashMap<String, Cancel>>,
+}
+
+impl Session<'_> {
+    // Writes a whole line at once, messages of searches running in
+    // parallel never interleave
+    pub fn send(&self, message: &Value) -> io::Result<()> {
+        let mut out = self.out.lock().unwrap();
+        writeln!(out, "{}", message)?;
+        out.flush()
+    }
+}
+
+// Lists the tree of `config` once and answers searches on `address` until
+// killed, keeping the directory listings of all searches in memory
+// `-` answers the requests on stdin on stdout instead
+// Every line sent by a client is a JSON array of arguments, turned into a
+// configuration by `parse`, the results are sent back as JSON lines like
+// `--format json` followed by `{"done": true, ...}`
+// Lines with a JSON object are JSON-RPC messages, see `rpc`
+pub fn serve<F>(config: Arc<Config>, address: &str, parse: F) -> Result<(), Box<dyn Error>>
+where
+    F: Fn(&[String]) -> Result<Arc<Config>, Box<dyn Error>> + Sync,
+{
+    let listener = if address == "-" {
+        None
+    } else {
+        Some(
+            Listener::bind(address)
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", address, err)))?,
+        )
+    };
+
+    let server = Server {
+        warm: Mutex::new(Arc::new(Cache::memory())),
+        parse: &parse,
+    };
+    let (config, cache) = server.with_listings(config);
+    let mut results = crate::search(config.clone());
+    let files = results.by_ref().count();
+    for error in results.remaining_errors() {
+        crate::report(&config, &error);
+    }
+    *server.warm.lock().unwrap() = Arc::new(cache.merged());
+
+    let listener = match listener {
+        Some(l) => l,
+        None => {
+            handle(Box::new(io::stdin()), Box::new(io::stdout()), &server);
+            return Ok(());
+        }
+    };
+    if !config.quiet {
+        eprintln!("listed {} files, listening on {}", files, address);
+    }
+
+    thread::scope(|scope| loop {
+        let (reader, writer) = match listener.accept() {
+            Ok(c) => c,
+            Err(err) => {
+                eprintln!("warning: {}: {}", address, err);
+                continue;
+            }
+        };
+
+        let server = &server;
+        scope.spawn(move || handle(reader, writer, server));
+    });
+
+    Ok(())
+}
+
+// Serves a client until it stops sending and its searches have finished,
+// searches whose results can't be sent anymore stop on their own
+fn handle(reader: Box<dyn Read + Send>, writer: Box<dyn Write + Send>, server: &Server<'_>) {
+    let session = Session {
+        server,
+        out: Mutex::new(BufWriter::new(writer)),
+        running: Mutex::new(HashMap::new()),
+    };
+
+    thread::scope(|scope| {
+        for line in BufReader::new(reader).lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => break,
+            };
+
+            let answered = match line.trim_start().as_bytes().first() {
+                None => Ok(()),
+                Some(b'{') => rpc::message(scope, &session, &line),
+                Some(_) => session.request(&line),
+            };
+            if answered.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+impl Session<'_> {
+    // A JSON array of arguments, answered before the next line is read
+    fn request(&self, line: &str) -> io::Result<()> {
+        let config = match arguments(line).and_then(|args| self.server.parse(&args)) {
+            Ok(c) => c,
+            Err(err) => return self.send(&json!({ "error": err.to_string() })),
+        };
+        if let Some(message) = unsupported(&config) {
+            return self.send(&json!({ "error": message }));
+        }
+
+        let mut out = self.out.lock().unwrap();
+        let mut printer = JsonPrinter::new(&mut *out, &config);
+        let summary = self.server.answer(config, &mut printer, |_| {})?;
+        let done = json!({
+            "done": true,
+            "matched": summary.matched,
+            "errors": summary.errors,
+        });
+        writeln!(out, "{}", done)?;
+        out.flush()
+    }
+}
+
+fn arguments(line: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let invalid = || -> Box<dyn Error> {
+        Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a request is a JSON array of arguments",
+        ))
+    };
+
+    let value: Value = serde_json::from_str(line).map_err(|_| invalid())?;
+    strings(&value).ok_or_else(invalid)
+}
+
+pub fn strings(value: &Value) -> Option<Vec<String>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|a| a.as_str().map(String::from))
+        .collect()
+}
+
+// Options that don't print to `--format json`
+pub fn unsupported(config: &Config) -> Option<&'static str> {
+    let unsupported = config.interactive
+        || config.watch
+        || config.exec.is_some()
+        || config.cat
+        || config.duplicates
+        || config.profile
+        || config.pattern_stats.is_some();
+    if unsupported {
+        Some("interactive, watch, exec, cat, duplicates, profile and pattern stats can't be requested")
+    } else {
+        None
+    }
+}
+
+impl Server<'_> {
+    pub fn parse(&self, args: &[String]) -> Result<Arc<Config>, Box<dyn Error>> {
+        (self.parse)(args)
+    }
+
+    // Searches with the listings of the previous searches
+    fn with_listings(&self, mut config: Arc<Config>) -> (Arc<Config>, Arc<Cache>) {
+        let cache = self.warm.lock().unwrap().clone();
+        // Only the caller has the configuration yet
+        if let Some(config) = Arc::get_mut(&mut config) {
+            config.listings = Some(cache.clone());
+        }
+        (config, cache)
+    }
+
+    // Prints the results of a request like `run`, `started` gets the search
+    // before its first result to cancel it or follow its progress
+    pub fn answer<W: Write, F: FnOnce(&SearchResults)>(
+        &self,
+        config: Arc<Config>,
+        printer: &mut JsonPrinter<W>,
+        started: F,
+    ) -> io::Result<Summary> {
+        let started_at = Instant::now();
+        let (config, cache) = self.with_listings(config);
+        let mut summary = Summary {
+            matched: false,
+            errors: 0,
+            interrupted: false,
+        };
+        let mut results = crate::search(config.clone());
+        let progress = results.progress().clone();
+        started(&results);
+
+        let mut buffered = Vec::new();
+        while let Some(result) = results.next() {
+            summary.matched = true;
+            if config.sort == SortBy::None && !config.group_by_dir {
+                crate::print(&config, printer, &result, &mut summary)?;
+            } else {
+                buffered.push(result);
+            }
+
+            for error in results.errors() {
+                send_error(&config, printer, &error, &mut summary)?;
+            }
+        }
+
+        sort::sort(&mut buffered, &config.sort);
+        if config.group_by_dir {
+            buffered.sort_by(|a, b| a.path.parent().cmp(&b.path.parent()));
+        }
+        for result in &buffered {
+            crate::print(&config, printer, result, &mut summary)?;
+        }
+
+        // `max_total` cancels as well, but isn't an interruption
+        let limited = config.max_total.is_some_and(|max| results.total >= max);
+        summary.interrupted = results.context.cancel.is_cancelled() && !limited;
+
+        for error in results.remaining_errors() {
+            send_error(&config, printer, &error, &mut summary)?;
+        }
+        if config.stats {
+            printer.stats(&progress, summary.errors, started_at.elapsed())?;
+        }
+
+        *self.warm.lock().unwrap() = Arc::new(cache.merged());
+        Ok(summary)
+    }
+}
+
+// Reported like `report` does on stderr
+fn send_error<W: Write>(
+    config: &Config,
+    printer: &mut JsonPrinter<W>,
+    error: &SearchError,
+    summary: &mut Summary,
+) -> io::Result<()> {
+    summary.errors += 1;
+    if config.quiet || !(error.fatal || config.verbose()) {
+        return Ok(());
+    }
+
+    printer.error(&error.path, &error.error.to_string())
+}
@@ -0,0 +1,249 @@
+use std::convert::TryFrom;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+use crate::cli;
+use crate::{Colors, LogLevel, Search};
+
+// Defaults from the config file, applied before the command line
+// so every option can still be overridden there
+// Either `~/.lpsrc` or `$XDG_CONFIG_HOME/lps/lps.toml`, both in TOML:
+//
+//     dop = 4
+//     color = "never"
+//     exclude-dir = ["target", "node_modules"]
+//     regex = true
+#[derive(Default)]
+pub struct Settings {
+    dop: Option<usize>,
+    walk_threads: Option<usize>,
+    search_threads: Option<usize>,
+    color: Option<String>,
+    no_ignore: bool,
+    hidden: bool,
+    follow: bool,
+    regex: bool,
+    max_depth: Option<usize>,
+    exclude: Vec<String>,
+    exclude_dir: Vec<String>,
+    colors: Option<Colors>,
+    log: Option<LogLevel>,
+    // Text of the config file, part of the cache key
+    source: String,
+}
+
+impl Settings {
+    // Reads the first config file found, a missing file isn't an error
+    pub fn load() -> Result<Settings, Box<dyn Error>> {
+        for path in config_paths() {
+            match fs::read_to_string(&path) {
+                Ok(text) => return Settings::parse(&text, &path),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(invalid(&path, &err.to_string())),
+            }
+        }
+
+        Ok(Settings::default())
+    }
+
+    fn parse(text: &str, path: &Path) -> Result<Settings, Box<dyn Error>> {
+        let table = match text.parse::<Table>() {
+            Ok(t) => t,
+            Err(err) => return Err(invalid(path, err.message())),
+        };
+
+        let mut settings = Settings {
+            source: String::from(text),
+            ..Settings::default()
+        };
+        for (key, value) in &table {
+            let wrong_type = || invalid(path, &format!("invalid value for {}", key));
+
+            match key.as_str() {
+                "dop" => settings.dop = Some(to_usize(value).ok_or_else(wrong_type)?),
+                "walk-threads" => {
+                    settings.walk_threads = Some(to_usize(value).ok_or_else(wrong_type)?)
+                }
+                "search-threads" => {
+                    settings.search_threads = Some(to_usize(value).ok_or_else(wrong_type)?)
+                }
+                "max-depth" => settings.max_depth = Some(to_usize(value).ok_or_else(wrong_type)?),
+                "color" => match value.as_str() {
+                    Some(when @ ("auto" | "always" | "never")) => {
+                        settings.color = Some(String::from(when))
+                    }
+                    _ => return Err(wrong_type()),
+                },
+                "no-ignore" => settings.no_ignore = value.as_bool().ok_or_else(wrong_type)?,
+                "hidden" => settings.hidden = value.as_bool().ok_or_else(wrong_type)?,
+                "follow" => settings.follow = value.as_bool().ok_or_else(wrong_type)?,
+                "regex" => settings.regex = value.as_bool().ok_or_else(wrong_type)?,
+                "exclude" => settings.exclude = to_strings(value).ok_or_else(wrong_type)?,
+                "exclude-dir" => settings.exclude_dir = to_strings(value).ok_or_else(wrong_type)?,
+                _ => return Err(invalid(path, &format!("unknown option {}", key))),
+            }
+        }
+
+        Ok(settings)
+    }
+
+    // Environment variables override the config file
+    // `LPS_DOP` sets the degree of parallelism, `LPS_COLORS` the colors
+    // like `file=35:match=1;31` and `LPS_IGNORE` adds excludes for files
+    // and directories, separated like `PATH`
+    // `LPS_LOG` sets the log level like `debug` or `2`, same as `-vv`
+    pub fn with_env(mut self) -> Result<Settings, Box<dyn Error>> {
+        if let Some(dop) = env::var_os("LPS_DOP") {
+            match dop.to_str().and_then(|s| s.parse::<usize>().ok()) {
+                Some(dop) => self.dop = Some(dop),
+                None => return Err(invalid_env("LPS_DOP")),
+            }
+        }
+
+        if let Some(spec) = env::var_os("LPS_COLORS") {
+            let spec = spec.to_str().ok_or_else(|| invalid_env("LPS_COLORS"))?;
+            self.colors = Some(Colors::parse(spec)?);
+        }
+
+        if let Some(level) = env::var_os("LPS_LOG") {
+            match level.to_str().and_then(LogLevel::parse) {
+                Some(level) => self.log = Some(level),
+                None => return Err(invalid_env("LPS_LOG")),
+            }
+        }
+
+        if let Some(patterns) = env::var_os("LPS_IGNORE") {
+            for pattern in env::split_paths(&patterns) {
+                let pattern = pattern.to_string_lossy().into_owned();
+                if pattern.is_empty() {
+                    continue;
+                }
+
+                self.exclude.push(pattern.clone());
+                self.exclude_dir.push(pattern);
+            }
+        }
+
+        Ok(self)
+    }
+
+    // Applies the defaults to the configuration
+    pub fn apply(&self, mut search: Search) -> Search {
+        if let Some(level) = self.log {
+            search = search.log_level(level);
+        }
+
+        if let Some(dop) = self.dop {
+            search = search.dop(dop);
+        }
+
+        if let Some(threads) = self.walk_threads {
+            search = search.walk_threads(threads);
+        }
+
+        if let Some(threads) = self.search_threads {
+            search = search.search_threads(threads);
+        }
+
+        if let Some(depth) = self.max_depth {
+            search = search.max_depth(depth);
+        }
+
+        for pattern in &self.exclude {
+            search = search.exclude(pattern);
+        }
+
+        for pattern in &self.exclude_dir {
+            search = search.exclude_dir(pattern);
+        }
+
+        if let Some(colors) = &self.colors {
+            search = search.colors(colors.clone());
+        }
+
+        search
+            .color(cli::use_color(self.color.as_deref()))
+            .no_ignore(self.no_ignore)
+            .hidden(self.hidden)
+            .follow(self.follow)
+            .regex(self.regex)
+    }
+
+    // Whether content is a regular expression unless `--fixed-strings` is given
+    pub fn regex(&self) -> bool {
+        self.regex
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+// `$XDG_CACHE_HOME/lps`, `~/.cache/lps` or `%LOCALAPPDATA%\lps` on Windows
+pub fn cache_dir() -> Option<PathBuf> {
+    let var = |name| {
+        env::var_os(name)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+
+    if cfg!(windows) {
+        return var("LOCALAPPDATA").map(|dir| dir.join("lps"));
+    }
+
+    match var("XDG_CACHE_HOME") {
+        Some(dir) => Some(dir.join("lps")),
+        None => var("HOME").map(|home| home.join(".cache").join("lps")),
+    }
+}
+
+fn config_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let home = env::var_os("HOME").map(PathBuf::from);
+
+    if let Some(home) = &home {
+        paths.push(home.join(".lpsrc"));
+    }
+
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => paths.push(PathBuf::from(dir).join("lps").join("lps.toml")),
+        _ => {
+            if let Some(home) = &home {
+                paths.push(home.join(".config").join("lps").join("lps.toml"));
+            }
+        }
+    }
+
+    paths
+}
+
+fn to_usize(value: &Value) -> Option<usize> {
+    value.as_integer().and_then(|i| usize::try_from(i).ok())
+}
+
+fn to_strings(value: &Value) -> Option<Vec<String>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|v| v.as_str().map(String::from))
+        .collect()
+}
+
+fn invalid(path: &Path, message: &str) -> Box<dyn Error> {
+    Box::new(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid config file {}: {}", path.display(), message),
+    ))
+}
+
+fn invalid_env(name: &str) -> Box<dyn Error> {
+    Box::new(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid value of {}", name),
+    ))
+}
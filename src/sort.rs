@@ -0,0 +1,36 @@
+use std::fs;
+use std::path::Path;
+
+use crate::FileMatch;
+
+// Order of the results printed by `run`
+#[derive(PartialEq)]
+pub enum SortBy {
+    // Results are printed as soon as they are found, in no particular order
+    None,
+    Path,
+    // Oldest first
+    Modified,
+    // Smallest first
+    Size,
+}
+
+// Orders results of a finished search, ties are broken by path
+// Archive entries have no metadata of their own and sort first
+pub fn sort(results: &mut [FileMatch], by: &SortBy) {
+    match by {
+        SortBy::None => {}
+        SortBy::Path => results.sort_by(|a, b| a.path.cmp(&b.path)),
+        SortBy::Modified => results.sort_by_cached_key(|r| {
+            let modified = metadata(&r.path).and_then(|m| m.modified().ok());
+            (modified, r.path.clone())
+        }),
+        SortBy::Size => {
+            results.sort_by_cached_key(|r| (metadata(&r.path).map(|m| m.len()), r.path.clone()))
+        }
+    }
+}
+
+fn metadata(path: &Path) -> Option<fs::Metadata> {
+    fs::metadata(path).ok()
+}
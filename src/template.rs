@@ -0,0 +1,147 @@
+use std::error::Error;
+use std::io;
+use std::path::Path;
+
+use crate::platform;
+use crate::printer::Columns;
+use crate::LineMatch;
+
+// Value substituted for a placeholder
+#[derive(Clone, Copy)]
+enum Field {
+    File,
+    Line,
+    Column,
+    End,
+    Offset,
+    // The matched text
+    Match,
+    // The whole line containing the match
+    Text,
+    Count,
+}
+
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+// Output layout given by `--format-template`, e.g. `{file}:{line}: {text}`
+// `{{` and `}}` are literal braces, `\t` and `\n` are tab and newline
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Template, Box<dyn Error>> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| invalid("unclosed placeholder"))?;
+                    let field = match &rest[..end] {
+                        "file" => Field::File,
+                        "line" => Field::Line,
+                        "col" | "column" => Field::Column,
+                        "end" => Field::End,
+                        "offset" => Field::Offset,
+                        "match" => Field::Match,
+                        "text" => Field::Text,
+                        "count" => Field::Count,
+                        name => return Err(invalid(&format!("unknown placeholder {{{}}}", name))),
+                    };
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(literal.split_off(0)));
+                    }
+                    parts.push(Part::Field(field));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(invalid("unmatched }")),
+                '\\' if chars.as_str().starts_with('t') => {
+                    chars.next();
+                    literal.push('\t');
+                }
+                '\\' if chars.as_str().starts_with('n') => {
+                    chars.next();
+                    literal.push('\n');
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Template { parts })
+    }
+
+    // Fills in the placeholders, ones without a value stay empty
+    // e.g. `{line}` in file search
+    // Columns and ends are counted like `columns`
+    pub fn render(
+        &self,
+        path: &Path,
+        line: Option<&LineMatch>,
+        count: Option<usize>,
+        columns: Columns,
+    ) -> String {
+        let mut out = String::new();
+
+        for part in &self.parts {
+            let field = match part {
+                Part::Literal(text) => {
+                    out.push_str(text);
+                    continue;
+                }
+                Part::Field(f) => *f,
+            };
+
+            match (field, line) {
+                (Field::File, _) => out.push_str(&platform::display(path)),
+                (Field::Count, _) => {
+                    if let Some(count) = count {
+                        out.push_str(&count.to_string());
+                    }
+                }
+                (Field::Line, Some(l)) => out.push_str(&l.line.to_string()),
+                (Field::Column, Some(l)) => {
+                    out.push_str(&columns.column(&l.content, l.column).to_string())
+                }
+                (Field::End, Some(l)) => {
+                    out.push_str(&columns.column(&l.content, l.end).to_string())
+                }
+                (Field::Offset, Some(l)) => out.push_str(&l.offset.to_string()),
+                (Field::Match, Some(l)) => {
+                    out.push_str(l.content.get(l.column..l.end).unwrap_or(""))
+                }
+                (Field::Text, Some(l)) => out.push_str(&l.content),
+                (_, None) => {}
+            }
+        }
+
+        out
+    }
+}
+
+fn invalid(message: &str) -> Box<dyn Error> {
+    Box::new(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid template: {}", message),
+    ))
+}
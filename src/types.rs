@@ -0,0 +1,100 @@
+use std::error::Error;
+use std::io;
+
+use crate::matcher::FilenameMatcher;
+
+// Built-in file types and the globs of their file names
+const BUILTIN: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    (
+        "cpp",
+        &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx", "*.h"],
+    ),
+    ("cs", &["*.cs"]),
+    ("css", &["*.css", "*.scss", "*.sass", "*.less"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.mjs", "*.cjs", "*.jsx"]),
+    ("json", &["*.json"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rb", &["*.rb", "Gemfile", "Rakefile"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("sql", &["*.sql"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx", "*.mts", "*.cts"]),
+    ("txt", &["*.txt"]),
+    ("xml", &["*.xml", "*.xsd", "*.xsl"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+// Known file types, the built-in ones can be extended with `add`
+pub struct TypeRegistry {
+    types: Vec<(String, Vec<String>)>,
+}
+
+impl TypeRegistry {
+    pub fn new() -> TypeRegistry {
+        let types = BUILTIN
+            .iter()
+            .map(|(name, globs)| {
+                let globs = globs.iter().map(|g| String::from(*g)).collect();
+                (String::from(*name), globs)
+            })
+            .collect();
+
+        TypeRegistry { types }
+    }
+
+    // Adds a definition like `web:*.html,*.css`, globs of
+    // existing types are extended
+    pub fn add(&mut self, definition: &str) -> Result<(), Box<dyn Error>> {
+        let (name, globs) = match definition.split_once(':') {
+            Some((name, globs)) if !name.is_empty() && !globs.is_empty() => (name, globs),
+            _ => {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid file type definition: {}", definition),
+                )));
+            }
+        };
+
+        let globs = globs.split(',').filter(|g| !g.is_empty()).map(String::from);
+        match self.types.iter_mut().find(|(n, _)| n == name) {
+            Some((_, existing)) => existing.extend(globs),
+            None => self.types.push((String::from(name), globs.collect())),
+        }
+
+        Ok(())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.types.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    // Compiles the globs of the given types, a file matches
+    // if any of them matches its name
+    pub fn matchers(&self, names: &[String]) -> Result<Vec<FilenameMatcher>, Box<dyn Error>> {
+        let mut matchers = Vec::new();
+
+        for name in names {
+            let globs = match self.types.iter().find(|(n, _)| n == name) {
+                Some((_, globs)) => globs,
+                None => {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("unknown file type: {}", name),
+                    )));
+                }
+            };
+
+            for glob in globs {
+                matchers.push(FilenameMatcher::glob(glob, false)?);
+            }
+        }
+
+        Ok(matchers)
+    }
+}
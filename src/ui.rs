@@ -0,0 +1,358 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::{cursor, execute, queue, terminal};
+
+use crate::platform;
+use crate::printer::Columns;
+use crate::{FileMatch, SearchError, SearchResults};
+
+// A single match, or a file in file search
+struct Entry {
+    path: PathBuf,
+    // 1-based, 0 in file search
+    line: usize,
+    column: usize,
+    content: String,
+}
+
+enum Message {
+    Found(Vec<Entry>),
+    // The search has finished
+    Done(Vec<SearchError>),
+}
+
+struct State {
+    entries: Vec<Entry>,
+    selected: usize,
+    // First entry shown in the list
+    scroll: usize,
+    done: bool,
+    // Lines of the file shown in the preview
+    preview: Option<(PathBuf, Vec<String>)>,
+}
+
+// Shows the results in a terminal UI while the search is running
+// Returns the number of results and the errors of the search once the UI is closed
+pub fn browse(results: SearchResults) -> Result<(usize, Vec<SearchError>), Box<dyn Error>> {
+    if !io::stdout().is_terminal() {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "interactive mode requires a terminal",
+        )));
+    }
+
+    // Results are received on a separate thread so the UI stays responsive
+    let cancel = results.context.cancel.clone();
+    let columns = results.config.columns;
+    let (sender, messages) = mpsc::channel();
+    let receiver = thread::spawn(move || {
+        let mut results = results;
+        for result in results.by_ref() {
+            if sender
+                .send(Message::Found(entries(result, columns)))
+                .is_err()
+            {
+                return;
+            }
+        }
+
+        let _ = sender.send(Message::Done(results.remaining_errors().collect()));
+    });
+
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    let mut state = State {
+        entries: Vec::new(),
+        selected: 0,
+        scroll: 0,
+        done: false,
+        preview: None,
+    };
+    let result = event_loop(&mut out, &mut state, &messages);
+
+    execute!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+
+    // Closing the UI early stops the search
+    cancel.cancel();
+    let mut errors = Vec::new();
+    for message in messages.iter() {
+        if let Message::Done(e) = message {
+            errors = e;
+        }
+    }
+    let _ = receiver.join();
+
+    result?;
+    Ok((state.entries.len(), errors))
+}
+
+fn entries(result: FileMatch, columns: Columns) -> Vec<Entry> {
+    let FileMatch { path, lines, .. } = result;
+    match lines {
+        Some(lines) => lines
+            .into_iter()
+            .map(|line| Entry {
+                path: path.clone(),
+                line: line.line,
+                column: columns.column(&line.content, line.column),
+                content: line.content,
+            })
+            .collect(),
+        None => vec![Entry {
+            path,
+            line: 0,
+            column: 0,
+            content: String::new(),
+        }],
+    }
+}
+
+fn event_loop(
+    out: &mut io::Stdout,
+    state: &mut State,
+    messages: &mpsc::Receiver<Message>,
+) -> io::Result<()> {
+    let mut dirty = true;
+
+    loop {
+        for message in messages.try_iter() {
+            match message {
+                Message::Found(entries) => state.entries.extend(entries),
+                Message::Done(_) => state.done = true,
+            }
+            dirty = true;
+        }
+
+        if dirty {
+            draw(out, state)?;
+            dirty = false;
+        }
+
+        if !event::poll(Duration::from_millis(50))? {
+            continue;
+        }
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            Event::Resize(..) => {
+                dirty = true;
+                continue;
+            }
+            _ => continue,
+        };
+
+        let page = list_height()?.max(1);
+        let last = state.entries.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Down | KeyCode::Char('j') => state.selected = (state.selected + 1).min(last),
+            KeyCode::Up | KeyCode::Char('k') => state.selected = state.selected.saturating_sub(1),
+            KeyCode::PageDown => state.selected = (state.selected + page).min(last),
+            KeyCode::PageUp => state.selected = state.selected.saturating_sub(page),
+            KeyCode::Home | KeyCode::Char('g') => state.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => state.selected = last,
+            KeyCode::Enter => {
+                if let Some(entry) = state.entries.get(state.selected) {
+                    open_editor(out, &entry.path, entry.line)?;
+                    // The file could have been changed in the editor
+                    state.preview = None;
+                }
+            }
+            _ => continue,
+        }
+        dirty = true;
+    }
+}
+
+// Rows of the result list, the rest of the screen shows the preview
+fn list_height() -> io::Result<usize> {
+    let (_, height) = terminal::size()?;
+    Ok((height as usize).saturating_sub(2) / 2)
+}
+
+fn draw(out: &mut io::Stdout, state: &mut State) -> io::Result<()> {
+    let (width, height) = terminal::size()?;
+    let (width, height) = (width as usize, height as usize);
+    let list_height = list_height()?;
+
+    // Keep the selection visible
+    if state.selected < state.scroll {
+        state.scroll = state.selected;
+    } else if state.selected >= state.scroll + list_height {
+        state.scroll = state.selected + 1 - list_height;
+    }
+
+    queue!(
+        out,
+        terminal::Clear(terminal::ClearType::All),
+        cursor::MoveTo(0, 0)
+    )?;
+
+    let status = format!(
+        " {} results{} - j/k move, enter opens $EDITOR, q quits",
+        state.entries.len(),
+        if state.done { "" } else { " (searching)" }
+    );
+    queue!(
+        out,
+        SetAttribute(Attribute::Reverse),
+        Print(fit(&status, width)),
+        SetAttribute(Attribute::Reset)
+    )?;
+
+    for (row, entry) in state
+        .entries
+        .iter()
+        .enumerate()
+        .skip(state.scroll)
+        .take(list_height)
+    {
+        let text = if entry.line == 0 {
+            platform::display(&entry.path).into_owned()
+        } else {
+            format!(
+                "{}:{}:{} {}",
+                platform::display(&entry.path),
+                entry.line,
+                entry.column,
+                entry.content.trim()
+            )
+        };
+
+        queue!(out, cursor::MoveTo(0, (row - state.scroll + 1) as u16))?;
+        if row == state.selected {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(fit(&text, width)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(out, Print(fit(&text, width)))?;
+        }
+    }
+
+    queue!(
+        out,
+        cursor::MoveTo(0, (list_height + 1) as u16),
+        SetForegroundColor(Color::DarkGrey),
+        Print("─".repeat(width)),
+        ResetColor
+    )?;
+
+    let preview_height = height.saturating_sub(list_height + 2);
+    if let Some(entry) = state.entries.get(state.selected) {
+        let path = entry.path.clone();
+        let line = entry.line;
+        draw_preview(
+            out,
+            state,
+            &path,
+            line,
+            list_height + 2,
+            preview_height,
+            width,
+        )?;
+    }
+
+    out.flush()
+}
+
+fn draw_preview(
+    out: &mut io::Stdout,
+    state: &mut State,
+    path: &Path,
+    line: usize,
+    top: usize,
+    height: usize,
+    width: usize,
+) -> io::Result<()> {
+    let cached = matches!(&state.preview, Some((p, _)) if p == path);
+    if !cached {
+        // Archive entries and unreadable files have no preview
+        let lines = fs::read(path)
+            .map(|data| {
+                String::from_utf8_lossy(&data)
+                    .lines()
+                    .map(|l| l.replace('\t', "    "))
+                    .collect()
+            })
+            .unwrap_or_default();
+        state.preview = Some((path.to_path_buf(), lines));
+    }
+    let lines = &state.preview.as_ref().unwrap().1;
+
+    // Center the matched line
+    let first = line.saturating_sub(1).saturating_sub(height / 2);
+    for (row, (number, text)) in lines
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(height)
+        .enumerate()
+    {
+        let text = format!("{:>5} {}", number + 1, text);
+        queue!(out, cursor::MoveTo(0, (top + row) as u16))?;
+        if number + 1 == line {
+            queue!(
+                out,
+                SetAttribute(Attribute::Bold),
+                SetForegroundColor(Color::Yellow),
+                Print(fit(&text, width)),
+                ResetColor,
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(out, Print(fit(&text, width)))?;
+        }
+    }
+
+    Ok(())
+}
+
+// Cuts text to the terminal width
+fn fit(text: &str, width: usize) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(width)
+        .collect()
+}
+
+// Opens `$VISUAL` or `$EDITOR` at the line, the UI is restored afterwards
+fn open_editor(out: &mut io::Stdout, path: &Path, line: usize) -> io::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+
+    execute!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+
+    let mut command = Command::new(program);
+    command.args(parts);
+    if line > 0 {
+        command.arg(format!("+{}", line));
+    }
+    let status = command.arg(path).status();
+
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    status.map(|_| ())
+}
@@ -0,0 +1,879 @@
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::ignore::IgnoreStack;
+use crate::kind::FileKind;
+use crate::log;
+use crate::log::Level;
+use crate::perm;
+use crate::platform;
+use crate::platform::is_hidden;
+use crate::profile::Phase;
+use crate::{Config, Context};
+
+// Identifies a directory independent of the path it was reached through
+#[cfg(unix)]
+type DirId = (u64, u64);
+
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(unix)]
+fn dir_id(path: &Path) -> io::Result<DirId> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path) -> io::Result<DirId> {
+    fs::canonicalize(path)
+}
+
+// Filesystem the directory is on, see `Search::one_file_system`
+#[cfg(unix)]
+fn device_of(id: &DirId) -> u64 {
+    id.0
+}
+
+// Rejected by `Search::build`
+#[cfg(not(unix))]
+fn device_of(_id: &DirId) -> u64 {
+    0
+}
+
+// Extra ignore files still apply without the ones of directories
+fn root_ignores(config: &Config, root: &Path) -> Option<Arc<IgnoreStack>> {
+    if config.no_ignore && config.ignore_files.is_empty() {
+        return None;
+    }
+
+    Some(IgnoreStack::root(
+        root,
+        &config.ignore_files,
+        !config.no_ignore,
+    ))
+}
+
+// Directories the walk doesn't descend into besides excluded and
+// ignored ones, the root itself is always read
+struct Boundary {
+    // Filesystem of every root if the walk stays on it
+    devices: Vec<Option<u64>>,
+    // Roots below other roots are walked as roots of their own
+    roots: HashMap<DirId, usize>,
+    // Names of the files given as roots by their directory, searched once
+    // as roots instead of by the walk
+    files: HashMap<DirId, Vec<OsString>>,
+    // Pseudo filesystems like `/proc` unless special files are allowed
+    special: Vec<DirId>,
+}
+
+// Reading their files can block forever or never end
+#[cfg(unix)]
+const SPECIAL_DIRS: [&str; 3] = ["/proc", "/sys", "/dev"];
+
+#[cfg(not(unix))]
+const SPECIAL_DIRS: [&str; 0] = [];
+
+impl Boundary {
+    fn new(config: &Config) -> Boundary {
+        let devices = if config.one_file_system {
+            config
+                .roots
+                .iter()
+                .map(|root| dir_id(root).ok().map(|id| device_of(&id)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let special = if config.allow_special {
+            Vec::new()
+        } else {
+            SPECIAL_DIRS
+                .iter()
+                .filter_map(|dir| dir_id(Path::new(dir)).ok())
+                .collect()
+        };
+
+        let mut roots = HashMap::new();
+        let mut files: HashMap<_, Vec<_>> = HashMap::new();
+        if config.roots.len() > 1 {
+            for (i, root) in config.roots.iter().enumerate() {
+                if !config.file_roots[i] {
+                    roots.extend(dir_id(root).ok().map(|id| (id, i)));
+                    continue;
+                }
+
+                let dir = match root.parent() {
+                    Some(p) if !p.as_os_str().is_empty() => p,
+                    _ => Path::new("."),
+                };
+                if let (Ok(id), Some(name)) = (dir_id(dir), root.file_name()) {
+                    files.entry(id).or_default().push(name.to_os_string());
+                }
+            }
+        }
+
+        Boundary {
+            devices,
+            special,
+            roots,
+            files,
+        }
+    }
+
+    fn allows(&self, id: &DirId, root: usize) -> bool {
+        let device = self.devices.get(root).copied().flatten();
+        device.is_none_or(|device| device_of(id) == device) && !self.special.contains(id)
+    }
+
+    // Whether the directory is another root, walked on its own
+    fn is_other_root(&self, id: &DirId, root: usize) -> bool {
+        self.roots.get(id).is_some_and(|&other| other != root)
+    }
+
+    // Names of the files in the directory `dir` given as roots
+    fn root_files(&self, dir: &DirId) -> &[OsString] {
+        self.files.get(dir).map_or(&[], |names| names.as_slice())
+    }
+}
+
+struct PendingDir {
+    path: PathBuf,
+    ignores: Option<Arc<IgnoreStack>>,
+    // Index of the root in `Config::roots` the directory is below
+    root: usize,
+    // Number of directories between the root and `path`
+    depth: usize,
+}
+
+// Order in which directories are read, see `Search::strategy`
+#[derive(Clone, Copy, PartialEq)]
+pub enum Strategy {
+    // Reads the newest directory first, only the siblings of the
+    // directories on the current path are pending
+    DepthFirst,
+    // Reads the oldest directory first, shallow files are found first
+    // but a whole level of directories can be pending
+    BreadthFirst,
+}
+
+// Pending directories in the order of the strategy
+enum Frontier {
+    Stack(Vec<PendingDir>),
+    Queue(VecDeque<PendingDir>),
+}
+
+impl Frontier {
+    // Roots are read in the order they were given
+    fn new(strategy: Strategy, roots: Vec<PendingDir>) -> Frontier {
+        let mut frontier = match strategy {
+            Strategy::DepthFirst => Frontier::Stack(Vec::new()),
+            Strategy::BreadthFirst => Frontier::Queue(VecDeque::new()),
+        };
+        frontier.extend(roots);
+        frontier
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Frontier::Stack(stack) => stack.len(),
+            Frontier::Queue(queue) => queue.len(),
+        }
+    }
+
+    fn pop(&mut self) -> Option<PendingDir> {
+        match self {
+            Frontier::Stack(stack) => stack.pop(),
+            Frontier::Queue(queue) => queue.pop_front(),
+        }
+    }
+
+    // Both keep the order of the directory listing
+    fn extend(&mut self, dirs: Vec<PendingDir>) {
+        match self {
+            Frontier::Stack(stack) => stack.extend(dirs.into_iter().rev()),
+            Frontier::Queue(queue) => queue.extend(dirs),
+        }
+    }
+}
+
+// Directories waiting to be read, shared by all walker threads
+struct Queue {
+    state: Mutex<QueueState>,
+    // Signalled whenever directories are added or a thread becomes idle
+    changed: Condvar,
+}
+
+struct QueueState {
+    pending: Frontier,
+    // Threads currently reading a directory, they can still add new ones
+    busy: usize,
+    // `found` doesn't accept any more files
+    stopped: bool,
+}
+
+// Walks the trees below the roots and passes every file matching the
+// filters to `found`, stops early if `found` returns `false`
+// Directories are read once even if reachable from several roots, a root
+// below another one is only walked as itself, so every file is reported
+// below the innermost root it's in
+// Directories are read by `threads` threads in parallel, each takes the
+// next pending directory and adds its subdirectories back to the queue,
+// so with more than one thread the strategy only roughly holds
+// Unreadable entries are skipped and reported to the context
+pub fn find_files_by_name<F: Fn(PathBuf) -> bool + Sync>(
+    config: &Config,
+    context: &Context,
+    threads: usize,
+    found: F,
+) {
+    let visited = Mutex::new(HashMap::new());
+    let boundary = Boundary::new(config);
+    let roots = config
+        .roots
+        .iter()
+        .enumerate()
+        .map(|(i, root)| PendingDir {
+            path: root.clone(),
+            ignores: root_ignores(config, root).filter(|_| !config.file_roots[i]),
+            root: i,
+            depth: 0,
+        })
+        .collect();
+    let queue = Queue {
+        state: Mutex::new(QueueState {
+            pending: Frontier::new(config.strategy, roots),
+            busy: 0,
+            stopped: false,
+        }),
+        changed: Condvar::new(),
+    };
+
+    thread::scope(|scope| {
+        for i in 0..threads.max(1) {
+            let (queue, visited, boundary, found) = (&queue, &visited, &boundary, &found);
+            log::spawn_scoped(scope, format!("walk-{}", i), move || {
+                let busy = walk(config, context, queue, visited, boundary, found);
+                context.profile.add_thread("walk", busy);
+                config.log.write(Level::Trace, || String::from("finished"));
+            });
+        }
+    });
+}
+
+// Returns the time spent reading directories, only measured with `--profile`
+fn walk<F: Fn(PathBuf) -> bool>(
+    config: &Config,
+    context: &Context,
+    queue: &Queue,
+    visited: &Mutex<HashMap<DirId, usize>>,
+    boundary: &Boundary,
+    found: &F,
+) -> Duration {
+    let mut busy = Duration::from_secs(0);
+    loop {
+        let dir = {
+            let mut state = queue.state.lock().unwrap();
+            loop {
+                if state.stopped || context.cancel.is_cancelled() {
+                    return busy;
+                }
+
+                if let Some(dir) = state.pending.pop() {
+                    state.busy += 1;
+                    config.log.write(Level::Trace, || {
+                        let pending = state.pending.len();
+                        format!("reading {}, {} pending", dir.path.display(), pending)
+                    });
+                    break dir;
+                }
+
+                // Nobody is left to add directories
+                if state.busy == 0 {
+                    return busy;
+                }
+
+                config.log.write(Level::Trace, || {
+                    format!("idle, {} threads reading", state.busy)
+                });
+                state = queue.changed.wait(state).unwrap();
+            }
+        };
+
+        let started = context.profile.start();
+        let subdirs = read_dir(config, context, dir, visited, boundary, found);
+        if let Some(started) = started {
+            busy += started.elapsed();
+        }
+
+        let mut state = queue.state.lock().unwrap();
+        state.busy -= 1;
+        match subdirs {
+            Some(dirs) => {
+                config.log.write(Level::Trace, || {
+                    format!("queued {} directories", dirs.len())
+                });
+                state.pending.extend(dirs)
+            }
+            None => state.stopped = true,
+        }
+        queue.changed.notify_all();
+    }
+}
+
+// Passes the files of a directory to `found` and returns its subdirectories
+// Returns `None` if `found` doesn't accept any more files
+fn read_dir<F: Fn(PathBuf) -> bool>(
+    config: &Config,
+    context: &Context,
+    dir: PendingDir,
+    visited: &Mutex<HashMap<DirId, usize>>,
+    boundary: &Boundary,
+    found: &F,
+) -> Option<Vec<PendingDir>> {
+    let PendingDir {
+        path: dir,
+        ignores,
+        root,
+        depth,
+    } = dir;
+    let mut subdirs = Vec::new();
+
+    let profile = &context.profile;
+
+    // A file given as root is searched without the filters of the walk,
+    // only the ones of the file itself apply
+    if depth == 0 && config.file_roots[root] {
+        context.progress.add_traversed();
+        let matched = profile.time(Phase::Filter, || {
+            is_kind(config, FileKind::File) && is_match(config, context, &dir, config.root_of(&dir))
+        });
+        return if matched && !found(dir) {
+            None
+        } else {
+            Some(subdirs)
+        };
+    }
+
+    // Followed symlinks can point back to one of their parents
+    let root_files = match profile.time(Phase::Traverse, || dir_id(&dir)) {
+        Ok(id) => {
+            // Mount points and pseudo filesystems are still reported,
+            // just not descended into
+            if depth > 0 && !boundary.allows(&id, root) {
+                context.progress.add_ignored();
+                config.log.write(Level::Debug, || {
+                    format!("{}: mount point or special, not read", dir.display())
+                });
+                return Some(subdirs);
+            }
+            if depth > 0 && boundary.is_other_root(&id, root) {
+                config.log.write(Level::Debug, || {
+                    format!("{}: walked as a root of its own", dir.display())
+                });
+                return Some(subdirs);
+            }
+
+            // Overlapping roots reach the same directories
+            let mut visited = visited.lock().unwrap();
+            if let Some(&other) = visited.get(&id) {
+                if other == root {
+                    config.log.write(Level::Info, || {
+                        format!("{}: directory cycle detected", dir.display())
+                    });
+                } else {
+                    config.log.write(Level::Debug, || {
+                        format!(
+                            "{}: already searched below {}",
+                            dir.display(),
+                            config.roots[other].display()
+                        )
+                    });
+                }
+                return Some(subdirs);
+            }
+            let root_files = boundary.root_files(&id);
+            visited.insert(id, root);
+            root_files
+        }
+        Err(err) => {
+            context.errors.send(&dir, err, depth == 0);
+            return Some(subdirs);
+        }
+    };
+
+    let ignores = profile.time(Phase::Filter, || {
+        ignores.map(|parent| IgnoreStack::push(&parent, &dir))
+    });
+
+    let entries = match list_dir(config, context, &dir, depth) {
+        Some(e) => e,
+        None => return Some(subdirs),
+    };
+
+    for (path, kind) in entries {
+        if context.cancel.is_cancelled() {
+            return None;
+        }
+
+        // Searched as a root of its own
+        if root_files
+            .iter()
+            .any(|name| Some(name.as_os_str()) == path.file_name())
+        {
+            config.log.write(Level::Debug, || {
+                format!("{}: searched as a root of its own", path.display())
+            });
+            continue;
+        }
+
+        // Hidden directories are pruned without descending
+        if !config.hidden && profile.time(Phase::Filter, || is_hidden(&path)) {
+            context.progress.add_ignored();
+            config
+                .log
+                .write(Level::Debug, || format!("{}: hidden", path.display()));
+            continue;
+        }
+
+        let is_dir = kind == FileKind::Directory;
+
+        // Excluded directories are pruned without descending
+        let exclusions = if is_dir {
+            &config.exclude_dir
+        } else {
+            &config.exclude
+        };
+        let excluded = profile.time(Phase::Filter, || {
+            if exclusions
+                .iter()
+                .any(|m| m.is_match(&path, &config.roots[root]))
+            {
+                Some("excluded")
+            } else if ignores
+                .as_ref()
+                .is_some_and(|i| i.is_ignored(&path, is_dir))
+            {
+                Some("ignored")
+            } else {
+                None
+            }
+        });
+        if let Some(reason) = excluded {
+            context.progress.add_ignored();
+            config
+                .log
+                .write(Level::Debug, || format!("{}: {}", path.display(), reason));
+            continue;
+        }
+
+        if is_dir {
+            if is_kind(config, FileKind::Directory) {
+                context.progress.add_traversed();
+                let matched = profile.time(Phase::Filter, || {
+                    is_match(config, context, &path, &config.roots[root])
+                });
+                if matched && !found(path.clone()) {
+                    return None;
+                }
+            }
+
+            // Entries of this directory would be beyond the maximum depth
+            if let Some(max_depth) = config.max_depth {
+                if depth + 1 >= max_depth {
+                    config.log.write(Level::Debug, || {
+                        format!("{}: beyond the maximum depth", path.display())
+                    });
+                    continue;
+                }
+            }
+
+            subdirs.push(PendingDir {
+                path,
+                ignores: ignores.as_ref().map(Arc::clone),
+                root,
+                depth: depth + 1,
+            });
+            continue;
+        }
+
+        context.progress.add_traversed();
+        let matched = profile.time(Phase::Filter, || {
+            is_kind(config, kind) && is_match(config, context, &path, &config.roots[root])
+        });
+        if !matched {
+            config.log.write(Level::Debug, || {
+                format!("{}: not matched by the filters", path.display())
+            });
+        } else if !found(path) {
+            return None;
+        }
+    }
+
+    Some(subdirs)
+}
+
+// Returns the entries of a directory with their kinds, taken from the cache
+// if the directory is unchanged since the last run
+fn list_dir(
+    config: &Config,
+    context: &Context,
+    dir: &Path,
+    depth: usize,
+) -> Option<Vec<(PathBuf, FileKind)>> {
+    let profile = &context.profile;
+
+    // Followed symlinks can change their kind without the directory changing
+    let cache = context.cache.as_ref().filter(|_| !config.follow);
+    let modified = cache.and_then(|_| fs::metadata(dir).and_then(|m| m.modified()).ok());
+    if let (Some(cache), Some(modified)) = (cache, modified) {
+        if let Some(entries) = cache.listing(dir, modified) {
+            return Some(entries);
+        }
+    }
+
+    let mut entries = match profile.time(Phase::Traverse, || fs::read_dir(dir)) {
+        Ok(d) => d,
+        Err(err) => {
+            // Nothing can be searched if the root is unreadable
+            context.errors.send(dir, err, depth == 0);
+            return None;
+        }
+    };
+
+    let mut listing = Vec::new();
+    let mut complete = true;
+    while let Some(entry) = profile.time(Phase::Traverse, || entries.next()) {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(err) => {
+                context.errors.skip(dir, err);
+                complete = false;
+                continue;
+            }
+        };
+
+        let path = entry.path();
+        let kind = profile.time(Phase::Traverse, || match entry.file_type() {
+            // Followed symlinks take the kind of their target and are
+            // only descended into then, broken ones stay symlinks
+            Ok(t) if t.is_symlink() && config.follow => match fs::metadata(&path) {
+                Ok(m) => Ok(FileKind::of(&m.file_type())),
+                Err(_) => Ok(FileKind::Symlink),
+            },
+            Ok(t) => Ok(FileKind::of(&t)),
+            Err(err) => Err(err),
+        });
+        match kind {
+            Ok(kind) => listing.push((path, kind)),
+            Err(err) => {
+                context.errors.skip(&path, err);
+                complete = false;
+            }
+        }
+    }
+
+    // Entries that couldn't be read are reported again next time
+    if let (Some(cache), Some(modified)) = (cache, modified) {
+        if complete {
+            cache.record_listing(dir, modified, &listing);
+        }
+    }
+
+    Some(listing)
+}
+
+// Reads the files to search from a list instead of walking the tree,
+// one path per line, `-` reads the list from stdin
+// The file filters still apply, ignore files and excludes don't
+pub fn read_file_list<F: FnMut(PathBuf) -> bool>(
+    config: &Config,
+    list: &Path,
+    context: &Context,
+    mut found: F,
+) {
+    let reader: Box<dyn BufRead> = if list == Path::new("-") {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        match fs::File::open(list) {
+            Ok(f) => Box::new(BufReader::new(f)),
+            Err(err) => {
+                context.errors.send(list, err, true);
+                return;
+            }
+        }
+    };
+
+    for line in reader.lines() {
+        if context.cancel.is_cancelled() {
+            return;
+        }
+
+        let line = match line {
+            Ok(l) => l,
+            Err(err) => {
+                context.errors.send(list, err, true);
+                return;
+            }
+        };
+
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            continue;
+        }
+
+        let path = PathBuf::from(line);
+        context.progress.add_traversed();
+        if !config.kinds.is_empty() || !config.allow_special {
+            let metadata = if config.follow {
+                fs::metadata(&path)
+            } else {
+                fs::symlink_metadata(&path)
+            };
+            match metadata {
+                Ok(m) if is_kind(config, FileKind::of(&m.file_type())) => {}
+                Ok(_) => continue,
+                Err(err) => {
+                    context.errors.skip(&path, err);
+                    continue;
+                }
+            }
+        }
+
+        let matched = context.profile.time(Phase::Filter, || {
+            is_match(config, context, &path, config.root_of(&path))
+        });
+        if matched && !found(path) {
+            return;
+        }
+    }
+}
+
+// Passes the given paths to `found` which are included in the walk
+pub fn filter_paths<F: FnMut(PathBuf) -> bool>(
+    config: &Config,
+    paths: Vec<PathBuf>,
+    context: &Context,
+    mut found: F,
+) {
+    for path in paths {
+        if context.cancel.is_cancelled() {
+            return;
+        }
+
+        if !path.is_file() {
+            continue;
+        }
+
+        context.progress.add_traversed();
+        if is_included(config, context, &path) && !found(path) {
+            return;
+        }
+    }
+}
+
+// Checks a single path below the root against the rules of the walk,
+// used for paths reported by filesystem notifications
+fn is_included(config: &Config, context: &Context, path: &Path) -> bool {
+    match skipped_by(config, path) {
+        Ok(reason) => reason.is_none(),
+        Err(err) => {
+            context.errors.skip(path, err);
+            false
+        }
+    }
+}
+
+// Why the walk wouldn't find `path`, `None` if it would, see `--why`
+// Every parent directory is checked like the walk checks it on the way
+// down, a file given as root is only checked by the filters of files
+pub fn skipped_by(config: &Config, path: &Path) -> io::Result<Option<String>> {
+    let root = config.root_of(path);
+    let relative = match path.strip_prefix(root) {
+        Ok(r) => r,
+        Err(_) => return Ok(Some(String::from("not below any root"))),
+    };
+    let is_root = config
+        .roots
+        .iter()
+        .zip(&config.file_roots)
+        .any(|(r, &file)| file && r == path);
+
+    let components: Vec<_> = relative.components().collect();
+    if let Some(max) = config
+        .max_depth
+        .filter(|&max| components.len() > max && !is_root)
+    {
+        return Ok(Some(format!("below the maximum depth of {}", max)));
+    }
+
+    let mut ignores = root_ignores(config, root).filter(|_| !is_root);
+    let mut current = root.to_path_buf();
+    for (i, component) in components.iter().enumerate() {
+        let is_dir = i + 1 < components.len();
+        ignores = ignores.map(|parent| IgnoreStack::push(&parent, &current));
+        current.push(component);
+        if is_root {
+            continue;
+        }
+
+        let which = if is_dir { "directory " } else { "" };
+        if !config.hidden && is_hidden(&current) {
+            return Ok(Some(format!("hidden {}{}", which, current.display())));
+        }
+
+        let exclusions = if is_dir {
+            &config.exclude_dir
+        } else {
+            &config.exclude
+        };
+        if let Some(m) = exclusions.iter().find(|m| m.is_match(&current, root)) {
+            return Ok(Some(format!(
+                "{}{} excluded by {}",
+                which,
+                current.display(),
+                m.describe()
+            )));
+        }
+
+        if let Some(rule) = ignores.as_ref().and_then(|i| i.rule(&current, is_dir)) {
+            if rule.ignored {
+                return Ok(Some(format!(
+                    "{}{} ignored by {}:{}:{}",
+                    which,
+                    current.display(),
+                    rule.file.display(),
+                    rule.line,
+                    rule.text
+                )));
+            }
+        }
+    }
+
+    // The walk doesn't follow symlinks unless asked to
+    let metadata = if config.follow {
+        fs::metadata(path)?
+    } else {
+        fs::symlink_metadata(path)?
+    };
+    let kind = FileKind::of(&metadata.file_type());
+    if !is_kind(config, kind) {
+        return Ok(Some(format!(
+            "{} isn't one of the kinds searched",
+            kind.name()
+        )));
+    }
+
+    Ok(filtered_by(config, path, root)?.map(String::from))
+}
+
+// Without any kinds given everything but directories and special files
+// is included, empty directories are when looking for empty entries
+fn is_kind(config: &Config, kind: FileKind) -> bool {
+    if config.kinds.is_empty() {
+        match kind {
+            FileKind::Directory => config.empty,
+            _ if kind.is_special() => config.allow_special,
+            _ => true,
+        }
+    } else {
+        config.kinds.contains(&kind)
+    }
+}
+
+// Applies the file filters, cheap checks first
+// Names are matched relative to `root`
+fn is_match(config: &Config, context: &Context, path: &Path, root: &Path) -> bool {
+    match filtered_by(config, path, root) {
+        Ok(filter) => filter.is_none(),
+        Err(err) => {
+            context.errors.skip(path, err);
+            false
+        }
+    }
+}
+
+// The file filter `path` doesn't pass, if any
+fn filtered_by(config: &Config, path: &Path, root: &Path) -> io::Result<Option<&'static str>> {
+    if let Some(matcher) = config.filename.as_ref().filter(|_| !config.name_or_content) {
+        if !matcher.is_match(path, root) {
+            return Ok(Some("doesn't match the name pattern"));
+        }
+    }
+
+    if !config.file_types.is_empty() && !config.file_types.iter().any(|m| m.is_match(path, root)) {
+        return Ok(Some("doesn't match the file types"));
+    }
+
+    let needs_metadata = config.min_size.is_some()
+        || config.max_size.is_some()
+        || config.newer_than.is_some()
+        || config.older_than.is_some()
+        || config.perm.is_some()
+        || config.owner.is_some()
+        || config.group.is_some()
+        || config.empty
+        || !config.attributes.is_empty();
+    if !needs_metadata {
+        return Ok(None);
+    }
+
+    let metadata = fs::metadata(path)?;
+
+    if !perm::is_match(&metadata, config.perm, config.owner, config.group) {
+        return Ok(Some("permissions, owner or group don't match"));
+    }
+
+    if !config
+        .attributes
+        .iter()
+        .all(|&(attribute, set)| platform::has_attribute(&metadata, path, attribute) == set)
+    {
+        return Ok(Some("attributes don't match"));
+    }
+
+    if config.empty {
+        let empty = if metadata.is_dir() {
+            fs::read_dir(path)?.next().is_none()
+        } else {
+            metadata.len() == 0
+        };
+        if !empty {
+            return Ok(Some("not empty"));
+        }
+    }
+
+    let size = metadata.len();
+    if config.min_size.is_some_and(|min| size < min) {
+        return Ok(Some("smaller than the minimum size"));
+    }
+
+    if config.max_size.is_some_and(|max| size > max) {
+        return Ok(Some("larger than the maximum size"));
+    }
+
+    if config.newer_than.is_some() || config.older_than.is_some() {
+        let modified = metadata.modified()?;
+
+        if config.newer_than.is_some_and(|time| modified < time) {
+            return Ok(Some("modified before the newer-than time"));
+        }
+
+        if config.older_than.is_some_and(|time| modified > time) {
+            return Ok(Some("modified after the older-than time"));
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,200 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::context::Cancel;
+use crate::printer::ResultPrinter;
+use crate::{Config, FileMatch, SearchError, Summary};
+
+// How often an interruption is checked while waiting for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Editors often write a file in several steps, changes arriving
+// within this time are searched together
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// Identifies a match within its file independent of its line number,
+// so inserting lines above doesn't report it again
+// Identical lines are told apart by their occurrence
+type Key = (String, usize, usize);
+
+// Matches already printed per file
+#[derive(Default)]
+pub struct Seen {
+    files: HashMap<PathBuf, HashSet<Key>>,
+}
+
+impl Seen {
+    // Remembers the matches of `result`, returns the ones known before
+    pub fn record(&mut self, result: &FileMatch) -> HashSet<Key> {
+        let keys = keys(result).into_iter().collect();
+
+        self.files
+            .insert(result.path.clone(), keys)
+            .unwrap_or_default()
+    }
+
+    // Removes everything from `result` that was already reported
+    // Returns `None` if nothing new is left
+    fn filter(&mut self, mut result: FileMatch) -> Option<FileMatch> {
+        let previous = self.record(&result);
+        let mut new = keys(&result).into_iter().map(|k| !previous.contains(&k));
+
+        match &mut result.lines {
+            Some(lines) => {
+                lines.retain(|_| new.next().unwrap_or(false));
+                if lines.is_empty() {
+                    return None;
+                }
+            }
+            None => {
+                if !new.next().unwrap_or(false) {
+                    return None;
+                }
+            }
+        }
+
+        Some(result)
+    }
+
+    // Matches of a file that no longer matches are reported again
+    // once they reappear
+    fn forget(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+}
+
+// Keys of the matches of `result`, in the same order as its lines
+fn keys(result: &FileMatch) -> Vec<Key> {
+    let lines = match &result.lines {
+        Some(l) => l,
+        // Counts are reported again when they change, files only once
+        None => return vec![(String::new(), result.count, 0)],
+    };
+
+    let mut occurrences = HashMap::new();
+    lines
+        .iter()
+        .map(|l| {
+            let n = occurrences.entry((&l.content, l.column)).or_insert(0);
+            *n += 1;
+            (l.content.clone(), l.column, *n)
+        })
+        .collect()
+}
+
+// Searches created and modified files below the roots until cancelled
+pub fn watch(
+    config: &Arc<Config>,
+    printer: &mut dyn ResultPrinter,
+    mut seen: Seen,
+    cancel: &Cancel,
+    summary: &mut Summary,
+) -> Result<(), Box<dyn Error>> {
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    // Notifications can contain the resolved path instead of the root
+    let mut watched = Vec::with_capacity(config.roots.len());
+    for root in &config.roots {
+        watcher.watch(root, RecursiveMode::Recursive)?;
+        watched.push(fs::canonicalize(root)?);
+    }
+
+    while !cancel.is_cancelled() {
+        let event = match events.recv_timeout(POLL_INTERVAL) {
+            Ok(e) => e,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+
+        let mut changed = BTreeSet::new();
+        collect(config, &watched, event, &mut changed, &mut seen);
+        let deadline = Instant::now() + DEBOUNCE;
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            match events.recv_timeout(left) {
+                Ok(event) => collect(config, &watched, event, &mut changed, &mut seen),
+                Err(_) => break,
+            }
+        }
+
+        if changed.is_empty() {
+            continue;
+        }
+
+        let mut unmatched = changed.clone();
+        let mut results = crate::start(config.clone(), Some(changed.into_iter().collect()));
+        for result in results.by_ref() {
+            unmatched.remove(&result.path);
+            if let Some(result) = seen.filter(result) {
+                summary.matched = true;
+                crate::print(config, printer, &result, summary)?;
+            }
+        }
+
+        for error in results.remaining_errors() {
+            crate::report(config, &error);
+            summary.errors += 1;
+        }
+
+        for path in &unmatched {
+            seen.forget(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn collect(
+    config: &Config,
+    watched: &[PathBuf],
+    event: notify::Result<Event>,
+    changed: &mut BTreeSet<PathBuf>,
+    seen: &mut Seen,
+) {
+    let event = match event {
+        Ok(e) => e,
+        // Missed events only delay results, they aren't fatal
+        Err(err) => {
+            let error = SearchError {
+                path: config.roots[0].clone(),
+                error: io::Error::other(err),
+                fatal: false,
+            };
+            crate::report(config, &error);
+            return;
+        }
+    };
+
+    let paths = event.paths.into_iter().map(|path| {
+        let below = watched
+            .iter()
+            .zip(&config.roots)
+            .find_map(|(watched, root)| {
+                // Joining nothing would make a file given as root a directory
+                path.strip_prefix(watched)
+                    .ok()
+                    .map(|relative| match relative {
+                        r if r.as_os_str().is_empty() => root.clone(),
+                        r => root.join(r),
+                    })
+            });
+        below.unwrap_or(path)
+    });
+
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(_) => changed.extend(paths),
+        EventKind::Remove(_) => {
+            for path in paths {
+                seen.forget(&path);
+            }
+        }
+        _ => {}
+    }
+}
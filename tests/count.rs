@@ -0,0 +1,65 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+// Creates a fresh directory containing `a.txt` with `content`
+fn fixture(name: &str, content: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("lps-count-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.txt"), content).unwrap();
+    dir
+}
+
+// Runs lps in `dir` without reading a config file, returns stdout
+fn lps(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_lps"))
+        .arg("--no-config")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "lps {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// `--count-matches` prints `a.txt:N`, `-o` one line per match
+fn agree(name: &str, content: &str, patterns: &[&str]) {
+    let dir = fixture(name, content);
+    let mut args = Vec::new();
+    for pattern in patterns {
+        args.extend_from_slice(&["-c", pattern]);
+    }
+
+    let mut only = args.clone();
+    only.extend_from_slice(&["-o", "--no-heading", "a.txt"]);
+    let printed = lps(&dir, &only).lines().count();
+
+    let mut count = args;
+    count.extend_from_slice(&["--count-matches", "a.txt"]);
+    let counted = lps(&dir, &count);
+    assert_eq!(counted.trim(), format!("a.txt:{}", printed));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn overlapping_literals_count_once() {
+    agree("literals", "aaaa\n", &["aa", "aaa"]);
+}
+
+#[test]
+fn overlapping_patterns_of_several_lines() {
+    agree(
+        "lines",
+        "foobar foo\nbarfoo\nnone\n",
+        &["foo", "oba", "bar"],
+    );
+}
+
+#[test]
+fn single_pattern() {
+    agree("single", "abab ab\nab\n", &["ab"]);
+}
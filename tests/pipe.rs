@@ -0,0 +1,33 @@
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::process::{self, Command, Stdio};
+
+// Reads the first line of the output, then closes the pipe like `| head -1`
+#[test]
+fn closed_stdout_exits_with_the_match_status() {
+    let dir = env::temp_dir().join(format!("lps-pipe-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "foo\n".repeat(200_000)).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lps"))
+        .args(["--no-config", "-c", "foo", "a.txt"])
+        .current_dir(&dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut first = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut first)
+        .unwrap();
+    assert!(first.contains("foo"));
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
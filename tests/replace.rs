@@ -0,0 +1,110 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+// Creates a fresh directory containing `file` with `content`
+fn fixture(name: &str, file: &str, content: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("lps-replace-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(file), content).unwrap();
+    dir
+}
+
+// Runs lps in `dir` without reading a config file, returns stdout
+fn lps(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_lps"))
+        .arg("--no-config")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "lps {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn dry_run_keeps_the_file() {
+    let dir = fixture("dry-run", "a.txt", "foo bar\nkeep\n");
+
+    let printed = lps(
+        &dir,
+        &["-c", "foo", "--replace", "baz", "--dry-run", "a.txt"],
+    );
+    assert!(printed.contains("baz bar"));
+    assert_eq!(
+        fs::read_to_string(dir.join("a.txt")).unwrap(),
+        "foo bar\nkeep\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn backup_has_the_original_content() {
+    let dir = fixture("backup", "a.txt", "foo bar\n");
+
+    lps(
+        &dir,
+        &["-c", "foo", "--replace", "baz", "--backup", "a.txt"],
+    );
+    assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "baz bar\n");
+    assert_eq!(
+        fs::read_to_string(dir.join("a.txt.bak")).unwrap(),
+        "foo bar\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn patterns_are_replaced_in_one_pass() {
+    let dir = fixture("one-pass", "a.txt", "foo bar\n");
+
+    lps(
+        &dir,
+        &["-c", "foo", "-c", "bar", "--replace", "xbarx", "a.txt"],
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("a.txt")).unwrap(),
+        "xbarx xbarx\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn permissions_are_kept() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = fixture("permissions", "run.sh", "echo foo\n");
+    let path = dir.join("run.sh");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+
+    lps(&dir, &["-c", "foo", "--replace", "baz", "run.sh"]);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "echo baz\n");
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o750);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_replace_in_their_target() {
+    let dir = fixture("symlink", "real.txt", "foo bar\n");
+    std::os::unix::fs::symlink("real.txt", dir.join("link.txt")).unwrap();
+
+    lps(&dir, &["-L", "-c", "foo", "--replace", "baz", "link.txt"]);
+    let link = fs::symlink_metadata(dir.join("link.txt")).unwrap();
+    assert!(link.file_type().is_symlink());
+    assert_eq!(
+        fs::read_to_string(dir.join("real.txt")).unwrap(),
+        "baz bar\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,92 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use lps::Config;
+
+// Creates a fresh directory containing `files` files with a match each
+fn fixture(name: &str, files: usize) -> PathBuf {
+    let dir = env::temp_dir().join(format!("lps-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    for i in 0..files {
+        fs::write(dir.join(format!("{}.txt", i)), "a needle in a haystack\n").unwrap();
+    }
+
+    dir
+}
+
+fn matching_files(name: &str, files: usize, dop: usize) -> usize {
+    let dir = fixture(name, files);
+    let config = Config::builder()
+        .root(&dir)
+        .no_ignore(true)
+        .content("needle")
+        .dop(dop)
+        .build()
+        .unwrap();
+
+    let found = lps::search(config).count();
+    fs::remove_dir_all(&dir).unwrap();
+    found
+}
+
+#[test]
+fn no_files() {
+    assert_eq!(matching_files("no-files", 0, 4), 0);
+}
+
+#[test]
+fn fewer_files_than_threads() {
+    assert_eq!(matching_files("fewer-files", 2, 8), 2);
+}
+
+#[test]
+fn uneven_remainder() {
+    assert_eq!(matching_files("uneven", 7, 3), 7);
+}
+
+#[test]
+fn single_thread() {
+    assert_eq!(matching_files("single-thread", 5, 1), 5);
+}
+
+#[test]
+fn lines_of_a_file_stay_together() {
+    let dir = fixture("together", 0);
+    let content = "needle\n".repeat(500);
+    for i in 0..64 {
+        fs::write(dir.join(format!("{}.txt", i)), &content).unwrap();
+    }
+    let config = Config::builder()
+        .root(&dir)
+        .no_ignore(true)
+        .content("needle")
+        .dop(8)
+        .build()
+        .unwrap();
+
+    let mut paths: Vec<_> = lps::search(config)
+        .map(|result| {
+            let lines = result.lines.unwrap();
+            assert_eq!(lines.len(), 500);
+            assert!(lines.windows(2).all(|w| w[0].line + 1 == w[1].line));
+            result.path
+        })
+        .collect();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let found = paths.len();
+    paths.sort();
+    paths.dedup();
+    assert_eq!(found, 64);
+    assert_eq!(paths.len(), 64);
+}
+
+#[test]
+fn zero_threads_are_rejected() {
+    let result = Config::builder().content("needle").dop(0).build();
+    assert!(result.is_err());
+}